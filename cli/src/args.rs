@@ -0,0 +1,186 @@
+/// Which implementation runs the script.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Backend {
+    /// Walks the syntax tree
+    #[default]
+    Tree,
+    /// Compiles to bytecode for the virtual machine
+    Vm,
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Command {
+    /// Run the script, or start the REPL without one
+    #[default]
+    Run,
+    /// Compile the script to a `.loxc` file
+    Compile,
+}
+/// The parsed command line.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Options {
+    pub command: Command,
+    pub backend: Backend,
+    /// The script is already compiled to bytecode
+    pub bytecode: bool,
+    /// Where to write the compiled script, by default next to it
+    pub output: Option<String>,
+    /// Print the bytecode instead of running it
+    pub disassemble: bool,
+    /// Print the VM stack before each instruction
+    pub trace: bool,
+    /// Print the tree of expressions the evaluator evaluates
+    pub trace_eval: bool,
+    /// Let `+` stringify a number or bool added to a string
+    pub stringify_concatenation: bool,
+    /// `None` starts the REPL
+    pub script: Option<String>,
+}
+pub const USAGE: &str = "\
+Usage: cli [run] [--backend=tree|vm] [--bytecode] [--disassemble] [--trace] [--trace-eval]
+           [--stringify-concatenation] [script]
+       cli compile [--output=path] script";
+
+impl Options {
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut options = Self::default();
+        let mut args = args.into_iter().peekable();
+        match args.peek().map(String::as_str) {
+            Some("run") => {
+                args.next();
+            }
+            Some("compile") => {
+                args.next();
+                options.command = Command::Compile;
+            }
+            _ => {}
+        }
+        for arg in args {
+            if let Some(backend) = arg.strip_prefix("--backend=") {
+                options.backend = match backend {
+                    "tree" => Backend::Tree,
+                    "vm" => Backend::Vm,
+                    other => return Err(format!("Unknown backend '{other}'.")),
+                };
+            } else if let Some(output) = arg.strip_prefix("--output=") {
+                options.output = Some(output.into());
+            } else if arg == "--bytecode" {
+                options.bytecode = true;
+            } else if arg == "--disassemble" {
+                options.disassemble = true;
+            } else if arg == "--trace" {
+                options.trace = true;
+            } else if arg == "--trace-eval" {
+                options.trace_eval = true;
+            } else if arg == "--stringify-concatenation" {
+                options.stringify_concatenation = true;
+            } else if arg.starts_with("--") {
+                return Err(format!("Unknown option '{arg}'."));
+            } else if options.script.replace(arg).is_some() {
+                return Err("Expected at most one script.".into());
+            }
+        }
+        if options.command == Command::Compile {
+            if options.script.is_none() {
+                return Err("Expected a script to compile.".into());
+            }
+            if options.bytecode
+                || options.trace
+                || options.trace_eval
+                || options.stringify_concatenation
+            {
+                return Err("Only --output and --disassemble apply when compiling.".into());
+            }
+            return Ok(options);
+        }
+        if options.output.is_some() {
+            return Err("--output only applies when compiling.".into());
+        }
+        // These only make sense for bytecode
+        if options.bytecode || options.disassemble || options.trace {
+            options.backend = Backend::Vm;
+        }
+        if options.backend == Backend::Vm && options.trace_eval {
+            return Err("--trace-eval only applies to the tree backend.".into());
+        }
+        if options.backend == Backend::Vm && options.stringify_concatenation {
+            return Err("--stringify-concatenation only applies to the tree backend.".into());
+        }
+        if options.backend == Backend::Vm && options.script.is_none() {
+            return Err("The vm backend needs a script to run.".into());
+        }
+        Ok(options)
+    }
+}
+
+#[cfg(test)]
+mod args_tests {
+    use super::*;
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        Options::parse(args.iter().map(|arg| arg.to_string()))
+    }
+    #[test]
+    fn defaults_to_the_tree_repl() {
+        assert_eq!(parse(&[]), Ok(Options::default()));
+    }
+    #[test]
+    fn selects_a_backend() {
+        let options = parse(&["--backend=vm", "a.lox"]).unwrap();
+        assert_eq!(options.backend, Backend::Vm);
+        assert_eq!(options.script.as_deref(), Some("a.lox"));
+        assert_eq!(
+            parse(&["a.lox", "--backend=tree"]).unwrap().backend,
+            Backend::Tree
+        );
+    }
+    #[test]
+    fn debugging_flags_imply_the_vm() {
+        let options = parse(&["--trace", "a.lox"]).unwrap();
+        assert!(options.trace);
+        assert_eq!(options.backend, Backend::Vm);
+        let options = parse(&["--disassemble", "a.lox"]).unwrap();
+        assert!(options.disassemble);
+        assert_eq!(options.backend, Backend::Vm);
+        assert!(parse(&["--disassemble"]).is_err());
+    }
+    #[test]
+    fn tracing_evaluation_needs_the_tree() {
+        let options = parse(&["--trace-eval", "a.lox"]).unwrap();
+        assert!(options.trace_eval);
+        assert_eq!(options.backend, Backend::Tree);
+        assert!(parse(&["--trace-eval"]).unwrap().trace_eval);
+        assert!(parse(&["--trace-eval", "--trace", "a.lox"]).is_err());
+        assert!(parse(&["--trace-eval", "--backend=vm", "a.lox"]).is_err());
+        assert!(parse(&["compile", "--trace-eval", "a.lox"]).is_err());
+    }
+    #[test]
+    fn stringifying_needs_the_tree() {
+        assert!(
+            parse(&["--stringify-concatenation"])
+                .unwrap()
+                .stringify_concatenation
+        );
+        let options = parse(&["--stringify-concatenation", "a.lox"]).unwrap();
+        assert!(options.stringify_concatenation);
+        assert!(parse(&["--stringify-concatenation", "--backend=vm", "a.lox"]).is_err());
+        assert!(parse(&["compile", "--stringify-concatenation", "a.lox"]).is_err());
+    }
+    #[test]
+    fn parses_commands() {
+        let options = parse(&["compile", "a.lox", "--output=b.loxc"]).unwrap();
+        assert_eq!(options.command, Command::Compile);
+        assert_eq!(options.output.as_deref(), Some("b.loxc"));
+        let options = parse(&["run", "--bytecode", "a.loxc"]).unwrap();
+        assert_eq!(options.command, Command::Run);
+        assert!(options.bytecode);
+        assert_eq!(options.backend, Backend::Vm);
+        assert!(parse(&["compile"]).is_err());
+        assert!(parse(&["--output=b.loxc", "a.lox"]).is_err());
+    }
+    #[test]
+    fn rejects_bad_arguments() {
+        assert!(parse(&["--backend=jit", "a.lox"]).is_err());
+        assert!(parse(&["--fast"]).is_err());
+        assert!(parse(&["a.lox", "b.lox"]).is_err());
+        assert!(parse(&["--backend=vm"]).is_err());
+    }
+}
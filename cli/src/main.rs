@@ -1,12 +1,208 @@
-fn run(bytes: impl Iterator<Item = u8>) -> std::io::Result<()> {
-    todo!()
+use args::{Backend, Command, Options};
+use diagnostics::{Diagnostic, Renderer, ToDiagnostic};
+use interpreter::{Interpreter, Limits, LoxError};
+use std::{io::IsTerminal, process::ExitCode};
+mod args;
+mod repl;
+mod trace;
+
+/// Exit codes, following the conventions of `sysexits.h`.
+mod exit {
+    pub const USAGE: u8 = 64;
+    pub const DATA_ERROR: u8 = 65;
+    pub const NO_INPUT: u8 = 66;
+    pub const SOFTWARE: u8 = 70;
 }
-fn run_file(path: impl AsRef<std::path::Path>) -> std::io::Result<()> {
+/// The stack everything runs on. Each Lox call recurses through the
+/// tree-walking evaluator, so deep recursion needs far more than the
+/// main thread has.
+const STACK_SIZE: usize = 64 * 1024 * 1024;
+enum Error {
+    Io(std::io::Error),
+    Scan(Vec<scanner::ScanError>),
+    Lox(LoxError),
+    VmCompile(Vec<vm::CompileError>),
+    VmRuntime(vm::RuntimeError),
+    Load(vm::LoadError),
+}
+impl From<std::io::Error> for Error {
+    fn from(value: std::io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+impl Error {
+    fn exit_code(&self) -> ExitCode {
+        ExitCode::from(match self {
+            Self::Io(_) | Self::Lox(LoxError::Io(_)) => exit::NO_INPUT,
+            Self::Scan(_)
+            | Self::Lox(LoxError::Scan(_) | LoxError::Parse(_) | LoxError::Resolve(_))
+            | Self::VmCompile(_)
+            | Self::Load(_) => exit::DATA_ERROR,
+            Self::Lox(LoxError::Runtime(_) | LoxError::LimitExceeded(_)) | Self::VmRuntime(_) => {
+                exit::SOFTWARE
+            }
+        })
+    }
+    fn diagnostics(&self) -> Vec<Diagnostic> {
+        match self {
+            Self::Io(e) => vec![Diagnostic::error(e)],
+            Self::Scan(errors) => errors.iter().map(ToDiagnostic::to_diagnostic).collect(),
+            Self::Lox(error) => error.diagnostics(),
+            Self::VmCompile(errors) => errors.iter().map(ToDiagnostic::to_diagnostic).collect(),
+            Self::VmRuntime(error) => vec![error.to_diagnostic()],
+            Self::Load(error) => vec![error.to_diagnostic()],
+        }
+    }
+    /// Renders the error to stderr.
+    fn report(&self, renderer: &Renderer) {
+        self.diagnostics()
+            .iter()
+            .for_each(|diagnostic| eprint!("{}", renderer.render(diagnostic)))
+    }
+}
+/// The tree-walking interpreter `options` ask for, letting calls use
+/// the stack set aside for them.
+fn interpreter(options: &Options) -> Interpreter {
+    let limits = Limits {
+        native_stack: STACK_SIZE / 2,
+        ..Limits::default()
+    };
+    Interpreter::new()
+        .with_limits(limits)
+        .with_stringify_concatenation(options.stringify_concatenation)
+}
+/// Whether diagnostics should be colored.
+fn color() -> bool {
+    std::io::stdout().is_terminal()
+}
+fn scan(source: &str) -> Result<Vec<token::Token>, Error> {
+    match scanner::Scanner::new(source).scan_tokens() {
+        (tokens, errors) if errors.is_empty() => Ok(tokens),
+        (_, errors) => Err(Error::Scan(errors)),
+    }
+}
+/// Runs `source` on the bytecode virtual machine.
+fn run_vm(source: &str, options: &Options) -> Result<(), Error> {
+    let tokens = scan(source)?;
+    let mut vm = vm::Vm::new().trace(options.trace);
+    vm::native::define_builtins(&mut vm);
+    let function = vm.compile(tokens).map_err(Error::VmCompile)?;
+    execute_vm(vm, function, options)
+}
+/// Runs the compiled script `function`, or only prints its bytecode
+/// when disassembling.
+fn execute_vm(mut vm: vm::Vm, function: vm::ObjRef, options: &Options) -> Result<(), Error> {
+    if options.disassemble {
+        print!("{}", vm::debug::disassemble(vm.heap(), function));
+        return Ok(());
+    }
+    vm.run(function).map_err(Error::VmRuntime)
+}
+fn read_bytes(path: &std::path::Path) -> Result<Vec<u8>, Error> {
     let mut buf = vec![];
     let mut file = std::fs::File::open(path)?;
     let _bytes = std::io::Read::read_to_end(&mut file, &mut buf)?;
-    run(buf.into_iter())
+    Ok(buf)
+}
+fn read(path: &std::path::Path) -> Result<String, Error> {
+    String::from_utf8(read_bytes(path)?)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e).into())
+}
+/// Runs the script at `path`, reporting any error to stderr.
+fn run_file(path: impl AsRef<std::path::Path>, options: &Options) -> Result<(), Error> {
+    let path = path.as_ref();
+    let name = path.display().to_string();
+    let source = read(path).inspect_err(|e| e.report(&Renderer::new(&name, "").color(color())))?;
+    match options.backend {
+        Backend::Tree => interpreter(options)
+            .eval(&source)
+            .map(drop)
+            .map_err(Error::Lox),
+        Backend::Vm => run_vm(&source, options),
+    }
+    .inspect_err(|e| e.report(&Renderer::new(&name, &source).color(color())))
+}
+/// Runs the `.loxc` file at `path`, reporting any error to stderr.
+fn run_bytecode(path: impl AsRef<std::path::Path>, options: &Options) -> Result<(), Error> {
+    let path = path.as_ref();
+    let name = path.display().to_string();
+    let renderer = Renderer::new(&name, "").color(color());
+    let run = || {
+        let bytes = read_bytes(path)?;
+        let mut vm = vm::Vm::new().trace(options.trace);
+        vm::native::define_builtins(&mut vm);
+        let function = vm.load(&bytes).map_err(Error::Load)?;
+        execute_vm(vm, function, options)
+    };
+    run().inspect_err(|e| e.report(&renderer))
+}
+/// Compiles the script at `path` to `output`, or next to the script with
+/// a `.loxc` extension, reporting any error to stderr.
+fn compile_file(path: impl AsRef<std::path::Path>, options: &Options) -> Result<(), Error> {
+    let path = path.as_ref();
+    let name = path.display().to_string();
+    let source = read(path).inspect_err(|e| e.report(&Renderer::new(&name, "").color(color())))?;
+    let compile = || -> Result<Vec<u8>, Error> {
+        let tokens = scan(&source)?;
+        let mut vm = vm::Vm::new();
+        let function = vm.compile(tokens).map_err(Error::VmCompile)?;
+        if options.disassemble {
+            print!("{}", vm::debug::disassemble(vm.heap(), function));
+        }
+        Ok(vm::bytecode::save(vm.heap(), function))
+    };
+    let bytes =
+        compile().inspect_err(|e| e.report(&Renderer::new(&name, &source).color(color())))?;
+    let output = match &options.output {
+        Some(output) => std::path::PathBuf::from(output),
+        None => path.with_extension("loxc"),
+    };
+    std::fs::write(&output, bytes)
+        .map_err(Error::Io)
+        .inspect_err(|e| e.report(&Renderer::new(&name, "").color(color())))
+}
+fn main() -> ExitCode {
+    let cli = std::thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(run_cli);
+    match cli.map(std::thread::JoinHandle::join) {
+        Ok(Ok(code)) => code,
+        Ok(Err(panic)) => std::panic::resume_unwind(panic),
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::from(exit::SOFTWARE)
+        }
+    }
 }
-fn main() {
-    println!("Hello, world!");
+fn run_cli() -> ExitCode {
+    let options = match Options::parse(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{message}\n{}", args::USAGE);
+            return ExitCode::from(exit::USAGE);
+        }
+    };
+    if options.trace_eval {
+        if let Err(e) = trace::install() {
+            eprintln!("{e}");
+        }
+    }
+    let result = match (options.command, &options.script) {
+        (Command::Compile, Some(path)) => compile_file(path, &options),
+        (Command::Run, Some(path)) if options.bytecode => run_bytecode(path, &options),
+        (_, Some(path)) => run_file(path, &options),
+        (_, None) => {
+            return match repl::Repl::new(interpreter(&options)).run() {
+                Ok(()) => ExitCode::SUCCESS,
+                Err(e) => {
+                    eprintln!("{e}");
+                    ExitCode::from(exit::NO_INPUT)
+                }
+            }
+        }
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => e.exit_code(),
+    }
 }
@@ -0,0 +1,142 @@
+use crate::Error;
+use diagnostics::Renderer;
+use interpreter::{Interpreter, LoxError, Value};
+use std::io::{self, BufRead, Write};
+use token::{Direction, Token, TokenType};
+
+/// The interactive session, keeping the pending input and the
+/// interpreter's globals between lines.
+pub struct Repl {
+    buffer: String,
+    lox: Interpreter,
+}
+impl Default for Repl {
+    fn default() -> Self {
+        Self::new(crate::interpreter(&crate::args::Options::default()))
+    }
+}
+/// Counts the parentheses and braces left open in `tokens`.
+fn open_delimiters(tokens: &[Token]) -> isize {
+    tokens
+        .iter()
+        .map(|token| match token.r#type {
+            TokenType::Paren(Direction::Left) | TokenType::Brace(Direction::Left) => 1,
+            TokenType::Paren(Direction::Right) | TokenType::Brace(Direction::Right) => -1,
+            _ => 0,
+        })
+        .sum()
+}
+impl Repl {
+    pub fn new(lox: Interpreter) -> Self {
+        Self {
+            buffer: String::new(),
+            lox,
+        }
+    }
+    fn prompt(&self) -> &'static str {
+        if self.buffer.is_empty() {
+            "> "
+        } else {
+            "... "
+        }
+    }
+    /// Feeds one line of input, returning the complete input once every
+    /// delimiter and string has been closed.
+    fn feed(&mut self, line: &str) -> Option<String> {
+        self.buffer.push_str(line);
+        self.buffer.push('\n');
+        let (tokens, errors) = scanner::Scanner::new(&self.buffer).scan_tokens();
+        let unterminated = errors
+            .iter()
+            .any(|error| error.kind == scanner::ScanErrorKind::UnterminatedString);
+        if open_delimiters(&tokens) > 0 || unterminated {
+            return None;
+        }
+        let source = std::mem::take(&mut self.buffer);
+        // Only the EOF-token, nothing to evaluate
+        (tokens.len() > 1).then_some(source)
+    }
+    /// Runs `source`, returning the value of a trailing expression.
+    fn evaluate(&mut self, source: &str) -> Result<Option<Value>, LoxError> {
+        self.lox.eval(source)
+    }
+    pub fn run(&mut self) -> io::Result<()> {
+        let mut lines = io::stdin().lock().lines();
+        loop {
+            print!("{}", self.prompt());
+            io::stdout().flush()?;
+            let Some(line) = lines.next().transpose()? else {
+                println!();
+                return Ok(());
+            };
+            let Some(source) = self.feed(&line) else {
+                continue;
+            };
+            match self.evaluate(&source) {
+                Ok(Some(value)) => println!("{value}"),
+                Ok(None) => {}
+                Err(e) => {
+                    Error::Lox(e).report(&Renderer::new("<repl>", &source).color(crate::color()))
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod repl_tests {
+    use super::*;
+    #[test]
+    fn waits_for_closing_delimiter() {
+        let mut repl = Repl::default();
+        assert!(repl.feed("{ print (1").is_none());
+        assert_eq!(repl.prompt(), "... ");
+        assert!(repl.feed(");").is_none());
+        assert!(repl.feed("}").is_some());
+        assert_eq!(repl.prompt(), "> ");
+    }
+    #[test]
+    fn waits_for_closing_quote() {
+        let mut repl = Repl::default();
+        assert!(repl.feed("print \"multi").is_none());
+        assert!(repl.feed("line\";").is_some());
+    }
+    #[test]
+    fn skips_blank_lines() {
+        let mut repl = Repl::default();
+        assert!(repl.feed("").is_none());
+        assert_eq!(repl.prompt(), "> ");
+    }
+    #[test]
+    fn has_builtins() {
+        let mut repl = Repl::default();
+        assert!(matches!(repl.evaluate("clock() >= 0"), Ok(Some(_))));
+    }
+    #[test]
+    fn prints_bare_expressions() {
+        let mut repl = Repl::default();
+        assert!(matches!(repl.evaluate("1 + 2"), Ok(Some(_))));
+        assert!(matches!(repl.evaluate("1 + 2;"), Ok(Some(_))));
+        assert!(matches!(repl.evaluate("var a = 1;"), Ok(None)));
+    }
+    #[test]
+    fn survives_errors() {
+        let mut repl = Repl::default();
+        assert!(matches!(repl.evaluate("var = 1;"), Err(LoxError::Parse(_))));
+        assert!(matches!(repl.evaluate("1 @ 2"), Err(LoxError::Scan(_))));
+        assert!(matches!(repl.evaluate("-\"a\""), Err(LoxError::Runtime(_))));
+        assert!(matches!(repl.evaluate("true"), Ok(Some(_))));
+        assert!(matches!(
+            repl.evaluate("return 1;"),
+            Err(LoxError::Resolve(_))
+        ));
+        assert!(matches!(repl.evaluate("this"), Err(LoxError::Resolve(_))));
+    }
+    #[test]
+    fn keeps_variables_between_lines() {
+        let mut repl = Repl::default();
+        assert!(matches!(repl.evaluate("var a = 1;"), Ok(None)));
+        assert!(matches!(repl.evaluate("a = a + 1"), Ok(Some(_))));
+        assert_eq!(repl.evaluate("a").ok().flatten(), Some(2.0.into()));
+    }
+}
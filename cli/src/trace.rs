@@ -0,0 +1,72 @@
+use expression::trace;
+use log::{LevelFilter, Log, Metadata, Record, SetLoggerError};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Prints the evaluator's trace to stderr as a tree, with the expressions
+/// an expression evaluates nested under it, followed by its result.
+#[derive(Debug, Default)]
+struct EvalTree {
+    depth: AtomicUsize,
+}
+impl EvalTree {
+    /// The line to print for a record from `target`, indented for where
+    /// it falls in the tree.
+    fn line(&self, target: &str, message: &str) -> String {
+        let depth = match target {
+            trace::ENTER => self.depth.fetch_add(1, Ordering::Relaxed),
+            // The result lines up with the expression it belongs to
+            trace::EXIT => self.depth.fetch_sub(1, Ordering::Relaxed) - 1,
+            _ => self.depth.load(Ordering::Relaxed),
+        };
+        let marker = if target == trace::EXIT { "=> " } else { "" };
+        format!("{}{marker}{message}", "  ".repeat(depth))
+    }
+}
+impl Log for EvalTree {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.target().starts_with(trace::EVAL)
+    }
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            let line = self.line(record.target(), &record.args().to_string());
+            eprintln!("{line}");
+        }
+    }
+    fn flush(&self) {}
+}
+static LOGGER: EvalTree = EvalTree {
+    depth: AtomicUsize::new(0),
+};
+/// Installs the logger printing the evaluation tree.
+pub fn install() -> Result<(), SetLoggerError> {
+    log::set_logger(&LOGGER)?;
+    log::set_max_level(LevelFilter::Trace);
+    Ok(())
+}
+
+#[cfg(test)]
+mod trace_tests {
+    use super::*;
+    #[test]
+    fn nests_expressions_under_their_parent() {
+        let tree = EvalTree::default();
+        let lines = [
+            (trace::ENTER, "Binary"),
+            (trace::ENTER, "Literal Number(1.0)"),
+            (trace::EXIT, "Number(1.0)"),
+            (trace::OPERATOR, "Evaluated Plus"),
+            (trace::EXIT, "Number(3.0)"),
+        ]
+        .map(|(target, message)| tree.line(target, message));
+        assert_eq!(
+            lines,
+            [
+                "Binary",
+                "  Literal Number(1.0)",
+                "  => Number(1.0)",
+                "  Evaluated Plus",
+                "=> Number(3.0)",
+            ]
+        );
+    }
+}
@@ -29,8 +29,8 @@ pub trait MaybeInto<T>: Sized {
 ///
 /// - `MaybeFrom<T> for U` implies [`MaybeInto`]`<U> for T`
 /// - [`maybe_from`] is reflexive, which means that `MaybeFrom<T> for T`
-/// is implemented and cannot fail -- the returned [`Option<T>`] variant will
-/// always be [`Option::Some(T)`].
+///   is implemented and cannot fail -- the returned [`Option<T>`] variant will
+///   always be [`Option::Some(T)`].
 ///
 /// `MaybeFrom<T>` can be implemented as follows:
 ///
@@ -0,0 +1,272 @@
+use core::fmt;
+use token::{Location, Span};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Error => "error",
+            Self::Warning => "warning",
+        })
+    }
+}
+/// A message about the source, pointing at the offending part of it
+/// when that is known.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub span: Option<Span>,
+    /// Further context, shown after the source
+    pub notes: Vec<String>,
+}
+impl Diagnostic {
+    pub fn error(message: impl ToString) -> Self {
+        Self {
+            severity: Severity::Error,
+            message: message.to_string(),
+            span: None,
+            notes: vec![],
+        }
+    }
+    pub fn warning(message: impl ToString) -> Self {
+        Self {
+            severity: Severity::Warning,
+            message: message.to_string(),
+            span: None,
+            notes: vec![],
+        }
+    }
+    pub fn at(self, span: Span) -> Self {
+        Self {
+            span: Some(span),
+            ..self
+        }
+    }
+    /// Points at the whole of `line`, for when only the line is known.
+    pub fn at_line(self, line: usize) -> Self {
+        let start = |line| Location {
+            offset: 0,
+            line,
+            column: 1,
+        };
+        self.at(Span::new(start(line), start(line + 1)))
+    }
+    pub fn with_note(mut self, note: impl ToString) -> Self {
+        self.notes.push(note.to_string());
+        self
+    }
+}
+/// Errors that can be reported as a [`Diagnostic`].
+pub trait ToDiagnostic {
+    fn to_diagnostic(&self) -> Diagnostic;
+}
+impl ToDiagnostic for Diagnostic {
+    fn to_diagnostic(&self) -> Diagnostic {
+        self.clone()
+    }
+}
+impl<T> ToDiagnostic for &T
+where
+    T: ToDiagnostic,
+{
+    fn to_diagnostic(&self) -> Diagnostic {
+        T::to_diagnostic(self)
+    }
+}
+mod style {
+    pub const RESET: &str = "\x1b[0m";
+    pub const BOLD: &str = "\x1b[1m";
+    pub const RED: &str = "\x1b[1;31m";
+    pub const YELLOW: &str = "\x1b[1;33m";
+    pub const BLUE: &str = "\x1b[1;34m";
+}
+/// Renders diagnostics against the source they refer to, as
+///
+/// ```text
+/// error: Expect ')' after expression.
+///  --> script.lox:1:9
+///   |
+/// 1 | print (1;
+///   |         ^
+///   = note: in f()
+/// ```
+pub struct Renderer<'a> {
+    name: &'a str,
+    source: &'a str,
+    color: bool,
+}
+impl<'a> Renderer<'a> {
+    pub fn new(name: &'a str, source: &'a str) -> Self {
+        Self {
+            name,
+            source,
+            color: false,
+        }
+    }
+    /// Whether to style the output with ANSI escape codes.
+    pub fn color(self, color: bool) -> Self {
+        Self { color, ..self }
+    }
+    fn paint(&self, style: &str, text: impl fmt::Display) -> String {
+        if self.color {
+            format!("{style}{text}{}", style::RESET)
+        } else {
+            text.to_string()
+        }
+    }
+    pub fn render(&self, diagnostic: &impl ToDiagnostic) -> String {
+        let diagnostic = diagnostic.to_diagnostic();
+        let severity_style = match diagnostic.severity {
+            Severity::Error => style::RED,
+            Severity::Warning => style::YELLOW,
+        };
+        let mut out = format!(
+            "{}{}\n",
+            self.paint(severity_style, diagnostic.severity),
+            self.paint(style::BOLD, format!(": {}", diagnostic.message)),
+        );
+        let Some(span) = diagnostic.span else {
+            out.push_str(&format!(
+                "{} {}\n",
+                self.paint(style::BLUE, " -->"),
+                self.name
+            ));
+            self.notes(&diagnostic, " ", &mut out);
+            return out;
+        };
+        let line_number = span.start.line.to_string();
+        let gutter = " ".repeat(line_number.len());
+        let line = span
+            .start
+            .line
+            .checked_sub(1)
+            .and_then(|index| self.source.lines().nth(index));
+        // A span from the start of the line skips its indentation
+        let indent = line.map_or(0, |line| {
+            line.chars().take_while(|c| c.is_whitespace()).count()
+        });
+        let column = if span.start.column <= 1 {
+            indent + 1
+        } else {
+            span.start.column
+        };
+        out.push_str(&format!(
+            "{gutter}{} {}:{}:{column}\n",
+            self.paint(style::BLUE, "-->"),
+            self.name,
+            span.start.line
+        ));
+        let Some(line) = line else {
+            self.notes(&diagnostic, &gutter, &mut out);
+            return out;
+        };
+        let bar = self.paint(style::BLUE, "|");
+        let width = line.chars().count();
+        // Carets up to the end of the span, or of the line if it continues
+        let end = if span.end.line == span.start.line {
+            span.end.column
+        } else {
+            width + 1
+        };
+        let carets = end.saturating_sub(column).max(1);
+        out.push_str(&format!("{gutter} {bar}\n"));
+        out.push_str(&format!(
+            "{} {bar} {line}\n",
+            self.paint(style::BLUE, &line_number)
+        ));
+        out.push_str(&format!(
+            "{gutter} {bar} {}{}\n",
+            " ".repeat(column - 1),
+            self.paint(severity_style, "^".repeat(carets))
+        ));
+        self.notes(&diagnostic, &gutter, &mut out);
+        out
+    }
+    fn notes(&self, diagnostic: &Diagnostic, gutter: &str, out: &mut String) {
+        for note in &diagnostic.notes {
+            out.push_str(&format!(
+                "{gutter} {} {note}\n",
+                self.paint(style::BLUE, "= note:")
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod renderer_tests {
+    use super::*;
+    use token::Location;
+    fn span(line: usize, start: usize, end: usize) -> Span {
+        Span::new(
+            Location {
+                offset: 0,
+                line,
+                column: start,
+            },
+            Location {
+                offset: 0,
+                line,
+                column: end,
+            },
+        )
+    }
+    #[test]
+    fn underlines_the_span() {
+        let source = "var a = 1;\nprint a +* 2;\n";
+        let diagnostic = Diagnostic::error("Expect expression.").at(span(2, 10, 11));
+        assert_eq!(
+            Renderer::new("script.lox", source).render(&diagnostic),
+            "error: Expect expression.\n \
+             --> script.lox:2:10\n  \
+             |\n\
+             2 | print a +* 2;\n  \
+             |          ^\n"
+        );
+    }
+    #[test]
+    fn wide_span() {
+        let source = "print -\"lox\";";
+        let diagnostic = Diagnostic::warning("Wide.").at(span(1, 8, 13));
+        assert!(Renderer::new("-", source)
+            .render(&diagnostic)
+            .ends_with("|        ^^^^^\n"));
+    }
+    #[test]
+    fn whole_line_with_notes() {
+        let source = "fun f() {\n    return -nil;\n}\n";
+        let diagnostic = Diagnostic::error("Operand must be a number.")
+            .at_line(2)
+            .with_note("[line 2] in f()")
+            .with_note("[line 4] in script");
+        assert_eq!(
+            Renderer::new("script.lox", source).render(&diagnostic),
+            "error: Operand must be a number.\n \
+             --> script.lox:2:5\n  \
+             |\n\
+             2 |     return -nil;\n  \
+             |     ^^^^^^^^^^^^\n  \
+             = note: [line 2] in f()\n  \
+             = note: [line 4] in script\n"
+        );
+    }
+    #[test]
+    fn without_span() {
+        let diagnostic = Diagnostic::error("Something went wrong.");
+        assert_eq!(
+            Renderer::new("script.lox", "").render(&diagnostic),
+            "error: Something went wrong.\n --> script.lox\n"
+        );
+    }
+    #[test]
+    fn colored() {
+        let diagnostic = Diagnostic::error("Oops.").at(span(1, 1, 2));
+        let rendered = Renderer::new("-", "x").color(true).render(&diagnostic);
+        assert!(rendered.starts_with("\x1b[1;31merror\x1b[0m"));
+        assert!(rendered.contains("\x1b[1;31m^\x1b[0m"));
+    }
+}
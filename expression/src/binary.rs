@@ -1,10 +1,11 @@
-use crate::{literal::Value, Expression, Node, OperatorNode};
+use crate::{literal::Value, trace, Environment, Expression, Node, OperatorNode, RuntimeError};
 use core::{
     fmt,
     ops::{Add, Div, Mul, Sub},
 };
+use token::Span;
 type BinaryMap<A, B, Output> =
-    dyn Fn(<A as Expression>::Output, <B as Expression>::Output) -> Option<Output>;
+    dyn Fn(<A as Expression>::Output, <B as Expression>::Output) -> Result<Output, RuntimeError>;
 pub trait BinaryOperator: OperatorNode {
     type A: Expression;
     type B: Expression;
@@ -24,6 +25,7 @@ pub trait BinaryOperator: OperatorNode {
             operand_a: a,
             operand_b: b,
             operator: Box::new(self),
+            span: Span::default(),
         }
     }
 }
@@ -37,6 +39,27 @@ where
     operand_a: A,
     operand_b: B,
     operator: Box<dyn BinaryOperator<A = A, B = B, Output = Output>>,
+    /// Where the operator appears in the source
+    span: Span,
+}
+impl<A, B, Output> BinaryExpression<A, B, Output>
+where
+    A: Expression,
+    B: Expression,
+    Output: Expression,
+{
+    /// Locates the operator at `span`, for errors raised by it.
+    pub fn at(self, span: Span) -> Self {
+        Self { span, ..self }
+    }
+    #[inline]
+    pub fn operands(&self) -> (&A, &B) {
+        (&self.operand_a, &self.operand_b)
+    }
+    #[inline]
+    pub fn span(&self) -> Span {
+        self.span
+    }
 }
 impl From<BinaryExpression<Node, Node, Node>> for Node {
     fn from(value: BinaryExpression<Node, Node, Node>) -> Self {
@@ -50,11 +73,11 @@ where
     Out: Expression + fmt::Debug,
 {
     type Output = Out;
-    fn eval(&self) -> Option<Self::Output> {
-        self.operand_a.eval().and_then(|a| {
-            self.operand_b.eval().and_then(|b| {
-                let res = self.operator.identity()(a, b);
-                println!("Evaluated {self:#?} as {res:?}");
+    fn eval(&self, environment: &Environment) -> Result<Self::Output, RuntimeError> {
+        self.operand_a.eval(environment).and_then(|a| {
+            self.operand_b.eval(environment).and_then(|b| {
+                let res = self.operator.identity()(a, b).map_err(|e| e.at(self.span));
+                log::trace!(target: trace::OPERATOR, "Evaluated {:?} as {res:?}", self.operator);
                 res
             })
         })
@@ -63,6 +86,9 @@ where
 #[derive(Debug)]
 pub enum ArithmeticOperator {
     Plus,
+    /// `+` that also stringifies a number or bool added to a string,
+    /// an opt-in extension to Lox
+    Concatenate,
     Minus,
     Divides,
     Times,
@@ -71,21 +97,19 @@ impl OperatorNode for ArithmeticOperator {
     type Output = Node;
 }
 impl Add<Value> for Node {
-    type Output = Option<Value>;
+    type Output = Result<Value, RuntimeError>;
     fn add(self, rhs: Value) -> Self::Output {
-        self.eval().and_then(|a| a.add(rhs))
+        self.constant().and_then(|a| a.add(rhs))
         // Node::binary(ArithmeticOperator::Plus.express(self, Node::Literal(rhs)))
     }
 }
 impl BinaryOperator for ArithmeticOperator {
     type A = Node;
     type B = Node;
-    fn identity(
-        &self,
-    ) -> Box<dyn Fn(<Node as Expression>::Output, <Node as Expression>::Output) -> Option<Node>>
-    {
+    fn identity(&self) -> Box<BinaryMap<Node, Node, Node>> {
         match self {
             Self::Plus => Box::new(|a, b| a.add(b).map(Node::Literal)),
+            Self::Concatenate => Box::new(|a, b| a.concatenate(b).map(Node::Literal)),
             Self::Minus => Box::new(|a, b| a.sub(b).map(Node::Literal)),
             Self::Divides => Box::new(|a, b| a.div(b).map(Node::Literal)),
             Self::Times => Box::new(|a, b| a.mul(b).map(Node::Literal)),
@@ -100,8 +124,8 @@ mod arithmetic_op_tests {
         assert_eq!(
             ArithmeticOperator::Plus
                 .express(15.0.into(), 5.0.into())
-                .eval(),
-            Some(20.0.into())
+                .eval(&Environment::new()),
+            Ok(20.0.into())
         )
     }
     #[test]
@@ -109,8 +133,8 @@ mod arithmetic_op_tests {
         assert_eq!(
             ArithmeticOperator::Divides
                 .express(15.0.into(), 5.0.into())
-                .eval(),
-            Some(3.0.into())
+                .eval(&Environment::new()),
+            Ok(3.0.into())
         )
     }
     #[test]
@@ -122,10 +146,68 @@ mod arithmetic_op_tests {
                     .into(),
                 4.0.into(),
             )
-            .eval()
-            .is_some_and(|l| l.eq(&5.0.into())));
+            .eval(&Environment::new())
+            .is_ok_and(|l| l.eq(&5.0.into())));
+    }
+    #[test]
+    fn concatenates_strings() {
+        assert_eq!(
+            ArithmeticOperator::Plus
+                .express("foo".into(), "bar".into())
+                .eval(&Environment::new()),
+            Ok("foobar".into())
+        )
+    }
+    #[test]
+    fn plus_rejects_mixed_operands() {
+        let operands: [(Node, Node); 4] = [
+            ("a".into(), 1.0.into()),
+            (1.0.into(), "a".into()),
+            ("a".into(), true.into()),
+            (Value::Nil.into(), Value::Nil.into()),
+        ];
+        for (a, b) in operands {
+            let res = ArithmeticOperator::Plus
+                .express(a, b)
+                .eval(&Environment::new());
+            assert_eq!(
+                res.map_err(|e| e.message),
+                Err("Operands must be two numbers or two strings.".into())
+            );
+        }
+    }
+    #[test]
+    fn concatenate_stringifies_numbers_and_bools() {
+        let cases: [(Node, Node, &str); 4] = [
+            ("n = ".into(), 3.0.into(), "n = 3"),
+            (1.5.into(), "x".into(), "1.5x"),
+            ("is ".into(), true.into(), "is true"),
+            ("a".into(), "b".into(), "ab"),
+        ];
+        for (a, b, expected) in cases {
+            assert_eq!(
+                ArithmeticOperator::Concatenate
+                    .express(a, b)
+                    .eval(&Environment::new()),
+                Ok(expected.into())
+            );
+        }
+        assert_eq!(
+            ArithmeticOperator::Concatenate
+                .express(1.0.into(), 2.0.into())
+                .eval(&Environment::new()),
+            Ok(3.0.into())
+        );
+    }
+    #[test]
+    fn concatenate_does_not_stringify_nil() {
+        assert!(ArithmeticOperator::Concatenate
+            .express("a".into(), Value::Nil.into())
+            .eval(&Environment::new())
+            .is_err());
     }
 }
+
 #[derive(Debug)]
 pub enum EqualityOperator {
     Eq,
@@ -137,17 +219,10 @@ impl OperatorNode for EqualityOperator {
 impl BinaryOperator for EqualityOperator {
     type A = Node;
     type B = Node;
-    fn identity(
-        &self,
-    ) -> Box<
-        dyn Fn(
-            <Self::A as Expression>::Output,
-            <Self::B as Expression>::Output,
-        ) -> Option<Self::Output>,
-    > {
+    fn identity(&self) -> Box<BinaryMap<Self::A, Self::B, Self::Output>> {
         match self {
-            Self::Eq => Box::new(|a, b| Some(Value::Boolean(a.eq(&b)).into())),
-            Self::Ne => Box::new(|a, b| Some(Value::Boolean(a.ne(&b)).into())),
+            Self::Eq => Box::new(|a, b| Ok(Value::Boolean(a.eq(&b)).into())),
+            Self::Ne => Box::new(|a, b| Ok(Value::Boolean(a.ne(&b)).into())),
         }
     }
 }
@@ -158,13 +233,15 @@ mod equality_operator_node_tests {
     fn string_to_string_equality() {
         assert!(EqualityOperator::Eq
             .express("Test".into(), "Test".into(),)
-            .eval()
+            .eval(&Environment::new())
+            .ok()
             .as_ref()
             .and_then(Node::as_bool)
             .is_some_and(std::convert::identity));
         assert!(EqualityOperator::Eq
             .express("Test".into(), "Testing".into(),)
-            .eval()
+            .eval(&Environment::new())
+            .ok()
             .as_ref()
             .and_then(Node::as_bool)
             .map(std::ops::Not::not)
@@ -174,29 +251,64 @@ mod equality_operator_node_tests {
     fn number_to_number_equality() {
         assert!(EqualityOperator::Eq
             .express(10.0.into(), 10.0.into(),)
-            .eval()
+            .eval(&Environment::new())
+            .ok()
             .as_ref()
             .and_then(Node::as_bool)
             .is_some_and(std::convert::identity));
         assert!(EqualityOperator::Eq
             .express(10.0.into(), 0.0.into(),)
-            .eval()
+            .eval(&Environment::new())
+            .ok()
             .as_ref()
             .and_then(Node::as_bool)
             .map(std::ops::Not::not)
             .is_some_and(std::convert::identity))
     }
     #[test]
+    fn nil_equality() {
+        assert_eq!(
+            EqualityOperator::Eq
+                .express(Value::Nil.into(), Value::Nil.into())
+                .eval(&Environment::new()),
+            Ok(true.into())
+        );
+    }
+    #[test]
+    fn different_types_are_never_equal() {
+        let operands: [(Node, Node); 5] = [
+            (1.0.into(), "1".into()),
+            (0.0.into(), false.into()),
+            ("".into(), Value::Nil.into()),
+            (false.into(), Value::Nil.into()),
+            ("true".into(), true.into()),
+        ];
+        for (a, b) in operands {
+            assert_eq!(
+                EqualityOperator::Eq.express(a, b).eval(&Environment::new()),
+                Ok(false.into())
+            );
+        }
+        assert_eq!(
+            EqualityOperator::Ne
+                .express(1.0.into(), "1".into())
+                .eval(&Environment::new()),
+            Ok(true.into())
+        );
+    }
+    #[test]
     fn bool_to_bool_equality() {
         assert!(EqualityOperator::Eq
             .express(false.into(), false.into(),)
-            .eval()
+            .eval(&Environment::new())
+            .ok()
             .as_ref()
             .and_then(Node::as_bool)
             .is_some_and(std::convert::identity));
         assert!(EqualityOperator::Eq
             .express(true.into(), false.into())
-            .eval()
+            .eval(&Environment::new())
+            .ok()
             .as_ref()
             .and_then(Node::as_bool)
             .map(std::ops::Not::not)
@@ -214,19 +326,20 @@ impl OperatorNode for OrderingOperatorNode {
 impl BinaryOperator for OrderingOperatorNode {
     type A = Node;
     type B = Node;
-    fn identity(
-        &self,
-    ) -> Box<
-        dyn Fn(
-            <Self::A as Expression>::Output,
-            <Self::B as Expression>::Output,
-        ) -> Option<Self::Output>,
-    > {
+    fn identity(&self) -> Box<BinaryMap<Self::A, Self::B, Self::Output>> {
         match self {
-            Self::Lt { equal: true } => Box::new(|a, b| Some(Value::Boolean(a.le(&b)).into())),
-            Self::Lt { equal: false } => Box::new(|a, b| Some(Value::Boolean(a.lt(&b)).into())),
-            Self::Gt { equal: true } => Box::new(|a, b| Some(Value::Boolean(a.ge(&b)).into())),
-            Self::Gt { equal: false } => Box::new(|a, b| Some(Value::Boolean(a.gt(&b)).into())),
+            Self::Lt { equal: true } => {
+                Box::new(|a, b| a.numbers(b).map(|(a, b)| Value::Boolean(a <= b).into()))
+            }
+            Self::Lt { equal: false } => {
+                Box::new(|a, b| a.numbers(b).map(|(a, b)| Value::Boolean(a < b).into()))
+            }
+            Self::Gt { equal: true } => {
+                Box::new(|a, b| a.numbers(b).map(|(a, b)| Value::Boolean(a >= b).into()))
+            }
+            Self::Gt { equal: false } => {
+                Box::new(|a, b| a.numbers(b).map(|(a, b)| Value::Boolean(a > b).into()))
+            }
         }
     }
 }
@@ -237,15 +350,44 @@ mod ord_tests {
     fn numbers_lt() {
         let res = OrderingOperatorNode::Lt { equal: false }
             .express(1.0.into(), 2.0.into())
-            .eval();
-        assert_eq!(res, Some(true.into()));
+            .eval(&Environment::new());
+        assert_eq!(res, Ok(true.into()));
         let res = OrderingOperatorNode::Lt { equal: false }
             .express(1.0.into(), 1.0.into())
-            .eval();
-        assert_eq!(res, Some(false.into()));
+            .eval(&Environment::new());
+        assert_eq!(res, Ok(false.into()));
         let res = OrderingOperatorNode::Lt { equal: true }
             .express(1.0.into(), 1.0.into())
-            .eval();
-        assert_eq!(res, Some(true.into()));
+            .eval(&Environment::new());
+        assert_eq!(res, Ok(true.into()));
+    }
+    #[test]
+    fn numbers_gt() {
+        let res = OrderingOperatorNode::Gt { equal: false }
+            .express(2.0.into(), 1.0.into())
+            .eval(&Environment::new());
+        assert_eq!(res, Ok(true.into()));
+        let res = OrderingOperatorNode::Gt { equal: true }
+            .express(1.0.into(), 2.0.into())
+            .eval(&Environment::new());
+        assert_eq!(res, Ok(false.into()));
+    }
+    #[test]
+    fn only_numbers_are_ordered() {
+        let operands: [(Node, Node); 4] = [
+            (1.0.into(), "b".into()),
+            ("a".into(), "b".into()),
+            (true.into(), false.into()),
+            (Value::Nil.into(), 1.0.into()),
+        ];
+        for (a, b) in operands {
+            let res = OrderingOperatorNode::Lt { equal: false }
+                .express(a, b)
+                .eval(&Environment::new());
+            assert_eq!(
+                res.map_err(|e| e.message),
+                Err("Operands must be numbers.".into())
+            );
+        }
     }
 }
@@ -0,0 +1,331 @@
+use crate::{
+    function::{Callable, Function, FunctionDeclaration},
+    Environment, Expression, Identifier, Node, RuntimeError, Value, Variable,
+};
+use core::fmt;
+use std::{cell::RefCell, collections::HashMap, mem, rc::Rc};
+use token::Span;
+
+/// A `class` declaration.
+#[derive(Debug)]
+pub struct ClassDeclaration {
+    pub name: Identifier,
+    pub superclass: Option<Variable>,
+    pub methods: Vec<Rc<FunctionDeclaration>>,
+}
+impl ClassDeclaration {
+    /// Creates the class, binding it to its name in `environment`.
+    pub(crate) fn declare(&self, environment: &Environment) -> Result<(), RuntimeError> {
+        let superclass = match &self.superclass {
+            Some(variable) => match variable.eval(environment)? {
+                Value::Class(class) => Some(class),
+                _ => {
+                    let error = RuntimeError::new("Superclass must be a class.");
+                    return Err(error.at(variable.span()));
+                }
+            },
+            None => None,
+        };
+        environment.define(&self.name, Value::Nil);
+        let closure = match &superclass {
+            Some(superclass) => {
+                let closure = environment.enclose();
+                closure.define("super", Value::Class(superclass.clone()));
+                closure
+            }
+            None => environment.clone(),
+        };
+        let methods = self
+            .methods
+            .iter()
+            .map(|method| {
+                let function = Function::new(method.clone(), closure.clone())
+                    .initializer(method.name.lexeme == "init");
+                (method.name.lexeme.clone(), Rc::new(function))
+            })
+            .collect();
+        let class = Class {
+            name: self.name.lexeme.clone(),
+            superclass,
+            methods,
+        };
+        environment.assign(&self.name.lexeme, Value::Class(Rc::new(class)))
+    }
+}
+pub struct Class {
+    name: String,
+    superclass: Option<Rc<Class>>,
+    methods: HashMap<String, Rc<Function>>,
+}
+impl Class {
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    /// The method called `name` on this class or its nearest superclass
+    /// that has one.
+    pub fn find_method(&self, name: &str) -> Option<Rc<Function>> {
+        match (self.methods.get(name), &self.superclass) {
+            (Some(method), _) => Some(method.clone()),
+            (None, Some(superclass)) => superclass.find_method(name),
+            (None, None) => None,
+        }
+    }
+}
+impl fmt::Debug for Class {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Class").field(&self.name).finish()
+    }
+}
+impl fmt::Display for Class {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.name)
+    }
+}
+/// Calling a class creates an instance, running `init` if it has one.
+impl Callable for Rc<Class> {
+    fn arity(&self) -> usize {
+        self.find_method("init")
+            .map_or(0, |initializer| initializer.arity())
+    }
+    fn call(&self, arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+        let instance = Rc::new(Instance {
+            class: self.clone(),
+            fields: RefCell::default(),
+        });
+        if let Some(initializer) = self.find_method("init") {
+            initializer.bind(instance.clone()).call(arguments)?;
+        }
+        Ok(Value::Instance(instance))
+    }
+}
+pub struct Instance {
+    class: Rc<Class>,
+    fields: RefCell<HashMap<String, Value>>,
+}
+impl Instance {
+    /// The field called `name`, or else the method bound to this
+    /// instance.
+    pub fn get(self: &Rc<Self>, name: &str) -> Result<Value, RuntimeError> {
+        if let Some(value) = self.fields.borrow().get(name) {
+            return Ok(value.clone());
+        }
+        match self.class.find_method(name) {
+            Some(method) => Ok(Value::Function(Rc::new(method.bind(self.clone())))),
+            None => Err(RuntimeError::new(format!("Undefined property '{name}'."))),
+        }
+    }
+    pub fn set(&self, name: impl ToString, value: Value) {
+        self.fields.borrow_mut().insert(name.to_string(), value);
+    }
+}
+/// Only the class, as the fields may well contain the instance itself.
+impl fmt::Debug for Instance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Instance").field(&self.class.name).finish()
+    }
+}
+impl fmt::Display for Instance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_fmt(format_args!("{} instance", self.class.name))
+    }
+}
+/// A property access, `object.name`.
+#[derive(Debug)]
+pub struct Get {
+    object: Node,
+    name: String,
+    /// Where the property name appears in the source
+    span: Span,
+}
+impl Get {
+    pub fn new(object: Node, name: impl ToString, span: Span) -> Self {
+        Self {
+            object,
+            name: name.to_string(),
+            span,
+        }
+    }
+}
+impl Get {
+    #[inline]
+    pub fn object(&self) -> &Node {
+        &self.object
+    }
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+impl From<Get> for Node {
+    fn from(value: Get) -> Self {
+        Node::Get(Box::new(value))
+    }
+}
+impl Expression for Get {
+    type Output = Value;
+    fn eval(&self, environment: &Environment) -> Result<Self::Output, RuntimeError> {
+        match self.object.eval(environment)? {
+            Value::Instance(instance) => instance.get(&self.name),
+            _ => Err(RuntimeError::new("Only instances have properties.")),
+        }
+        .map_err(|e| e.at(self.span))
+    }
+}
+/// A field assignment, `object.name = value`.
+#[derive(Debug)]
+pub struct Set {
+    object: Node,
+    name: String,
+    value: Node,
+    /// Where the property name appears in the source
+    span: Span,
+}
+impl Set {
+    /// Turns the property access being assigned to into an assignment.
+    pub fn new(target: Get, value: Node) -> Self {
+        Self {
+            object: target.object,
+            name: target.name,
+            value,
+            span: target.span,
+        }
+    }
+}
+impl Set {
+    #[inline]
+    pub fn object(&self) -> &Node {
+        &self.object
+    }
+    #[inline]
+    pub fn value(&self) -> &Node {
+        &self.value
+    }
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+impl From<Set> for Node {
+    fn from(value: Set) -> Self {
+        Node::Set(Box::new(value))
+    }
+}
+impl Expression for Set {
+    type Output = Value;
+    fn eval(&self, environment: &Environment) -> Result<Self::Output, RuntimeError> {
+        let Value::Instance(instance) = self.object.eval(environment)? else {
+            return Err(RuntimeError::new("Only instances have fields.").at(self.span));
+        };
+        let value = self.value.eval(environment)?;
+        environment
+            .allocate(self.name.len() + mem::size_of::<Value>())
+            .map_err(|e| e.at(self.span))?;
+        instance.set(&self.name, value.clone());
+        Ok(value)
+    }
+}
+/// A superclass method lookup, `super.method`.
+#[derive(Debug)]
+pub struct Super {
+    keyword: Variable,
+    method: String,
+    /// Where the method name appears in the source
+    span: Span,
+}
+impl Super {
+    pub fn new(keyword: Span, method: impl ToString, span: Span) -> Self {
+        Self {
+            keyword: Variable::new("super", keyword),
+            method: method.to_string(),
+            span,
+        }
+    }
+    #[inline]
+    pub fn keyword(&self) -> &Variable {
+        &self.keyword
+    }
+    #[inline]
+    pub fn method(&self) -> &str {
+        &self.method
+    }
+}
+impl From<Super> for Node {
+    fn from(value: Super) -> Self {
+        Node::Super(Box::new(value))
+    }
+}
+impl Expression for Super {
+    type Output = Value;
+    fn eval(&self, environment: &Environment) -> Result<Self::Output, RuntimeError> {
+        let Value::Class(superclass) = self.keyword.eval(environment)? else {
+            unreachable!("'super' is only ever bound to a class")
+        };
+        // `this` is bound in the scope just inside the one binding `super`
+        let this = match self.keyword.depth() {
+            Some(depth) => environment.ancestor(depth.saturating_sub(1)),
+            None => environment.clone(),
+        };
+        let Value::Instance(instance) = this.get("this")? else {
+            unreachable!("'this' is only ever bound to an instance")
+        };
+        match superclass.find_method(&self.method) {
+            Some(method) => Ok(Value::Function(Rc::new(method.bind(instance)))),
+            None => {
+                let error = RuntimeError::new(format!("Undefined property '{}'.", self.method));
+                Err(error.at(self.span))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod class_tests {
+    use super::*;
+    use crate::Statement;
+    fn method(name: &str, value: f64) -> Rc<FunctionDeclaration> {
+        Rc::new(FunctionDeclaration {
+            name: name.into(),
+            params: vec![],
+            body: vec![Statement::Return {
+                value: Some(value.into()),
+                span: Span::default(),
+            }],
+        })
+    }
+    fn declare(environment: &Environment, name: &str, superclass: Option<&str>) {
+        ClassDeclaration {
+            name: name.into(),
+            superclass: superclass.map(|name| Variable::new(name, Span::default())),
+            methods: vec![method(name, 1.0), method("shared", 2.0)],
+        }
+        .declare(environment)
+        .unwrap();
+    }
+    #[test]
+    fn methods_are_inherited() {
+        let environment = Environment::new();
+        declare(&environment, "A", None);
+        declare(&environment, "B", Some("A"));
+        let Ok(Value::Class(b)) = environment.get("B") else {
+            panic!("B should be a class")
+        };
+        assert!(b.find_method("A").is_some());
+        assert!(b.find_method("B").is_some());
+        assert!(b.find_method("C").is_none());
+    }
+    #[test]
+    fn fields_shadow_methods() {
+        let environment = Environment::new();
+        declare(&environment, "A", None);
+        let Ok(Value::Instance(a)) = environment.get("A").and_then(|class| match class {
+            Value::Class(class) => class.call(vec![]),
+            _ => unreachable!(),
+        }) else {
+            panic!("calling A should create an instance")
+        };
+        assert!(matches!(a.get("shared"), Ok(Value::Function(_))));
+        a.set("shared", 3.0.into());
+        assert_eq!(a.get("shared"), Ok(3.0.into()));
+    }
+}
@@ -0,0 +1,183 @@
+use crate::{
+    limits::{Budget, CallGuard, Limits},
+    native::NativeFunction,
+    RuntimeError, Value,
+};
+use output::Output;
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+/// The bindings of one scope, along with the scope it is nested in.
+#[derive(Debug, Default)]
+struct Scope {
+    values: HashMap<String, Value>,
+    enclosing: Option<Environment>,
+    /// Shared by every scope of the program
+    budget: Rc<Budget>,
+    streams: Rc<Streams>,
+}
+/// Where the program writes, shared by every scope of the program.
+#[derive(Debug)]
+struct Streams {
+    stdout: RefCell<Output>,
+    stderr: RefCell<Output>,
+}
+impl Default for Streams {
+    fn default() -> Self {
+        Self {
+            stdout: RefCell::new(Output::stdout()),
+            stderr: RefCell::new(Output::stderr()),
+        }
+    }
+}
+/// A handle on a chain of scopes. Clones share the same bindings, so
+/// a nested scope sees assignments made through any handle on its
+/// enclosing scopes.
+#[derive(Debug, Default, Clone)]
+pub struct Environment(Rc<RefCell<Scope>>);
+impl Environment {
+    /// A new global scope.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// A new scope nested inside this one, e.g. for a block.
+    pub fn enclose(&self) -> Self {
+        Self(Rc::new(RefCell::new(Scope {
+            values: HashMap::new(),
+            enclosing: Some(self.clone()),
+            budget: self.0.borrow().budget.clone(),
+            streams: self.0.borrow().streams.clone(),
+        })))
+    }
+    /// Where `print` writes, stdout unless configured otherwise.
+    pub fn stdout(&self) -> Output {
+        self.0.borrow().streams.stdout.borrow().clone()
+    }
+    pub fn set_stdout(&self, output: Output) {
+        *self.0.borrow().streams.stdout.borrow_mut() = output;
+    }
+    /// Where the host should report errors, stderr unless configured
+    /// otherwise.
+    pub fn stderr(&self) -> Output {
+        self.0.borrow().streams.stderr.borrow().clone()
+    }
+    pub fn set_stderr(&self, output: Output) {
+        *self.0.borrow().streams.stderr.borrow_mut() = output;
+    }
+    /// The limits on the program running in this environment.
+    pub fn limits(&self) -> Limits {
+        self.0.borrow().budget.limits()
+    }
+    pub fn set_limits(&self, limits: Limits) {
+        self.0.borrow().budget.set_limits(limits);
+    }
+    /// Forgets the steps, allocations and time used so far, so that the limits
+    /// apply afresh, e.g. to the next script run in this environment.
+    pub fn reset_budget(&self) {
+        self.0.borrow().budget.reset();
+    }
+    pub(crate) fn step(&self) -> Result<(), RuntimeError> {
+        self.0.borrow().budget.step()
+    }
+    pub(crate) fn allocate(&self, bytes: usize) -> Result<(), RuntimeError> {
+        self.0.borrow().budget.allocate(bytes)
+    }
+    pub(crate) fn enter_call(&self) -> Result<CallGuard, RuntimeError> {
+        self.0.borrow().budget.enter_call()
+    }
+    /// Binds `name` in this scope, shadowing any outer binding and
+    /// replacing any binding of the same name in this scope.
+    pub fn define(&self, name: impl ToString, value: Value) {
+        self.0.borrow_mut().values.insert(name.to_string(), value);
+    }
+    /// Binds `name` in this scope to a native function taking `arity`
+    /// arguments.
+    pub fn define_native(
+        &self,
+        name: &str,
+        arity: usize,
+        function: impl Fn(&[Value]) -> Result<Value, RuntimeError> + 'static,
+    ) {
+        self.define(name, NativeFunction::new(name, arity, function).into());
+    }
+    /// The value bound to `name` in the nearest scope that binds it.
+    pub fn get(&self, name: &str) -> Result<Value, RuntimeError> {
+        let scope = self.0.borrow();
+        match (scope.values.get(name), &scope.enclosing) {
+            (Some(value), _) => Ok(value.clone()),
+            (None, Some(enclosing)) => enclosing.get(name),
+            (None, None) => Err(undefined(name)),
+        }
+    }
+    /// Rebinds `name` in the nearest scope that binds it.
+    pub fn assign(&self, name: &str, value: Value) -> Result<(), RuntimeError> {
+        let mut scope = self.0.borrow_mut();
+        if let Some(slot) = scope.values.get_mut(name) {
+            *slot = value;
+            return Ok(());
+        }
+        match &scope.enclosing {
+            Some(enclosing) => enclosing.assign(name, value),
+            None => Err(undefined(name)),
+        }
+    }
+    /// The scope `distance` scopes out from this one.
+    pub fn ancestor(&self, distance: usize) -> Self {
+        let mut environment = self.clone();
+        for _ in 0..distance {
+            let enclosing = environment.0.borrow().enclosing.clone();
+            match enclosing {
+                Some(enclosing) => environment = enclosing,
+                None => break,
+            }
+        }
+        environment
+    }
+    /// The outermost, global scope.
+    pub fn globals(&self) -> Self {
+        self.ancestor(usize::MAX)
+    }
+    /// Whether `name` is bound in this scope or any enclosing one.
+    #[inline]
+    pub fn is_defined(&self, name: &str) -> bool {
+        self.get(name).is_ok()
+    }
+}
+fn undefined(name: &str) -> RuntimeError {
+    RuntimeError::new(format!("Undefined variable '{name}'."))
+}
+
+#[cfg(test)]
+mod environment_tests {
+    use super::*;
+    #[test]
+    fn shadowing() {
+        let globals = Environment::new();
+        globals.define("a", "global".into());
+        let block = globals.enclose();
+        assert_eq!(block.get("a"), Ok("global".into()));
+        block.define("a", "block".into());
+        assert_eq!(block.get("a"), Ok("block".into()));
+        assert_eq!(globals.get("a"), Ok("global".into()));
+    }
+    #[test]
+    fn assign_updates_the_enclosing_scope() {
+        let globals = Environment::new();
+        globals.define("a", 1.0.into());
+        globals.enclose().assign("a", 2.0.into()).unwrap();
+        assert_eq!(globals.get("a"), Ok(2.0.into()));
+    }
+    #[test]
+    fn undefined_variables_are_named() {
+        let environment = Environment::new().enclose();
+        assert_eq!(
+            environment.get("missing").map_err(|e| e.message),
+            Err("Undefined variable 'missing'.".into())
+        );
+        assert_eq!(
+            environment
+                .assign("missing", Value::Nil)
+                .map_err(|e| e.message),
+            Err("Undefined variable 'missing'.".into())
+        );
+    }
+}
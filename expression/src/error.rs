@@ -0,0 +1,53 @@
+use crate::limits::Limit;
+use core::fmt;
+use diagnostics::{Diagnostic, ToDiagnostic};
+use token::Span;
+
+/// An error raised while evaluating, located at the operator or name
+/// that raised it once that is known.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuntimeError {
+    pub message: String,
+    pub span: Option<Span>,
+    /// The limit that ran out, if that is what stopped the program
+    pub limit: Option<Limit>,
+}
+impl RuntimeError {
+    pub fn new(message: impl ToString) -> Self {
+        Self {
+            message: message.to_string(),
+            span: None,
+            limit: None,
+        }
+    }
+    pub fn limit_exceeded(limit: Limit) -> Self {
+        Self {
+            limit: Some(limit),
+            ..Self::new(limit)
+        }
+    }
+    /// Locates the error at `span`, unless it was already located by a
+    /// more specific part of the expression.
+    pub fn at(mut self, span: Span) -> Self {
+        self.span.get_or_insert(span);
+        self
+    }
+}
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.span {
+            Some(span) => f.write_fmt(format_args!("[line {span}] {}", self.message)),
+            None => f.write_str(&self.message),
+        }
+    }
+}
+impl std::error::Error for RuntimeError {}
+impl ToDiagnostic for RuntimeError {
+    fn to_diagnostic(&self) -> Diagnostic {
+        let diagnostic = Diagnostic::error(&self.message);
+        match self.span {
+            Some(span) => diagnostic.at(span),
+            None => diagnostic,
+        }
+    }
+}
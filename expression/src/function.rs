@@ -0,0 +1,220 @@
+use crate::{
+    class::Instance, statement::Completion, Environment, Expression, Identifier, Node,
+    RuntimeError, Statement, Value,
+};
+use core::fmt;
+use std::{mem, rc::Rc};
+use token::Span;
+
+/// The most arguments a call may pass, and so the most parameters a
+/// function may declare.
+pub const MAX_ARITY: usize = 255;
+
+/// Anything that can be called with a list of arguments.
+pub trait Callable {
+    fn arity(&self) -> usize;
+    fn call(&self, arguments: Vec<Value>) -> Result<Value, RuntimeError>;
+}
+/// A `fun` declaration, shared by every closure created from it.
+#[derive(Debug)]
+pub struct FunctionDeclaration {
+    pub name: Identifier,
+    pub params: Vec<Identifier>,
+    pub body: Vec<Statement>,
+}
+/// A function value, closing over the environment it was declared in.
+pub struct Function {
+    declaration: Rc<FunctionDeclaration>,
+    closure: Environment,
+    /// Whether this is a class' `init` method, which always returns
+    /// the instance
+    is_initializer: bool,
+}
+impl Function {
+    pub fn new(declaration: Rc<FunctionDeclaration>, closure: Environment) -> Self {
+        Self {
+            declaration,
+            closure,
+            is_initializer: false,
+        }
+    }
+    pub fn initializer(self, is_initializer: bool) -> Self {
+        Self {
+            is_initializer,
+            ..self
+        }
+    }
+    /// The method bound to `instance`, with `this` referring to it.
+    pub fn bind(&self, instance: Rc<Instance>) -> Self {
+        let closure = self.closure.enclose();
+        closure.define("this", Value::Instance(instance));
+        Self {
+            declaration: self.declaration.clone(),
+            closure,
+            is_initializer: self.is_initializer,
+        }
+    }
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.declaration.name.lexeme
+    }
+}
+/// Only the name, as the closure may well contain the function itself.
+impl fmt::Debug for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Function").field(&self.name()).finish()
+    }
+}
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_fmt(format_args!("<fn {}>", self.name()))
+    }
+}
+impl Callable for Function {
+    fn arity(&self) -> usize {
+        self.declaration.params.len()
+    }
+    fn call(&self, arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+        let environment = self.closure.enclose();
+        for (param, argument) in self.declaration.params.iter().zip(arguments) {
+            environment.define(param, argument);
+        }
+        for statement in &self.declaration.body {
+            if let Completion::Return(value) = statement.run(&environment)? {
+                if self.is_initializer {
+                    break;
+                }
+                return Ok(value);
+            }
+        }
+        if self.is_initializer {
+            self.closure.get("this")
+        } else {
+            Ok(Value::Nil)
+        }
+    }
+}
+/// A call expression, `callee(arguments...)`.
+#[derive(Debug)]
+pub struct Call {
+    callee: Node,
+    arguments: Vec<Node>,
+    /// Where the closing parenthesis appears in the source
+    span: Span,
+}
+impl Call {
+    pub fn new(callee: Node, arguments: Vec<Node>, span: Span) -> Self {
+        Self {
+            callee,
+            arguments,
+            span,
+        }
+    }
+}
+impl Call {
+    #[inline]
+    pub fn callee(&self) -> &Node {
+        &self.callee
+    }
+    #[inline]
+    pub fn arguments(&self) -> &[Node] {
+        &self.arguments
+    }
+}
+impl From<Call> for Node {
+    fn from(value: Call) -> Self {
+        Node::Call(Box::new(value))
+    }
+}
+impl Expression for Call {
+    type Output = Value;
+    fn eval(&self, environment: &Environment) -> Result<Self::Output, RuntimeError> {
+        let callee = self.callee.eval(environment)?;
+        let arguments = self
+            .arguments
+            .iter()
+            .map(|argument| argument.eval(environment))
+            .collect::<Result<Vec<_>, _>>()?;
+        // The new instance, or a scope binding the arguments
+        let bytes = match &callee {
+            Value::Class(_) => mem::size_of::<Instance>(),
+            _ => mem::size_of::<(String, Value)>() * (arguments.len() + 1),
+        };
+        let callee: &dyn Callable = match &callee {
+            Value::Function(function) => function.as_ref(),
+            Value::Native(native) => native.as_ref(),
+            Value::Class(class) => class,
+            _ => {
+                let error = RuntimeError::new("Can only call functions and classes.");
+                return Err(error.at(self.span));
+            }
+        };
+        if arguments.len() != callee.arity() {
+            return Err(RuntimeError::new(format!(
+                "Expected {} arguments but got {}.",
+                callee.arity(),
+                arguments.len()
+            ))
+            .at(self.span));
+        }
+        environment.allocate(bytes).map_err(|e| e.at(self.span))?;
+        let _call = environment.enter_call().map_err(|e| e.at(self.span))?;
+        // Natives don't know where they were called from
+        callee.call(arguments).map_err(|e| e.at(self.span))
+    }
+}
+
+#[cfg(test)]
+mod function_tests {
+    use super::*;
+    use crate::Variable;
+    fn variable(name: &str) -> Node {
+        Variable::new(name, Span::default()).into()
+    }
+    /// `fun add(a, b) { return a + b; }`
+    fn add() -> Statement {
+        Statement::Function(Rc::new(FunctionDeclaration {
+            name: "add".into(),
+            params: vec!["a".into(), "b".into()],
+            body: vec![Statement::Return {
+                value: Some(Node::plus(variable("a"), variable("b"))),
+                span: Span::default(),
+            }],
+        }))
+    }
+    #[test]
+    fn call_returns_a_value() {
+        let environment = Environment::new();
+        add().execute(&environment).unwrap();
+        let call = Call::new(
+            variable("add"),
+            vec![1.0.into(), 2.0.into()],
+            Span::default(),
+        );
+        assert_eq!(call.eval(&environment), Ok(3.0.into()));
+    }
+    #[test]
+    fn arity_is_checked() {
+        let environment = Environment::new();
+        add().execute(&environment).unwrap();
+        let call = Call::new(variable("add"), vec![1.0.into()], Span::default());
+        assert_eq!(
+            call.eval(&environment).map_err(|e| e.message),
+            Err("Expected 2 arguments but got 1.".into())
+        );
+    }
+    #[test]
+    fn only_functions_are_callable() {
+        let call = Call::new("add".into(), vec![], Span::default());
+        assert_eq!(
+            call.eval(&Environment::new()).map_err(|e| e.message),
+            Err("Can only call functions and classes.".into())
+        );
+    }
+    #[test]
+    fn display() {
+        let environment = Environment::new();
+        add().execute(&environment).unwrap();
+        assert_eq!(environment.get("add").unwrap().to_string(), "<fn add>");
+    }
+}
@@ -2,19 +2,19 @@ use core::fmt;
 use std::ops::{Add, Div, Mul, Neg, Not, Sub};
 pub trait Expression {
     type Output;
-    fn eval(&self) -> Option<Self::Output>;
+    fn eval(&self, environment: &Environment) -> Result<Self::Output, RuntimeError>;
 }
 impl<T> Expression for Option<T>
 where
     T: Expression + Copy,
 {
     type Output = T;
-    fn eval(&self) -> Option<Self::Output> {
+    fn eval(&self, _environment: &Environment) -> Result<Self::Output, RuntimeError> {
         let Some(ref val) = self else {
-            return None;
+            return Err(RuntimeError::new("Expect expression."));
         };
 
-        Some(*val)
+        Ok(*val)
     }
 }
 impl<T> Expression for Box<T>
@@ -22,8 +22,8 @@ where
     T: Expression,
 {
     type Output = <T as Expression>::Output;
-    fn eval(&self) -> Option<Self::Output> {
-        T::eval(self)
+    fn eval(&self, environment: &Environment) -> Result<Self::Output, RuntimeError> {
+        T::eval(self, environment)
     }
 }
 impl<T> Expression for Box<dyn Fn() -> T>
@@ -31,85 +31,102 @@ where
     T: Expression,
 {
     type Output = <T as Expression>::Output;
-    fn eval(&self) -> Option<Self::Output> {
-        self().eval()
+    fn eval(&self, environment: &Environment) -> Result<Self::Output, RuntimeError> {
+        self().eval(environment)
     }
 }
+pub mod class;
+mod environment;
+mod error;
+pub mod function;
+mod limits;
+pub use limits::{Limit, Limits, DEFAULT_NATIVE_STACK};
+pub mod native;
+pub use environment::Environment;
+pub use error::RuntimeError;
 mod literal;
+pub use literal::Value;
+mod logical;
+pub use logical::{LogicalExpression, LogicalOperator};
+mod statement;
+pub use statement::Statement;
+pub mod trace;
 mod unary;
 use unary::UnaryExpression;
+mod variable;
 pub use unary::{UnaryNodeOperator, UnaryOperator};
+pub use variable::{Assignment, Identifier, Variable};
 pub trait OperatorNode: fmt::Debug {
     type Output: Expression;
 }
 pub mod binary;
 use binary::{ArithmeticOperator, BinaryExpression, BinaryOperator};
 impl Add for Node {
-    type Output = Option<Node>;
+    type Output = Result<Node, RuntimeError>;
     fn add(self, rhs: Self) -> Self::Output {
-        println!("Adding expression {self:?} to expression {rhs:?}");
+        log::trace!(target: trace::ARITHMETIC, "Adding expression {self:?} to expression {rhs:?}");
         if let Node::Literal(v) = self {
             v.add(rhs)
         } else {
-            self.eval().and_then(|a| a.add(rhs))
-            // self.eval()
+            self.constant().and_then(|a| a.add(rhs))
+            // self.constant()
             //     .map(Node::Literal)
             //     .map(|a| ArithmeticOperator::Plus.express(a, rhs).into())
         }
     }
 }
 impl Sub for Node {
-    type Output = Option<Node>;
+    type Output = Result<Node, RuntimeError>;
     fn sub(self, rhs: Self) -> Self::Output {
-        println!("Subtracting expression {rhs:?} from expression {self:?}");
+        log::trace!(target: trace::ARITHMETIC, "Subtracting expression {rhs:?} from expression {self:?}");
         if let Node::Literal(v) = self {
             v.sub(rhs)
         } else {
-            self.eval().and_then(|a| a.sub(rhs)).into()
+            self.constant().and_then(|a| a.sub(rhs))
         }
     }
 }
 impl Div for Node {
-    type Output = Option<Node>;
+    type Output = Result<Node, RuntimeError>;
     fn div(self, rhs: Self) -> Self::Output {
-        println!("divtracting expression {rhs:?} from expression {self:?}");
+        log::trace!(target: trace::ARITHMETIC, "Dividing expression {self:?} by expression {rhs:?}");
         if let Node::Literal(v) = self {
             v.div(rhs)
         } else {
-            self.eval().and_then(|a| a.div(rhs)).into()
+            self.constant().and_then(|a| a.div(rhs))
         }
     }
 }
 impl Mul for Node {
-    type Output = Option<Node>;
+    type Output = Result<Node, RuntimeError>;
     fn mul(self, rhs: Self) -> Self::Output {
-        println!("Multiplying expression {rhs:?} from expression {self:?}");
+        log::trace!(target: trace::ARITHMETIC, "Multiplying expression {self:?} by expression {rhs:?}");
         if let Node::Literal(v) = self {
             v.mul(rhs)
         } else {
-            self.eval().and_then(|a| a.mul(rhs)).into()
+            self.constant().and_then(|a| a.mul(rhs))
         }
     }
 }
 impl Neg for Node {
-    type Output = Option<f32>;
+    type Output = Result<f64, RuntimeError>;
     fn neg(self) -> Self::Output {
-        println!("Negating expression {self:?}");
+        log::trace!(target: trace::ARITHMETIC, "Negating expression {self:?}");
         if let Node::Literal(v) = self {
             v.neg()
         } else {
-            self.eval().and_then(Neg::neg)
+            self.constant().and_then(Neg::neg)
         }
     }
 }
 impl Not for Node {
-    type Output = Option<bool>;
+    type Output = Result<bool, RuntimeError>;
     fn not(self) -> Self::Output {
-        println!("Negating expression {self:?}");
+        log::trace!(target: trace::ARITHMETIC, "Negating expression {self:?}");
         if let Node::Literal(v) = self {
-            Some(v.not())
+            Ok(v.not())
         } else {
-            self.eval().map(Not::not)
+            self.constant().map(Not::not)
         }
     }
 }
@@ -117,7 +134,7 @@ impl PartialEq for Node {
     fn eq(&self, other: &Self) -> bool {
         match self {
             Node::Literal(l1) => l1.eq(other),
-            other => other.eval().is_some_and(|e| e.eq(other)),
+            other => other.constant().is_ok_and(|e| e.eq(other)),
         }
     }
 }
@@ -125,7 +142,15 @@ pub enum Node {
     Literal(literal::Value),
     Unary(Box<UnaryExpression<Node, Node>>),
     Binary(Box<BinaryExpression<Node, Node, Node>>),
+    Logical(Box<LogicalExpression>),
     Grouping(Box<Node>),
+    Variable(Variable),
+    Assign(Box<Assignment>),
+    Call(Box<function::Call>),
+    Get(Box<class::Get>),
+    Set(Box<class::Set>),
+    This(Variable),
+    Super(Box<class::Super>),
 }
 impl fmt::Debug for Node {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -133,30 +158,81 @@ impl fmt::Debug for Node {
             Self::Literal(v) => f.debug_tuple("LiteralExpression").field(v).finish(),
             Self::Unary(u) => f.write_fmt(format_args!("{u:?}")),
             Self::Binary(b) => f.write_fmt(format_args!("{b:?}")),
+            Self::Logical(l) => f.write_fmt(format_args!("{l:?}")),
             Self::Grouping(e) => f.debug_tuple("GroupingExpression").field(e).finish(),
+            Self::Variable(v) => f.write_fmt(format_args!("{v:?}")),
+            Self::Assign(a) => f.write_fmt(format_args!("{a:?}")),
+            Self::Call(c) => f.write_fmt(format_args!("{c:?}")),
+            Self::Get(g) => f.write_fmt(format_args!("{g:?}")),
+            Self::Set(s) => f.write_fmt(format_args!("{s:?}")),
+            Self::This(t) => f.debug_tuple("This").field(t).finish(),
+            Self::Super(s) => f.write_fmt(format_args!("{s:?}")),
         }
     }
 }
 impl Expression for Node {
     type Output = literal::Value;
-    fn eval(&self) -> Option<Self::Output> {
-        println!("Evaluating ExpressionNode {self:?}");
+    fn eval(&self, environment: &Environment) -> Result<Self::Output, RuntimeError> {
+        log::trace!(target: trace::ENTER, "{}", self.label());
+        let result = self.evaluate(environment);
+        match &result {
+            Ok(value) => log::trace!(target: trace::EXIT, "{value:?}"),
+            Err(error) => log::trace!(target: trace::EXIT, "error: {}", error.message),
+        }
+        result
+    }
+}
+impl Node {
+    fn evaluate(&self, environment: &Environment) -> Result<literal::Value, RuntimeError> {
+        environment.step()?;
         match self {
-            Self::Literal(v) => Some(Self::Literal(v.clone())),
-            Self::Binary(b) => b.eval(),
-            Self::Unary(u) => u.eval(),
-            Self::Grouping(a) => a.eval().map(Node::Literal),
+            Self::Literal(v) => Ok(Self::Literal(v.clone())),
+            Self::Binary(b) => b.eval(environment).and_then(|node| {
+                // Concatenation is the only operator that allocates
+                if let Node::Literal(literal::Value::String(s)) = &node {
+                    environment.allocate(s.0.len())?;
+                }
+                Ok(node)
+            }),
+            Self::Unary(u) => u.eval(environment),
+            Self::Logical(l) => l.eval(environment).map(Node::Literal),
+            Self::Grouping(a) => a.eval(environment).map(Node::Literal),
+            Self::Variable(v) => v.eval(environment).map(Node::Literal),
+            Self::Assign(a) => a.eval(environment).map(Node::Literal),
+            Self::Call(c) => c.eval(environment).map(Node::Literal),
+            Self::Get(g) => g.eval(environment).map(Node::Literal),
+            Self::Set(s) => s.eval(environment).map(Node::Literal),
+            Self::This(t) => t.eval(environment).map(Node::Literal),
+            Self::Super(s) => s.eval(environment).map(Node::Literal),
         }
         .and_then(|node| match node {
-            Node::Literal(v) => {
-                println!("Result is {v:?}");
-                Some(v)
-            }
-            other => other.eval(),
+            Node::Literal(v) => Ok(v),
+            other => other.eval(environment),
         })
     }
-}
-impl Node {
+    /// The kind of node, with its operator, name or value if it has
+    /// one, but not its operands.
+    fn label(&self) -> String {
+        match self {
+            Self::Literal(v) => format!("Literal {v:?}"),
+            Self::Unary(_) => "Unary".into(),
+            Self::Binary(_) => "Binary".into(),
+            Self::Logical(l) => format!("Logical {:?}", l.operator()),
+            Self::Grouping(_) => "Grouping".into(),
+            Self::Variable(v) => format!("Variable {}", v.name()),
+            Self::Assign(a) => format!("Assign {}", a.variable().name()),
+            Self::Call(_) => "Call".into(),
+            Self::Get(g) => format!("Get {}", g.name()),
+            Self::Set(s) => format!("Set {}", s.name()),
+            Self::This(_) => "This".into(),
+            Self::Super(s) => format!("Super {}", s.method()),
+        }
+    }
+    /// Evaluates without any variables in scope, as the operators
+    /// and comparisons implemented directly on nodes do.
+    pub fn constant(&self) -> Result<literal::Value, RuntimeError> {
+        self.eval(&Environment::new())
+    }
     pub fn binary(b: impl Into<BinaryExpression<Node, Node, Node>>) -> Self {
         Self::Binary(Box::new(b.into()))
     }
@@ -177,7 +253,7 @@ impl Node {
     pub fn literal_value(value: impl Into<literal::Value>) -> Self {
         Self::Literal(value.into())
     }
-    pub fn number(value: impl Into<f32>) -> Self {
+    pub fn number(value: impl Into<f64>) -> Self {
         Self::literal_value(value.into())
     }
     pub fn negation(a: impl Into<Node>) -> Self {
@@ -208,14 +284,57 @@ mod expr_node_tests {
         #[test]
         fn numbers() {
             println!("\n===\nnumbers");
-            let e = Node::plus(Node::literal_value(1.0), Node::number(2.0)).eval();
-            assert_eq!(e, Some(3.0.into()));
+            let e =
+                Node::plus(Node::literal_value(1.0), Node::number(2.0)).eval(&Environment::new());
+            assert_eq!(e, Ok(3.0.into()));
         }
         #[test]
         fn exprs() {
             println!("\n===\nexprs");
-            let e = Node::plus(Node::negation(Node::plus(2.0, 3.0)), Node::plus(2.0, 3.0)).eval();
-            assert_eq!(e, Some(0.0.into()))
+            let e = Node::plus(Node::negation(Node::plus(2.0, 3.0)), Node::plus(2.0, 3.0))
+                .eval(&Environment::new());
+            assert_eq!(e, Ok(0.0.into()))
+        }
+    }
+    mod error_tests {
+        use super::*;
+        fn message(node: Node) -> Option<String> {
+            node.eval(&Environment::new()).err().map(|e| e.message)
+        }
+        #[test]
+        fn operands_must_be_numbers() {
+            let expected = Some("Operands must be numbers.".into());
+            assert_eq!(message(Node::minus(true, 1.0)), expected);
+            assert_eq!(message(Node::times(1.0, "b")), expected);
+            assert_eq!(message(Node::divides(false, true)), expected);
+        }
+        #[test]
+        fn plus_needs_numbers_or_strings() {
+            assert_eq!(
+                message(Node::plus("a", 1.0)),
+                Some("Operands must be two numbers or two strings.".into())
+            );
+        }
+        #[test]
+        fn operand_must_be_a_number() {
+            assert_eq!(
+                message(Node::negation("x")),
+                Some("Operand must be a number.".into())
+            );
+        }
+        #[test]
+        fn innermost_location_wins() {
+            let location = |offset| token::Location {
+                offset,
+                line: 1,
+                column: offset + 1,
+            };
+            let inner = token::Span::new(location(4), location(5));
+            let outer = token::Span::new(location(0), location(1));
+            let error = RuntimeError::new("Operand must be a number.")
+                .at(inner)
+                .at(outer);
+            assert_eq!(error.span, Some(inner));
         }
     }
 }
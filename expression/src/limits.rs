@@ -0,0 +1,222 @@
+use crate::RuntimeError;
+use core::fmt;
+use std::{
+    cell::Cell,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+/// How much native stack evaluation may use unless configured otherwise:
+/// half the stack Rust gives a spawned thread by default.
+pub const DEFAULT_NATIVE_STACK: usize = 1024 * 1024;
+/// How many steps pass between checks of the clock.
+const CLOCK_INTERVAL: u64 = 256;
+
+/// One of the [`Limits`] on running a program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    Steps,
+    CallDepth,
+    /// Too many bytes allocated over the run, whether or not they have
+    /// since been freed
+    Allocation,
+    Time,
+}
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Steps => "Step limit exceeded.",
+            // As Lox reports running out of call frames
+            Self::CallDepth => "Stack overflow.",
+            Self::Allocation => "Allocation limit exceeded.",
+            Self::Time => "Time limit exceeded.",
+        })
+    }
+}
+/// Bounds on the resources a program may use, `None` being unbounded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// Expressions evaluated plus statements executed
+    pub steps: Option<u64>,
+    pub call_depth: Option<usize>,
+    /// Bytes of native stack evaluation may use, whatever the call depth.
+    /// Every call and nested expression or statement recurses through the
+    /// evaluator, so without this deep recursion would overflow the stack
+    /// and abort the process. It must fit in the stack of the thread
+    /// running the program.
+    pub native_stack: usize,
+    /// Bytes allocated for strings, instances, fields and call scopes
+    /// over the whole run, a budget rather than a bound on memory in
+    /// use: nothing is counted as freed, as there is no collector to say
+    /// when memory is reclaimed.
+    pub allocation: Option<usize>,
+    pub time: Option<Duration>,
+}
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            steps: None,
+            call_depth: None,
+            native_stack: DEFAULT_NATIVE_STACK,
+            allocation: None,
+            time: None,
+        }
+    }
+}
+/// What a program has used so far against its limits, shared by every
+/// scope of the program.
+#[derive(Debug)]
+pub(crate) struct Budget {
+    limits: Cell<Limits>,
+    steps: Cell<u64>,
+    depth: Cell<usize>,
+    /// The address of the native stack at the first step of the run, or
+    /// zero before it
+    stack_base: Cell<usize>,
+    allocated: Cell<usize>,
+    started: Cell<Instant>,
+}
+impl Default for Budget {
+    fn default() -> Self {
+        Self {
+            limits: Cell::new(Limits::default()),
+            steps: Cell::new(0),
+            depth: Cell::new(0),
+            stack_base: Cell::new(0),
+            allocated: Cell::new(0),
+            started: Cell::new(Instant::now()),
+        }
+    }
+}
+impl Budget {
+    pub(crate) fn limits(&self) -> Limits {
+        self.limits.get()
+    }
+    pub(crate) fn set_limits(&self, limits: Limits) {
+        self.limits.set(limits);
+    }
+    /// Starts counting afresh, e.g. for a new run.
+    pub(crate) fn reset(&self) {
+        self.steps.set(0);
+        self.stack_base.set(0);
+        self.allocated.set(0);
+        self.started.set(Instant::now());
+    }
+    pub(crate) fn step(&self) -> Result<(), RuntimeError> {
+        let here = std::ptr::addr_of!(self) as usize;
+        if self.stack_base.get() == 0 {
+            self.stack_base.set(here);
+        }
+        let limits = self.limits.get();
+        if self.stack_base.get().abs_diff(here) > limits.native_stack {
+            return Err(RuntimeError::limit_exceeded(Limit::CallDepth));
+        }
+        let steps = self.steps.get() + 1;
+        self.steps.set(steps);
+        if limits.steps.is_some_and(|max| steps > max) {
+            return Err(RuntimeError::limit_exceeded(Limit::Steps));
+        }
+        if steps.is_multiple_of(CLOCK_INTERVAL)
+            && limits
+                .time
+                .is_some_and(|max| self.started.get().elapsed() > max)
+        {
+            return Err(RuntimeError::limit_exceeded(Limit::Time));
+        }
+        Ok(())
+    }
+    pub(crate) fn allocate(&self, bytes: usize) -> Result<(), RuntimeError> {
+        let allocated = self.allocated.get() + bytes;
+        self.allocated.set(allocated);
+        match self.limits.get().allocation {
+            Some(max) if allocated > max => Err(RuntimeError::limit_exceeded(Limit::Allocation)),
+            _ => Ok(()),
+        }
+    }
+    /// Enters a call, which lasts until the returned guard is dropped.
+    pub(crate) fn enter_call(self: &Rc<Self>) -> Result<CallGuard, RuntimeError> {
+        let depth = self.depth.get() + 1;
+        if self.limits.get().call_depth.is_some_and(|max| depth > max) {
+            return Err(RuntimeError::limit_exceeded(Limit::CallDepth));
+        }
+        self.depth.set(depth);
+        Ok(CallGuard(self.clone()))
+    }
+}
+/// Leaves the call it was returned for when dropped, however the call
+/// finishes.
+pub(crate) struct CallGuard(Rc<Budget>);
+impl Drop for CallGuard {
+    fn drop(&mut self) {
+        self.0.depth.set(self.0.depth.get() - 1);
+    }
+}
+
+#[cfg(test)]
+mod limits_tests {
+    use super::*;
+    fn budget(limits: Limits) -> Rc<Budget> {
+        let budget = Rc::new(Budget::default());
+        budget.set_limits(limits);
+        budget
+    }
+    #[test]
+    fn steps_run_out() {
+        let budget = budget(Limits {
+            steps: Some(2),
+            ..Limits::default()
+        });
+        assert!(budget.step().is_ok());
+        assert!(budget.step().is_ok());
+        assert_eq!(budget.step().unwrap_err().limit, Some(Limit::Steps));
+        budget.reset();
+        assert!(budget.step().is_ok());
+    }
+    #[test]
+    fn calls_are_counted_while_active() {
+        let budget = budget(Limits {
+            call_depth: Some(1),
+            ..Limits::default()
+        });
+        let outer = budget.enter_call().unwrap();
+        let error = budget.enter_call().err().unwrap();
+        assert_eq!(error.limit, Some(Limit::CallDepth));
+        assert_eq!(error.message, "Stack overflow.");
+        drop(outer);
+        assert!(budget.enter_call().is_ok());
+    }
+    #[test]
+    fn native_stack_runs_out() {
+        fn nest(budget: &Rc<Budget>, levels: usize) -> Result<(), RuntimeError> {
+            budget.step()?;
+            match levels {
+                0 => Ok(()),
+                _ => nest(budget, levels - 1),
+            }
+        }
+        let budget = budget(Limits {
+            native_stack: 0,
+            ..Limits::default()
+        });
+        assert!(nest(&budget, 0).is_ok());
+        assert_eq!(nest(&budget, 1).unwrap_err().limit, Some(Limit::CallDepth));
+        // Each run measures from wherever it starts
+        budget.reset();
+        assert!(nest(&budget, 0).is_ok());
+    }
+    #[test]
+    fn allocation_and_time_run_out() {
+        let budget = budget(Limits {
+            allocation: Some(10),
+            time: Some(Duration::ZERO),
+            ..Limits::default()
+        });
+        assert!(budget.allocate(10).is_ok());
+        assert_eq!(
+            budget.allocate(1).unwrap_err().limit,
+            Some(Limit::Allocation)
+        );
+        let error = (0..CLOCK_INTERVAL).try_for_each(|_| budget.step());
+        assert_eq!(error.unwrap_err().limit, Some(Limit::Time));
+    }
+}
@@ -1,8 +1,16 @@
-use crate::{Expression, Node};
+use crate::{
+    class::{Class, Instance},
+    function::Function,
+    native::NativeFunction,
+    trace, Node, RuntimeError,
+};
+use convert::MaybeFrom;
+use core::fmt;
 use std::ops::{Add, Div, Mul, Neg, Not, Sub};
-use token::StringValue;
-impl From<f32> for Node {
-    fn from(value: f32) -> Self {
+use std::rc::Rc;
+use token::{KeywordType, LiteralValue, StringValue, Token, TokenType};
+impl From<f64> for Node {
+    fn from(value: f64) -> Self {
         Value::from(value).into()
     }
 }
@@ -22,11 +30,16 @@ impl From<&str> for Node {
         Value::from(value).into()
     }
 }
-#[derive(Debug, PartialEq, PartialOrd, Clone)]
+#[derive(Debug, Clone)]
 pub enum Value {
-    Number(f32),
+    Number(f64),
     String(StringValue),
     Boolean(bool),
+    Nil,
+    Function(Rc<Function>),
+    Native(Rc<NativeFunction>),
+    Class(Rc<Class>),
+    Instance(Rc<Instance>),
 }
 impl Value {
     #[inline]
@@ -36,14 +49,40 @@ impl Value {
             _ => None,
         }
     }
+    /// Only `nil` and `false` are falsey, every other value is truthy.
+    #[inline]
+    pub fn is_truthy(&self) -> bool {
+        !matches!(self, Self::Nil | Self::Boolean(false))
+    }
+    /// Both operands as numbers, for operators only defined on numbers.
+    pub fn numbers(self, rhs: Self) -> Result<(f64, f64), RuntimeError> {
+        match (self, rhs) {
+            (Value::Number(n1), Value::Number(n2)) => Ok((n1, n2)),
+            _ => Err(RuntimeError::new("Operands must be numbers.")),
+        }
+    }
+}
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Number(n) => f.write_fmt(format_args!("{n}")),
+            Self::String(s) => f.write_str(s),
+            Self::Boolean(b) => f.write_fmt(format_args!("{b}")),
+            Self::Nil => f.write_str("nil"),
+            Self::Function(function) => f.write_fmt(format_args!("{function}")),
+            Self::Native(native) => f.write_fmt(format_args!("{native}")),
+            Self::Class(class) => f.write_fmt(format_args!("{class}")),
+            Self::Instance(instance) => f.write_fmt(format_args!("{instance}")),
+        }
+    }
 }
 impl From<Value> for Node {
     fn from(value: Value) -> Self {
         Node::Literal(value)
     }
 }
-impl From<f32> for Value {
-    fn from(value: f32) -> Self {
+impl From<f64> for Value {
+    fn from(value: f64) -> Self {
         Value::Number(value)
     }
 }
@@ -67,101 +106,274 @@ impl From<bool> for Value {
         Value::Boolean(value)
     }
 }
+/// Extracts the Rust value a native function wants from an argument,
+/// `None` if the argument has a different type.
+macro_rules! extract {
+    ($($t:ty => $pattern:pat => $value:expr;)*) => {
+        $(
+            impl MaybeFrom<&Value> for $t {
+                fn maybe_from(value: &Value) -> Option<Self> {
+                    match value {
+                        $pattern => Some($value),
+                        _ => None,
+                    }
+                }
+            }
+            impl MaybeFrom<Value> for $t {
+                fn maybe_from(value: Value) -> Option<Self> {
+                    <$t>::maybe_from(&value)
+                }
+            }
+        )*
+    };
+}
+extract! {
+    f64 => Value::Number(n) => *n;
+    String => Value::String(s) => s.0.clone();
+    bool => Value::Boolean(b) => *b;
+}
+/// Identifiers name values rather than being values themselves.
+impl MaybeFrom<LiteralValue> for Value {
+    fn maybe_from(value: LiteralValue) -> Option<Self> {
+        match value {
+            LiteralValue::Number(n) => Some(Value::Number(n)),
+            LiteralValue::String(s) => Some(Value::String(s)),
+            LiteralValue::Identifier(_) => None,
+        }
+    }
+}
+/// The value of a literal token, `None` for any other token.
+impl MaybeFrom<&Token> for Value {
+    fn maybe_from(token: &Token) -> Option<Self> {
+        match token.r#type {
+            TokenType::Keyword(KeywordType::Nil) => Some(Value::Nil),
+            TokenType::Keyword(KeywordType::True) => Some(Value::Boolean(true)),
+            TokenType::Keyword(KeywordType::False) => Some(Value::Boolean(false)),
+            TokenType::Number | TokenType::String => {
+                Option::<LiteralValue>::from(&token.literal).and_then(Value::maybe_from)
+            }
+            _ => None,
+        }
+    }
+}
+impl MaybeFrom<LiteralValue> for Node {
+    fn maybe_from(value: LiteralValue) -> Option<Self> {
+        Value::maybe_from(value).map(Node::Literal)
+    }
+}
+impl MaybeFrom<&Token> for Node {
+    fn maybe_from(token: &Token) -> Option<Self> {
+        Value::maybe_from(token).map(Node::Literal)
+    }
+}
 impl Value {
-    pub fn number_expression(value: impl Into<f32>) -> Self {
+    pub fn number_expression(value: impl Into<f64>) -> Self {
         Self::Number(value.into())
     }
 }
+/// Values of different types are never equal, and functions, classes
+/// and instances are only equal to themselves.
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Number(n1), Self::Number(n2)) => n1.eq(n2),
+            (Self::String(s1), Self::String(s2)) => s1.eq(s2),
+            (Self::Boolean(b1), Self::Boolean(b2)) => b1.eq(b2),
+            (Self::Nil, Self::Nil) => true,
+            (Self::Function(f1), Self::Function(f2)) => Rc::ptr_eq(f1, f2),
+            (Self::Native(n1), Self::Native(n2)) => Rc::ptr_eq(n1, n2),
+            (Self::Class(c1), Self::Class(c2)) => Rc::ptr_eq(c1, c2),
+            (Self::Instance(i1), Self::Instance(i2)) => Rc::ptr_eq(i1, i2),
+            _ => false,
+        }
+    }
+}
 impl PartialEq<Node> for Value {
     fn eq(&self, other: &Node) -> bool {
-        other.eval().is_some_and(|other| self.eq(&other))
+        other.constant().is_ok_and(|other| self.eq(&other))
     }
 }
 impl Add for Value {
-    type Output = Option<Value>;
+    type Output = Result<Value, RuntimeError>;
     fn add(self, rhs: Self) -> Self::Output {
-        println!("Adding literal {self:?} to literal {rhs:?}");
+        log::trace!(target: trace::ARITHMETIC, "Adding literal {self:?} to literal {rhs:?}");
         match (self, rhs) {
-            (Value::Number(n1), Value::Number(n2)) => Some(Value::Number(n1.add(n2))),
-            _ => None,
+            (Value::Number(n1), Value::Number(n2)) => Ok(Value::Number(n1.add(n2))),
+            (Value::String(StringValue(s1)), Value::String(StringValue(s2))) => {
+                Ok(Value::from(s1 + &s2))
+            }
+            _ => Err(RuntimeError::new(
+                "Operands must be two numbers or two strings.",
+            )),
+        }
+    }
+}
+impl Value {
+    /// `+` in the stringifying dialect: a number or bool added to a string
+    /// is converted with its `Display` form before concatenating.
+    pub fn concatenate(self, rhs: Self) -> Result<Value, RuntimeError> {
+        match (self, rhs) {
+            (Value::String(StringValue(s)), v @ (Value::Number(_) | Value::Boolean(_))) => {
+                Ok(Value::from(format!("{s}{v}")))
+            }
+            (v @ (Value::Number(_) | Value::Boolean(_)), Value::String(StringValue(s))) => {
+                Ok(Value::from(format!("{v}{s}")))
+            }
+            (a, b) => a.add(b),
         }
     }
 }
 impl Add<Node> for Value {
-    type Output = Option<Node>;
+    type Output = Result<Node, RuntimeError>;
     fn add(self, rhs: Node) -> Self::Output {
-        println!("Adding literal {self:?} to expression {rhs:?}");
-        rhs.eval().and_then(|rhs| self.add(rhs)).map(Node::Literal)
+        log::trace!(target: trace::ARITHMETIC, "Adding literal {self:?} to expression {rhs:?}");
+        rhs.constant()
+            .and_then(|rhs| self.add(rhs))
+            .map(Node::Literal)
     }
 }
 impl Sub for Value {
-    type Output = Option<Value>;
+    type Output = Result<Value, RuntimeError>;
     fn sub(self, rhs: Self) -> Self::Output {
-        println!("subing literal {self:?} to literal {rhs:?}");
+        log::trace!(target: trace::ARITHMETIC, "Subtracting literal {rhs:?} from literal {self:?}");
         match (self, rhs) {
-            (Value::Number(n1), Value::Number(n2)) => Some(Value::Number(n1.sub(n2))),
-            _ => None,
+            (Value::Number(n1), Value::Number(n2)) => Ok(Value::Number(n1.sub(n2))),
+            _ => Err(RuntimeError::new("Operands must be numbers.")),
         }
     }
 }
 impl Sub<Node> for Value {
-    type Output = Option<Node>;
+    type Output = Result<Node, RuntimeError>;
     fn sub(self, rhs: Node) -> Self::Output {
-        println!("Subtracting expression {rhs:?} from literal {self:?}");
-        rhs.eval().and_then(|rhs| self.sub(rhs)).map(Node::Literal)
+        log::trace!(target: trace::ARITHMETIC, "Subtracting expression {rhs:?} from literal {self:?}");
+        rhs.constant()
+            .and_then(|rhs| self.sub(rhs))
+            .map(Node::Literal)
     }
 }
 impl Div for Value {
-    type Output = Option<Value>;
+    type Output = Result<Value, RuntimeError>;
     fn div(self, rhs: Self) -> Self::Output {
-        println!("diving literal {self:?} to literal {rhs:?}");
+        log::trace!(target: trace::ARITHMETIC, "Dividing literal {self:?} by literal {rhs:?}");
         match (self, rhs) {
-            (Value::Number(n1), Value::Number(n2)) => Some(Value::Number(n1.div(n2))),
-            _ => None,
+            (Value::Number(n1), Value::Number(n2)) => Ok(Value::Number(n1.div(n2))),
+            _ => Err(RuntimeError::new("Operands must be numbers.")),
         }
     }
 }
 impl Div<Node> for Value {
-    type Output = Option<Node>;
+    type Output = Result<Node, RuntimeError>;
     fn div(self, rhs: Node) -> Self::Output {
-        println!("dividing literal {self:?} by expression {rhs:?}");
-        rhs.eval().and_then(|rhs| self.div(rhs)).map(Node::Literal)
+        log::trace!(target: trace::ARITHMETIC, "dividing literal {self:?} by expression {rhs:?}");
+        rhs.constant()
+            .and_then(|rhs| self.div(rhs))
+            .map(Node::Literal)
     }
 }
 impl Mul for Value {
-    type Output = Option<Value>;
+    type Output = Result<Value, RuntimeError>;
     fn mul(self, rhs: Self) -> Self::Output {
-        println!("muling literal {self:?} to literal {rhs:?}");
+        log::trace!(target: trace::ARITHMETIC, "Multiplying literal {self:?} by literal {rhs:?}");
         match (self, rhs) {
-            (Value::Number(n1), Value::Number(n2)) => Some(Value::Number(n1.mul(n2))),
-            _ => None,
+            (Value::Number(n1), Value::Number(n2)) => Ok(Value::Number(n1.mul(n2))),
+            _ => Err(RuntimeError::new("Operands must be numbers.")),
         }
     }
 }
 impl Mul<Node> for Value {
-    type Output = Option<Node>;
+    type Output = Result<Node, RuntimeError>;
     fn mul(self, rhs: Node) -> Self::Output {
-        println!("Multiplying literal {self:?} by expression {rhs:?}");
-        rhs.eval().and_then(|rhs| self.mul(rhs)).map(Node::Literal)
+        log::trace!(target: trace::ARITHMETIC, "Multiplying literal {self:?} by expression {rhs:?}");
+        rhs.constant()
+            .and_then(|rhs| self.mul(rhs))
+            .map(Node::Literal)
     }
 }
 impl Neg for Value {
-    type Output = Option<f32>;
+    type Output = Result<f64, RuntimeError>;
     fn neg(self) -> Self::Output {
-        println!("Negating literal-expression {self:?}");
+        log::trace!(target: trace::ARITHMETIC, "Negating literal {self:?}");
         match self {
-            Self::Number(f) => Some(f.neg()),
-            _ => None,
+            Self::Number(f) => Ok(f.neg()),
+            _ => Err(RuntimeError::new("Operand must be a number.")),
         }
     }
 }
 impl Not for Value {
     type Output = bool;
     fn not(self) -> Self::Output {
-        match self {
-            Self::String(StringValue(s)) => s.is_empty(),
-            Self::Number(f) => !(f != 0.),
-            Self::Boolean(b) => b.not(),
+        !self.is_truthy()
+    }
+}
+#[cfg(test)]
+mod literal_tests {
+    use super::*;
+    use crate::{Environment, Expression};
+    use convert::MaybeInto;
+    use token::Span;
+    fn token(r#type: TokenType, lexeme: &str, literal: Option<LiteralValue>) -> Token {
+        Token::new(r#type, lexeme, literal, Span::default())
+    }
+    #[test]
+    fn numbers_are_double_precision() {
+        let sum = (Value::from(0.1) + Value::from(0.2)).unwrap();
+        assert_eq!(sum.to_string(), "0.30000000000000004");
+        assert_eq!(Value::from(16777217.0).to_string(), "16777217");
+    }
+    #[test]
+    fn from_tokens() {
+        let cases = [
+            (
+                token(TokenType::Keyword(KeywordType::Nil), "nil", None),
+                Value::Nil,
+            ),
+            (
+                token(TokenType::Keyword(KeywordType::True), "true", None),
+                Value::Boolean(true),
+            ),
+            (
+                token(TokenType::Keyword(KeywordType::False), "false", None),
+                Value::Boolean(false),
+            ),
+            (
+                token(TokenType::Number, "2.5", Some(LiteralValue::Number(2.5))),
+                Value::Number(2.5),
+            ),
+            (
+                token(
+                    TokenType::String,
+                    "\"lox\"",
+                    Some(LiteralValue::String("lox".into())),
+                ),
+                "lox".into(),
+            ),
+        ];
+        for (token, expected) in cases {
+            let node: Option<Node> = (&token).maybe_into();
+            assert_eq!(
+                node.map(|node| node.eval(&Environment::new())),
+                Some(Ok(expected))
+            );
         }
     }
+    #[test]
+    fn not_from_other_tokens() {
+        let identifier = token(
+            TokenType::Identifier,
+            "a",
+            Some(LiteralValue::Identifier(token::StringIdentifier(
+                "a".into(),
+            ))),
+        );
+        assert!(Value::maybe_from(&identifier).is_none());
+        assert!(Value::maybe_from(&token(TokenType::Plus, "+", None)).is_none());
+    }
+    #[test]
+    fn display() {
+        assert_eq!(Value::Nil.to_string(), "nil");
+        assert_eq!(Value::Boolean(true).to_string(), "true");
+        assert_eq!(Value::Number(3.0).to_string(), "3");
+        assert_eq!(Value::Number(-0.5).to_string(), "-0.5");
+        assert_eq!(Value::from("lox").to_string(), "lox");
+    }
 }
@@ -0,0 +1,91 @@
+use crate::{literal::Value, Environment, Expression, Node, RuntimeError};
+
+/// `and` and `or`, which unlike the binary operators only evaluate
+/// their right operand when the left one doesn't decide the result.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogicalOperator {
+    And,
+    Or,
+}
+impl LogicalOperator {
+    pub fn express(self, a: Node, b: Node) -> LogicalExpression {
+        LogicalExpression {
+            operand_a: a,
+            operand_b: b,
+            operator: self,
+        }
+    }
+}
+#[derive(Debug)]
+pub struct LogicalExpression {
+    operand_a: Node,
+    operand_b: Node,
+    operator: LogicalOperator,
+}
+impl LogicalExpression {
+    #[inline]
+    pub fn operands(&self) -> (&Node, &Node) {
+        (&self.operand_a, &self.operand_b)
+    }
+    #[inline]
+    pub fn operator(&self) -> &LogicalOperator {
+        &self.operator
+    }
+}
+impl From<LogicalExpression> for Node {
+    fn from(value: LogicalExpression) -> Self {
+        Node::Logical(Box::new(value))
+    }
+}
+impl Expression for LogicalExpression {
+    type Output = Value;
+    /// Evaluates to the deciding operand itself rather than a bool.
+    fn eval(&self, environment: &Environment) -> Result<Self::Output, RuntimeError> {
+        let a = self.operand_a.eval(environment)?;
+        match (self.operator, a.is_truthy()) {
+            (LogicalOperator::Or, true) | (LogicalOperator::And, false) => Ok(a),
+            _ => self.operand_b.eval(environment),
+        }
+    }
+}
+
+#[cfg(test)]
+mod logical_tests {
+    use super::*;
+    use crate::Variable;
+    use token::Span;
+    fn eval(
+        operator: LogicalOperator,
+        a: impl Into<Node>,
+        b: impl Into<Node>,
+    ) -> Result<Value, RuntimeError> {
+        operator
+            .express(a.into(), b.into())
+            .eval(&Environment::new())
+    }
+    #[test]
+    fn returns_the_deciding_operand() {
+        assert_eq!(eval(LogicalOperator::Or, "hi", 2.0), Ok("hi".into()));
+        assert_eq!(
+            eval(LogicalOperator::Or, Value::Nil, "yes"),
+            Ok("yes".into())
+        );
+        assert_eq!(eval(LogicalOperator::And, Value::Nil, 2.0), Ok(Value::Nil));
+        assert_eq!(eval(LogicalOperator::And, 1.0, 2.0), Ok(2.0.into()));
+        assert_eq!(eval(LogicalOperator::And, false, "no"), Ok(false.into()));
+    }
+    #[test]
+    fn short_circuits() {
+        // The undefined variable would raise an error if evaluated
+        let undefined = || Variable::new("undefined", Span::default());
+        assert_eq!(
+            eval(LogicalOperator::Or, true, undefined()),
+            Ok(true.into())
+        );
+        assert_eq!(
+            eval(LogicalOperator::And, false, undefined()),
+            Ok(false.into())
+        );
+        assert!(eval(LogicalOperator::And, true, undefined()).is_err());
+    }
+}
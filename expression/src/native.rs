@@ -0,0 +1,145 @@
+use crate::{function::Callable, Environment, RuntimeError, Value};
+use convert::{MaybeFrom, MaybeInto};
+use core::fmt;
+use std::{rc::Rc, time::Instant};
+
+/// The host side of a native function.
+pub type NativeFn = dyn Fn(&[Value]) -> Result<Value, RuntimeError>;
+
+/// A function implemented by the host rather than in Lox.
+pub struct NativeFunction {
+    name: String,
+    arity: usize,
+    function: Box<NativeFn>,
+}
+impl NativeFunction {
+    pub fn new(
+        name: impl ToString,
+        arity: usize,
+        function: impl Fn(&[Value]) -> Result<Value, RuntimeError> + 'static,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            arity,
+            function: Box::new(function),
+        }
+    }
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+impl fmt::Debug for NativeFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NativeFunction")
+            .field("name", &self.name)
+            .field("arity", &self.arity)
+            .finish_non_exhaustive()
+    }
+}
+impl fmt::Display for NativeFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("<native fn>")
+    }
+}
+impl Callable for NativeFunction {
+    fn arity(&self) -> usize {
+        self.arity
+    }
+    fn call(&self, arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+        (self.function)(&arguments)
+    }
+}
+impl From<NativeFunction> for Value {
+    fn from(value: NativeFunction) -> Self {
+        Value::Native(Rc::new(value))
+    }
+}
+/// The argument at `index` as a `T`, or an error saying it should have
+/// been `expected`, e.g. "a number".
+pub fn argument<'a, T>(
+    arguments: &'a [Value],
+    index: usize,
+    expected: &str,
+) -> Result<T, RuntimeError>
+where
+    T: MaybeFrom<&'a Value>,
+{
+    arguments
+        .get(index)
+        .and_then(MaybeInto::maybe_into)
+        .ok_or_else(|| RuntimeError::new(format!("Argument {} must be {expected}.", index + 1)))
+}
+/// Defines the natives every program can rely on:
+///
+/// - `clock()`, the seconds elapsed since they were defined
+pub fn define_builtins(environment: &Environment) {
+    let start = Instant::now();
+    environment.define_native("clock", 0, move |_| {
+        Ok(Value::Number(start.elapsed().as_secs_f64()))
+    });
+}
+
+#[cfg(test)]
+mod native_tests {
+    use super::*;
+    use crate::{function::Call, Expression, Node, Variable};
+    use token::Span;
+    fn call(
+        environment: &Environment,
+        name: &str,
+        arguments: Vec<Node>,
+    ) -> Result<Value, RuntimeError> {
+        let callee = Variable::new(name, Span::default()).into();
+        Call::new(callee, arguments, Span::default()).eval(environment)
+    }
+    #[test]
+    fn natives_are_called_with_their_arguments() {
+        let environment = Environment::new();
+        environment.define_native("hypot", 2, |arguments| {
+            let a: f64 = argument(arguments, 0, "a number")?;
+            let b: f64 = argument(arguments, 1, "a number")?;
+            Ok(Value::Number(a.hypot(b)))
+        });
+        environment.define_native("shout", 1, |arguments| {
+            let s: String = argument(arguments, 0, "a string")?;
+            Ok(s.to_uppercase().into())
+        });
+        assert_eq!(
+            call(&environment, "hypot", vec![3.0.into(), 4.0.into()]),
+            Ok(5.0.into())
+        );
+        assert_eq!(
+            call(&environment, "shout", vec!["lox".into()]),
+            Ok("LOX".into())
+        );
+        assert_eq!(
+            call(&environment, "shout", vec![1.0.into()]).map_err(|e| e.message),
+            Err("Argument 1 must be a string.".into())
+        );
+    }
+    #[test]
+    fn arity_is_checked() {
+        let environment = Environment::new();
+        define_builtins(&environment);
+        assert_eq!(
+            call(&environment, "clock", vec![1.0.into()]).map_err(|e| e.message),
+            Err("Expected 0 arguments but got 1.".into())
+        );
+    }
+    #[test]
+    fn clock_counts_up() {
+        let environment = Environment::new();
+        define_builtins(&environment);
+        let Ok(Value::Number(first)) = call(&environment, "clock", vec![]) else {
+            panic!("clock should return a number");
+        };
+        let Ok(Value::Number(second)) = call(&environment, "clock", vec![]) else {
+            panic!("clock should return a number");
+        };
+        assert!(first >= 0.0 && second >= first);
+        let clock = environment.get("clock").unwrap();
+        assert_eq!(clock.to_string(), "<native fn>");
+        assert_eq!(clock, clock.clone());
+    }
+}
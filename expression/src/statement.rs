@@ -0,0 +1,232 @@
+use crate::{
+    class::ClassDeclaration,
+    function::{Function, FunctionDeclaration},
+    Environment, Expression, Identifier, Node, RuntimeError, Value,
+};
+use std::rc::Rc;
+use token::Span;
+
+#[derive(Debug)]
+pub enum Statement {
+    Expression(Node),
+    Print(Node),
+    Var {
+        name: Identifier,
+        initializer: Option<Node>,
+    },
+    Block(Vec<Statement>),
+    If {
+        condition: Node,
+        then_branch: Box<Statement>,
+        else_branch: Option<Box<Statement>>,
+    },
+    While {
+        condition: Node,
+        body: Box<Statement>,
+    },
+    Function(Rc<FunctionDeclaration>),
+    Class(Rc<ClassDeclaration>),
+    Return {
+        value: Option<Node>,
+        /// Where the `return` keyword appears in the source
+        span: Span,
+    },
+}
+/// How a statement finished: either on to the next statement, or
+/// returning from the enclosing function.
+#[derive(Debug, PartialEq)]
+pub(crate) enum Completion {
+    Normal,
+    Return(Value),
+}
+impl Statement {
+    pub fn execute(&self, environment: &Environment) -> Result<(), RuntimeError> {
+        self.run(environment).map(drop)
+    }
+    pub(crate) fn run(&self, environment: &Environment) -> Result<Completion, RuntimeError> {
+        environment.step()?;
+        match self {
+            Self::Expression(expression) => {
+                expression.eval(environment).map(|_| Completion::Normal)
+            }
+            Self::Print(expression) => {
+                let value = expression.eval(environment)?;
+                writeln!(environment.stdout(), "{value}").map_err(RuntimeError::new)?;
+                Ok(Completion::Normal)
+            }
+            Self::Var { name, initializer } => {
+                let value = match initializer {
+                    Some(initializer) => initializer.eval(environment)?,
+                    None => Value::Nil,
+                };
+                environment.define(name, value);
+                Ok(Completion::Normal)
+            }
+            Self::Block(statements) => {
+                let environment = environment.enclose();
+                for statement in statements {
+                    if let Completion::Return(value) = statement.run(&environment)? {
+                        return Ok(Completion::Return(value));
+                    }
+                }
+                Ok(Completion::Normal)
+            }
+            Self::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                if condition.eval(environment)?.is_truthy() {
+                    then_branch.run(environment)
+                } else if let Some(else_branch) = else_branch {
+                    else_branch.run(environment)
+                } else {
+                    Ok(Completion::Normal)
+                }
+            }
+            Self::While { condition, body } => {
+                while condition.eval(environment)?.is_truthy() {
+                    if let Completion::Return(value) = body.run(environment)? {
+                        return Ok(Completion::Return(value));
+                    }
+                }
+                Ok(Completion::Normal)
+            }
+            Self::Function(declaration) => {
+                let function = Function::new(declaration.clone(), environment.clone());
+                environment.define(&declaration.name, Value::Function(Rc::new(function)));
+                Ok(Completion::Normal)
+            }
+            Self::Class(declaration) => declaration
+                .declare(environment)
+                .map(|()| Completion::Normal),
+            Self::Return { value, .. } => {
+                let value = match value {
+                    Some(value) => value.eval(environment)?,
+                    None => Value::Nil,
+                };
+                Ok(Completion::Return(value))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod statement_tests {
+    use super::*;
+    use crate::binary::{BinaryOperator, OrderingOperatorNode};
+    #[test]
+    fn var_declaration() {
+        let environment = Environment::new();
+        Statement::Var {
+            name: "a".into(),
+            initializer: Some(Node::plus(1.0, 2.0)),
+        }
+        .execute(&environment)
+        .unwrap();
+        Statement::Var {
+            name: "b".into(),
+            initializer: None,
+        }
+        .execute(&environment)
+        .unwrap();
+        assert!(environment.is_defined("a"));
+        assert!(environment.is_defined("b"));
+    }
+    #[test]
+    fn print_writes_to_stdout() {
+        let environment = Environment::new();
+        let (stdout, captured) = output::Output::capture();
+        environment.set_stdout(stdout);
+        let block = Statement::Block(vec![
+            Statement::Print(Node::plus(1.0, 2.0)),
+            Statement::Print("lox".into()),
+        ]);
+        block.execute(&environment).unwrap();
+        assert_eq!(captured.contents(), "3\nlox\n");
+    }
+    #[test]
+    fn failing_initializer() {
+        let environment = Environment::new();
+        let result = Statement::Var {
+            name: "a".into(),
+            initializer: Some(Node::negation("lox")),
+        }
+        .execute(&environment);
+        assert_eq!(
+            result.map_err(|e| e.message),
+            Err("Operand must be a number.".into())
+        );
+        assert!(!environment.is_defined("a"));
+    }
+    #[test]
+    fn if_branches() {
+        let environment = Environment::new();
+        let declare = |name: &str| {
+            Box::new(Statement::Var {
+                name: name.into(),
+                initializer: None,
+            })
+        };
+        Statement::If {
+            condition: false.into(),
+            then_branch: declare("then"),
+            else_branch: Some(declare("else")),
+        }
+        .execute(&environment)
+        .unwrap();
+        assert!(!environment.is_defined("then"));
+        assert!(environment.is_defined("else"));
+    }
+    #[test]
+    fn while_false_never_runs() {
+        let environment = Environment::new();
+        Statement::While {
+            condition: false.into(),
+            body: Box::new(Statement::Var {
+                name: "body".into(),
+                initializer: None,
+            }),
+        }
+        .execute(&environment)
+        .unwrap();
+        assert!(!environment.is_defined("body"));
+    }
+    #[test]
+    fn blocks_scope_their_declarations() {
+        let environment = Environment::new();
+        environment.define("a", "outer".into());
+        let variable = |name| Node::from(crate::Variable::new(name, token::Span::default()));
+        Statement::Block(vec![
+            Statement::Var {
+                name: "a".into(),
+                initializer: Some("inner".into()),
+            },
+            Statement::Var {
+                name: "b".into(),
+                initializer: Some(variable("a")),
+            },
+        ])
+        .execute(&environment)
+        .unwrap();
+        assert_eq!(environment.get("a"), Ok("outer".into()));
+        assert!(!environment.is_defined("b"));
+    }
+    #[test]
+    fn assignment_in_a_loop() {
+        let environment = Environment::new();
+        environment.define("i", 0.0.into());
+        let variable = || crate::Variable::new("i", token::Span::default());
+        Statement::While {
+            condition: OrderingOperatorNode::Lt { equal: false }
+                .express(variable().into(), 3.0.into())
+                .into(),
+            body: Box::new(Statement::Expression(
+                crate::Assignment::new(variable(), Node::plus(variable(), 1.0)).into(),
+            )),
+        }
+        .execute(&environment)
+        .unwrap();
+        assert_eq!(environment.get("i"), Ok(3.0.into()));
+    }
+}
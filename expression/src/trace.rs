@@ -0,0 +1,14 @@
+//! The `log` targets the evaluator traces under, all at the trace level.
+//! Nothing is formatted unless a logger is listening.
+
+/// Every target below starts with this, to enable them all at once.
+pub const EVAL: &str = "expression::eval";
+/// An expression about to be evaluated, each followed by its [`EXIT`]
+/// once any nested expressions have been.
+pub const ENTER: &str = "expression::eval::enter";
+/// What an expression evaluated to, or the error it raised.
+pub const EXIT: &str = "expression::eval::exit";
+/// A unary or binary operator applied to its operands.
+pub const OPERATOR: &str = "expression::eval::operator";
+/// Arithmetic done directly on nodes and values.
+pub const ARITHMETIC: &str = "expression::eval::arithmetic";
@@ -1,13 +1,13 @@
-use crate::{literal, Expression, Node, OperatorNode};
+use crate::{literal, trace, Environment, Expression, Node, OperatorNode, RuntimeError};
 use core::{
     fmt,
     ops::{Neg, Not},
 };
+use token::Span;
+type UnaryMap<A, Output> = dyn Fn(<A as Expression>::Output) -> Result<Output, RuntimeError>;
 pub trait UnaryNodeOperator: OperatorNode {
     type A: Expression;
-    fn identity(
-        &self,
-    ) -> Box<dyn Fn(<Self::A as Expression>::Output) -> Option<<Self as OperatorNode>::Output>>;
+    fn identity(&self) -> Box<UnaryMap<Self::A, <Self as OperatorNode>::Output>>;
     fn express(self, a: Self::A) -> UnaryExpression<Self::A, Self::Output>
     where
         Self: Sized + 'static,
@@ -17,6 +17,7 @@ pub trait UnaryNodeOperator: OperatorNode {
         UnaryExpression {
             operand: a,
             operator: Box::new(self),
+            span: Span::default(),
         }
     }
 }
@@ -30,13 +31,9 @@ impl OperatorNode for UnaryOperator {
 }
 impl UnaryNodeOperator for UnaryOperator {
     type A = Node;
-    fn identity(&self) -> Box<dyn Fn(<Box<Node> as Expression>::Output) -> Option<Node>> {
+    fn identity(&self) -> Box<UnaryMap<Self::A, Self::Output>> {
         match self {
-            Self::Not => Box::new(|a| {
-                Some(a.not())
-                    .map(literal::Value::Boolean)
-                    .map(Node::Literal)
-            }),
+            Self::Not => Box::new(|a| Ok(Node::Literal(literal::Value::Boolean(a.not())))),
             Self::Neg => Box::new(|a| a.neg().map(literal::Value::Number).map(Node::Literal)),
         }
     }
@@ -49,6 +46,26 @@ where
 {
     operand: O,
     operator: Box<dyn UnaryNodeOperator<A = O, Output = Output>>,
+    /// Where the operator appears in the source
+    span: Span,
+}
+impl<O, Output> UnaryExpression<O, Output>
+where
+    O: Expression,
+    Output: Expression,
+{
+    /// Locates the operator at `span`, for errors raised by it.
+    pub fn at(self, span: Span) -> Self {
+        Self { span, ..self }
+    }
+    #[inline]
+    pub fn operand(&self) -> &O {
+        &self.operand
+    }
+    #[inline]
+    pub fn span(&self) -> Span {
+        self.span
+    }
 }
 impl From<UnaryExpression<Node, Node>> for Node {
     fn from(value: UnaryExpression<Node, Node>) -> Self {
@@ -61,10 +78,32 @@ where
     Output: Expression + fmt::Debug,
 {
     type Output = Output;
-    fn eval(&self) -> Option<Self::Output> {
-        println!("Evaluating UnaryExpression {self:?}");
-        self.operand
-            .eval()
-            .and_then(|a| self.operator.identity()(a))
+    fn eval(&self, environment: &Environment) -> Result<Self::Output, RuntimeError> {
+        self.operand.eval(environment).and_then(|a| {
+            let res = self.operator.identity()(a).map_err(|e| e.at(self.span));
+            log::trace!(target: trace::OPERATOR, "Evaluated {:?} as {res:?}", self.operator);
+            res
+        })
+    }
+}
+#[cfg(test)]
+mod truthiness_tests {
+    use super::*;
+    fn not(value: impl Into<Node>) -> Result<Node, RuntimeError> {
+        UnaryOperator::Not
+            .express(value.into())
+            .eval(&Environment::new())
+    }
+    #[test]
+    fn nil_and_false_are_falsey() {
+        assert_eq!(not(literal::Value::Nil), Ok(true.into()));
+        assert_eq!(not(false), Ok(true.into()));
+    }
+    #[test]
+    fn everything_else_is_truthy() {
+        assert_eq!(not(true), Ok(false.into()));
+        assert_eq!(not(0.0), Ok(false.into()));
+        assert_eq!(not(""), Ok(false.into()));
+        assert_eq!(not("false"), Ok(false.into()));
     }
 }
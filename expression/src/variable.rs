@@ -0,0 +1,187 @@
+use crate::{Environment, Expression, Node, RuntimeError, Value};
+use core::{cell::Cell, fmt};
+use token::Span;
+
+/// A name being declared, and where it appears in the source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Identifier {
+    pub lexeme: String,
+    pub span: Span,
+}
+impl Identifier {
+    pub fn new(lexeme: impl ToString, span: Span) -> Self {
+        Self {
+            lexeme: lexeme.to_string(),
+            span,
+        }
+    }
+}
+impl From<&str> for Identifier {
+    fn from(value: &str) -> Self {
+        Self::new(value, Span::default())
+    }
+}
+impl fmt::Display for Identifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.lexeme)
+    }
+}
+/// Where a variable reference binds, as worked out ahead of evaluation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Resolution {
+    /// Not resolved, so looked up through every enclosing scope
+    Unresolved,
+    /// Declared this many scopes out from the reference
+    Local(usize),
+    Global,
+}
+/// A reference to a variable by name.
+#[derive(Debug)]
+pub struct Variable {
+    name: String,
+    /// Where the name appears in the source
+    span: Span,
+    resolution: Cell<Resolution>,
+}
+impl Variable {
+    pub fn new(name: impl ToString, span: Span) -> Self {
+        Self {
+            name: name.to_string(),
+            span,
+            resolution: Cell::new(Resolution::Unresolved),
+        }
+    }
+    /// Binds this reference to the variable declared `depth` scopes out.
+    pub fn resolve_local(&self, depth: usize) {
+        self.resolution.set(Resolution::Local(depth));
+    }
+    /// Binds this reference to the global variable of its name.
+    pub fn resolve_global(&self) {
+        self.resolution.set(Resolution::Global);
+    }
+    /// How many scopes out the variable was declared, if it was resolved
+    /// to a local one.
+    pub fn depth(&self) -> Option<usize> {
+        match self.resolution.get() {
+            Resolution::Local(depth) => Some(depth),
+            _ => None,
+        }
+    }
+    /// The scope holding the variable this refers to.
+    fn scope(&self, environment: &Environment) -> Environment {
+        match self.resolution.get() {
+            Resolution::Unresolved => environment.clone(),
+            Resolution::Local(depth) => environment.ancestor(depth),
+            Resolution::Global => environment.globals(),
+        }
+    }
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    #[inline]
+    pub fn span(&self) -> Span {
+        self.span
+    }
+}
+impl From<Variable> for Node {
+    fn from(value: Variable) -> Self {
+        Node::Variable(value)
+    }
+}
+impl Expression for Variable {
+    type Output = Value;
+    fn eval(&self, environment: &Environment) -> Result<Self::Output, RuntimeError> {
+        self.scope(environment)
+            .get(&self.name)
+            .map_err(|e| e.at(self.span))
+    }
+}
+/// Assigns the value of an expression to an existing variable, and
+/// evaluates to that value.
+#[derive(Debug)]
+pub struct Assignment {
+    variable: Variable,
+    value: Node,
+}
+impl Assignment {
+    pub fn new(variable: Variable, value: Node) -> Self {
+        Self { variable, value }
+    }
+    #[inline]
+    pub fn variable(&self) -> &Variable {
+        &self.variable
+    }
+    #[inline]
+    pub fn value(&self) -> &Node {
+        &self.value
+    }
+}
+impl From<Assignment> for Node {
+    fn from(value: Assignment) -> Self {
+        Node::Assign(Box::new(value))
+    }
+}
+impl Expression for Assignment {
+    type Output = Value;
+    fn eval(&self, environment: &Environment) -> Result<Self::Output, RuntimeError> {
+        let value = self.value.eval(environment)?;
+        self.variable
+            .scope(environment)
+            .assign(&self.variable.name, value.clone())
+            .map_err(|e| e.at(self.variable.span))?;
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod variable_tests {
+    use super::*;
+    #[test]
+    fn read_and_assign() {
+        let environment = Environment::new();
+        environment.define("a", 1.0.into());
+        let assign = Assignment::new(
+            Variable::new("a", Span::default()),
+            Node::plus(Variable::new("a", Span::default()), 1.0),
+        );
+        assert_eq!(assign.eval(&environment), Ok(2.0.into()));
+        assert_eq!(
+            Variable::new("a", Span::default()).eval(&environment),
+            Ok(2.0.into())
+        );
+    }
+    #[test]
+    fn undefined_variable_error_is_located() {
+        let location = |offset| token::Location {
+            offset,
+            line: 1,
+            column: offset + 1,
+        };
+        let span = Span::new(location(0), location(3));
+        let error = Variable::new("foo", span)
+            .eval(&Environment::new())
+            .unwrap_err();
+        assert_eq!(error.message, "Undefined variable 'foo'.");
+        assert_eq!(error.span, Some(span));
+    }
+    #[test]
+    fn resolved_variables_skip_nearer_scopes() {
+        let globals = Environment::new();
+        globals.define("a", "global".into());
+        let block = globals.enclose();
+        block.define("a", "block".into());
+        let inner = block.enclose();
+        let variable = Variable::new("a", Span::default());
+        assert_eq!(variable.eval(&inner), Ok("block".into()));
+        variable.resolve_global();
+        assert_eq!(variable.eval(&inner), Ok("global".into()));
+        variable.resolve_local(1);
+        assert_eq!(variable.eval(&inner), Ok("block".into()));
+        let assign = Assignment::new(Variable::new("a", Span::default()), 1.0.into());
+        assign.variable().resolve_global();
+        assign.eval(&inner).unwrap();
+        assert_eq!(globals.get("a"), Ok(1.0.into()));
+        assert_eq!(block.get("a"), Ok("block".into()));
+    }
+}
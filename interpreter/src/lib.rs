@@ -0,0 +1,401 @@
+use core::fmt;
+use diagnostics::{Diagnostic, ToDiagnostic};
+use expression::{Environment, Expression, Statement};
+use parser::{ParseError, Parser};
+use resolver::{ResolveError, Resolver};
+use scanner::{ScanError, Scanner};
+use std::path::Path;
+
+pub use expression::{native, Limit, Limits, RuntimeError, Value};
+pub use output::{Captured, Output};
+
+/// Anything that can go wrong running Lox, from reading the script to
+/// running it.
+#[derive(Debug)]
+pub enum LoxError {
+    Io(std::io::Error),
+    Scan(Vec<ScanError>),
+    Parse(Vec<ParseError>),
+    Resolve(Vec<ResolveError>),
+    Runtime(RuntimeError),
+    /// The program ran into one of its [`Limits`]. [`Limit::Allocation`]
+    /// counts every byte allocated since the script started, not the
+    /// memory still in use, so a long loop can run into it without
+    /// holding on to anything, unlike the bytecode VM's heap limit.
+    LimitExceeded(RuntimeError),
+}
+impl LoxError {
+    /// The limit the program ran into, if that's what stopped it.
+    pub fn limit(&self) -> Option<Limit> {
+        match self {
+            Self::LimitExceeded(error) => error.limit,
+            _ => None,
+        }
+    }
+    /// Every error as a diagnostic, in the order they were found.
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        match self {
+            Self::Io(e) => vec![Diagnostic::error(e)],
+            Self::Scan(errors) => errors.iter().map(ToDiagnostic::to_diagnostic).collect(),
+            Self::Parse(errors) => errors.iter().map(ToDiagnostic::to_diagnostic).collect(),
+            Self::Resolve(errors) => errors.iter().map(ToDiagnostic::to_diagnostic).collect(),
+            Self::Runtime(error) | Self::LimitExceeded(error) => vec![error.to_diagnostic()],
+        }
+    }
+}
+/// One error per line.
+impl fmt::Display for LoxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn lines(f: &mut fmt::Formatter<'_>, errors: &[impl fmt::Display]) -> fmt::Result {
+            let mut errors = errors.iter();
+            if let Some(first) = errors.next() {
+                f.write_fmt(format_args!("{first}"))?;
+            }
+            errors.try_for_each(|error| f.write_fmt(format_args!("\n{error}")))
+        }
+        match self {
+            Self::Io(e) => f.write_fmt(format_args!("{e}")),
+            Self::Scan(errors) => lines(f, errors),
+            Self::Parse(errors) => lines(f, errors),
+            Self::Resolve(errors) => lines(f, errors),
+            Self::Runtime(error) | Self::LimitExceeded(error) => {
+                f.write_fmt(format_args!("{error}"))
+            }
+        }
+    }
+}
+impl std::error::Error for LoxError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::Runtime(e) | Self::LimitExceeded(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+impl From<std::io::Error> for LoxError {
+    fn from(value: std::io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+impl From<RuntimeError> for LoxError {
+    fn from(value: RuntimeError) -> Self {
+        match value.limit {
+            Some(_) => Self::LimitExceeded(value),
+            None => Self::Runtime(value),
+        }
+    }
+}
+/// A session running Lox on the tree-walking evaluator, with globals
+/// that persist from one script to the next.
+///
+/// ```
+/// use interpreter::{Interpreter, Value};
+///
+/// let mut lox = Interpreter::new();
+/// lox.eval_str("var greeting = \"hello\";").unwrap();
+/// let value = lox.eval_str("greeting + \" world\"").unwrap();
+/// assert_eq!(value, Value::from("hello world"));
+/// ```
+#[derive(Debug)]
+pub struct Interpreter {
+    globals: Environment,
+    /// Whether `+` stringifies a number or bool added to a string
+    stringify_concatenation: bool,
+}
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl Interpreter {
+    /// A new session with only the builtin natives defined.
+    pub fn new() -> Self {
+        let globals = Environment::new();
+        expression::native::define_builtins(&globals);
+        Self {
+            globals,
+            stringify_concatenation: false,
+        }
+    }
+    /// Opts into the dialect where `+` stringifies a number or bool
+    /// when the other operand is a string, as in `"n = " + 1`.
+    pub fn with_stringify_concatenation(self, enabled: bool) -> Self {
+        Self {
+            stringify_concatenation: enabled,
+            ..self
+        }
+    }
+    /// Bounds what each script may use. Calls and expressions may only
+    /// nest as deep as [`Limits::native_stack`] allows, which is
+    /// [`expression::DEFAULT_NATIVE_STACK`] unless told otherwise.
+    /// [`Limits::allocation`] is a budget for all the script allocates,
+    /// freed or not, rather than a bound on the memory it holds.
+    pub fn with_limits(self, limits: Limits) -> Self {
+        self.globals.set_limits(limits);
+        self
+    }
+    pub fn limits(&self) -> Limits {
+        self.globals.limits()
+    }
+    /// Sends what `print` writes to `output` instead of stdout.
+    pub fn with_stdout(self, output: Output) -> Self {
+        self.globals.set_stdout(output);
+        self
+    }
+    /// Sends what [`Interpreter::report`] writes to `output` instead of
+    /// stderr.
+    pub fn with_stderr(self, output: Output) -> Self {
+        self.globals.set_stderr(output);
+        self
+    }
+    /// Writes `error` to stderr, one error per line.
+    pub fn report(&self, error: &LoxError) -> std::io::Result<()> {
+        writeln!(self.globals.stderr(), "{error}")
+    }
+    /// Runs `source`, returning the value of its last statement if that
+    /// is an expression. That last expression may leave out its
+    /// semicolon. Each call has the full limits to itself.
+    pub fn eval(&mut self, source: &str) -> Result<Option<Value>, LoxError> {
+        self.globals.reset_budget();
+        let statements = Parser::new(scan(source)?)
+            .stringify_concatenation(self.stringify_concatenation)
+            .trailing_expression(true)
+            .parse()
+            .map_err(LoxError::Parse)?;
+        Resolver::new()
+            .resolve(&statements)
+            .map_err(LoxError::Resolve)?;
+        let Some((last, statements)) = statements.split_last() else {
+            return Ok(None);
+        };
+        for statement in statements {
+            statement.execute(&self.globals)?;
+        }
+        match last {
+            Statement::Expression(expression) => Ok(Some(expression.eval(&self.globals)?)),
+            statement => {
+                statement.execute(&self.globals)?;
+                Ok(None)
+            }
+        }
+    }
+    /// Runs `source` as [`Interpreter::eval`], with `nil` for no value.
+    pub fn eval_str(&mut self, source: &str) -> Result<Value, LoxError> {
+        Ok(self.eval(source)?.unwrap_or(Value::Nil))
+    }
+    /// Runs the script at `path`, as [`Interpreter::eval_str`].
+    pub fn run_file(&mut self, path: impl AsRef<Path>) -> Result<Value, LoxError> {
+        let source = std::fs::read_to_string(path)?;
+        self.eval_str(&source)
+    }
+    /// The global called `name`, if it is defined.
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        self.globals.get(name).ok()
+    }
+    /// Defines the global `name`, replacing any existing definition.
+    pub fn set_global(&mut self, name: &str, value: impl Into<Value>) {
+        self.globals.define(name, value.into());
+    }
+    /// Defines the global `name` as a native function taking `arity`
+    /// arguments.
+    pub fn define_native(
+        &mut self,
+        name: &str,
+        arity: usize,
+        function: impl Fn(&[Value]) -> Result<Value, RuntimeError> + 'static,
+    ) {
+        self.globals.define_native(name, arity, function);
+    }
+}
+fn scan(source: &str) -> Result<Vec<token::Token>, LoxError> {
+    match Scanner::new(source).scan_tokens() {
+        (tokens, errors) if errors.is_empty() => Ok(tokens),
+        (_, errors) => Err(LoxError::Scan(errors)),
+    }
+}
+
+#[cfg(test)]
+mod interpreter_tests {
+    use super::*;
+    #[test]
+    fn returns_the_last_expression() {
+        let mut lox = Interpreter::new();
+        assert_eq!(lox.eval_str("1 + 2").unwrap(), Value::from(3.0));
+        assert_eq!(
+            lox.eval_str("var a = 1; a * 10;").unwrap(),
+            Value::from(10.0)
+        );
+        assert_eq!(lox.eval_str("var b = 2;").unwrap(), Value::Nil);
+        assert_eq!(lox.eval_str("").unwrap(), Value::Nil);
+        assert_eq!(lox.eval("print 1;").unwrap(), None);
+        assert_eq!(lox.eval("b").unwrap(), Some(Value::from(2.0)));
+        assert_eq!(
+            lox.eval("var c = 3; b + c").unwrap(),
+            Some(Value::from(5.0))
+        );
+        assert_eq!(lox.get_global("c"), Some(Value::from(3.0)));
+        assert!(matches!(
+            lox.eval("var d = 4; d d"),
+            Err(LoxError::Parse(_))
+        ));
+        assert_eq!(lox.get_global("d"), None);
+    }
+    #[test]
+    fn globals_persist_between_calls() {
+        let mut lox = Interpreter::new();
+        lox.eval_str("fun twice(x) { return x * 2; }").unwrap();
+        lox.set_global("n", 21.0);
+        assert_eq!(lox.eval_str("twice(n)").unwrap(), Value::from(42.0));
+        lox.eval_str("var name = \"lox\";").unwrap();
+        assert_eq!(lox.get_global("name"), Some(Value::from("lox")));
+        assert_eq!(lox.get_global("missing"), None);
+    }
+    #[test]
+    fn natives_can_be_defined() {
+        let mut lox = Interpreter::new();
+        lox.define_native("greet", 1, |arguments| {
+            let name: String = native::argument(arguments, 0, "a string")?;
+            Ok(format!("hello {name}").into())
+        });
+        assert_eq!(
+            lox.eval_str("greet(\"lox\")").unwrap(),
+            Value::from("hello lox")
+        );
+        assert!(matches!(lox.eval_str("clock()"), Ok(Value::Number(_))));
+    }
+    #[test]
+    fn concatenation_can_stringify() {
+        let mut lox = Interpreter::new();
+        assert!(matches!(
+            lox.eval_str("\"n = \" + 1"),
+            Err(LoxError::Runtime(_))
+        ));
+        let mut lox = Interpreter::new().with_stringify_concatenation(true);
+        assert_eq!(lox.eval_str("\"n = \" + 1").unwrap(), Value::from("n = 1"));
+        assert_eq!(lox.eval_str("true + \"!\"").unwrap(), Value::from("true!"));
+    }
+    #[test]
+    fn errors_are_unified() {
+        let mut lox = Interpreter::new();
+        assert!(matches!(lox.eval_str("\"open"), Err(LoxError::Scan(_))));
+        assert!(matches!(lox.eval_str("var = 1;"), Err(LoxError::Parse(_))));
+        assert!(matches!(
+            lox.eval_str("return 1;"),
+            Err(LoxError::Resolve(_))
+        ));
+        let Err(LoxError::Runtime(error)) = lox.eval_str("-\"a\";") else {
+            panic!("expected a runtime error");
+        };
+        assert_eq!(error.message, "Operand must be a number.");
+        assert!(matches!(
+            lox.run_file("/no/such/file.lox"),
+            Err(LoxError::Io(_))
+        ));
+        let error = lox.eval_str("nope;").unwrap_err();
+        assert_eq!(error.diagnostics().len(), 1);
+        assert!(error.to_string().contains("Undefined variable 'nope'."));
+    }
+    #[test]
+    fn limits_stop_runaway_scripts() {
+        let limited = |limits: Limits, source: &str| {
+            let error = Interpreter::new()
+                .with_limits(limits)
+                .eval_str(source)
+                .unwrap_err();
+            assert!(matches!(error, LoxError::LimitExceeded(_)), "{error:?}");
+            error.limit()
+        };
+        let recurse = "fun f(n) { return f(n + 1) + 1; } f(0);";
+        let shallow = Limits {
+            call_depth: Some(8),
+            ..Limits::default()
+        };
+        assert_eq!(limited(shallow, recurse), Some(Limit::CallDepth));
+        // Even unbounded, recursion stops short of overflowing the stack
+        assert_eq!(limited(Limits::default(), recurse), Some(Limit::CallDepth));
+        let forever = "while (true) {}";
+        let steps = Limits {
+            steps: Some(1000),
+            ..Limits::default()
+        };
+        assert_eq!(limited(steps, forever), Some(Limit::Steps));
+        let time = Limits {
+            time: Some(std::time::Duration::from_millis(10)),
+            ..Limits::default()
+        };
+        assert_eq!(limited(time, forever), Some(Limit::Time));
+        let allocation = Limits {
+            allocation: Some(64 * 1024),
+            ..Limits::default()
+        };
+        let grow = "var s = \"x\"; while (true) s = s + s;";
+        assert_eq!(limited(allocation, grow), Some(Limit::Allocation));
+        // Even what is freed straight away counts
+        let churn = "for (var i = 0; i < 100000; i = i + 1) { var s = \"ab\" + \"cd\"; }";
+        assert_eq!(limited(allocation, churn), Some(Limit::Allocation));
+        // Every script starts afresh
+        let mut lox = Interpreter::new().with_limits(steps);
+        assert_eq!(lox.limits(), steps);
+        for _ in 0..3 {
+            lox.eval_str("for (var i = 0; i < 50; i = i + 1) {}")
+                .unwrap();
+        }
+        assert_eq!(lox.eval_str("-nil").unwrap_err().limit(), None);
+    }
+    #[test]
+    fn deep_nesting_is_an_error() {
+        let parse_error = |source: String| {
+            let error = Interpreter::new().eval_str(&source).unwrap_err();
+            assert!(matches!(error, LoxError::Parse(_)), "{error:?}");
+        };
+        parse_error(format!("{}1{}", "(".repeat(5000), ")".repeat(5000)));
+        parse_error("{".repeat(20000) + &"}".repeat(20000));
+        parse_error(format!("{}1", "-".repeat(200000)));
+        parse_error(format!("1{}", " + 1".repeat(200000)));
+        // What the parser allows may still be too deep for a small stack
+        let nested = format!("{}1{}", "(".repeat(40), ")".repeat(40));
+        let limits = Limits {
+            native_stack: 4 * 1024,
+            ..Limits::default()
+        };
+        let mut lox = Interpreter::new().with_limits(limits);
+        assert_eq!(
+            lox.eval_str(&nested).unwrap_err().limit(),
+            Some(Limit::CallDepth)
+        );
+        assert_eq!(lox.eval_str("1").unwrap(), Value::Number(1.0));
+    }
+    #[test]
+    fn calls_nest_as_deep_as_the_stack_allows() {
+        const STACK: usize = 64 * 1024 * 1024;
+        let count = "fun count(n) { if (n == 0) return 0; return 1 + count(n - 1); }";
+        let deep = std::thread::Builder::new()
+            .stack_size(STACK)
+            .spawn(move || {
+                let limits = Limits {
+                    native_stack: STACK / 2,
+                    ..Limits::default()
+                };
+                let mut lox = Interpreter::new().with_limits(limits);
+                lox.eval_str(count).unwrap();
+                lox.eval_str("count(1000)").unwrap().to_string()
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+        assert_eq!(deep, "1000");
+    }
+    #[test]
+    fn output_can_be_captured() {
+        let (stdout, printed) = Output::capture();
+        let (stderr, reported) = Output::capture();
+        let mut lox = Interpreter::new().with_stdout(stdout).with_stderr(stderr);
+        lox.eval_str("print \"hello\"; print 1 + 2;").unwrap();
+        assert_eq!(printed.take(), "hello\n3\n");
+        let error = lox.eval_str("print nope;").unwrap_err();
+        lox.report(&error).unwrap();
+        assert_eq!(printed.contents(), "");
+        assert!(reported.contents().contains("Undefined variable 'nope'."));
+    }
+}
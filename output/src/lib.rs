@@ -0,0 +1,106 @@
+use core::fmt;
+use std::{
+    cell::RefCell,
+    io::{self, Write},
+    rc::Rc,
+};
+
+/// Where a program's output goes, such as what `print` shows. Clones
+/// share the same sink, so that `writeln!` works through any of them.
+#[derive(Clone)]
+pub struct Output(Rc<RefCell<dyn Write>>);
+impl Output {
+    /// Writes to `writer`.
+    pub fn new(writer: impl Write + 'static) -> Self {
+        Self(Rc::new(RefCell::new(writer)))
+    }
+    pub fn stdout() -> Self {
+        Self::new(io::stdout())
+    }
+    pub fn stderr() -> Self {
+        Self::new(io::stderr())
+    }
+    /// Hands everything written to `callback`, a piece at a time.
+    pub fn callback(callback: impl FnMut(&str) + 'static) -> Self {
+        Self::new(Callback(callback))
+    }
+    /// Keeps everything written, to be read back from the [`Captured`].
+    pub fn capture() -> (Self, Captured) {
+        let captured = Captured::default();
+        (Self::new(captured.clone()), captured)
+    }
+    pub fn write_fmt(&self, args: fmt::Arguments<'_>) -> io::Result<()> {
+        self.0.borrow_mut().write_fmt(args)
+    }
+    pub fn flush(&self) -> io::Result<()> {
+        self.0.borrow_mut().flush()
+    }
+}
+impl Default for Output {
+    fn default() -> Self {
+        Self::stdout()
+    }
+}
+impl fmt::Debug for Output {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Output").finish_non_exhaustive()
+    }
+}
+struct Callback<F>(F);
+impl<F: FnMut(&str)> Write for Callback<F> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        (self.0)(&String::from_utf8_lossy(buf));
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+/// Everything written to an [`Output::capture`].
+#[derive(Debug, Clone, Default)]
+pub struct Captured(Rc<RefCell<Vec<u8>>>);
+impl Captured {
+    pub fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.0.borrow()).into_owned()
+    }
+    /// Empties the capture, returning what it held.
+    pub fn take(&self) -> String {
+        let contents = self.contents();
+        self.0.borrow_mut().clear();
+        contents
+    }
+}
+impl Write for Captured {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod output_tests {
+    use super::*;
+    #[test]
+    fn captures_what_is_written() {
+        let (output, captured) = Output::capture();
+        let clone = output.clone();
+        writeln!(output, "one").unwrap();
+        write!(clone, "two {}", 2).unwrap();
+        assert_eq!(captured.take(), "one\ntwo 2");
+        assert_eq!(captured.contents(), "");
+    }
+    #[test]
+    fn calls_back_with_what_is_written() {
+        let lines = Rc::new(RefCell::new(String::new()));
+        let output = Output::callback({
+            let lines = lines.clone();
+            move |s| lines.borrow_mut().push_str(s)
+        });
+        writeln!(output, "hello {}", 42).unwrap();
+        output.flush().unwrap();
+        assert_eq!(*lines.borrow(), "hello 42\n");
+    }
+}
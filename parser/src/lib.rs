@@ -1,14 +1,1023 @@
-// pub fn add(left: u64, right: u64) -> u64 {
-//     left + right
-// }
-
-// #[cfg(test)]
-// mod tests {
-//     use super::*;
-
-//     #[test]
-//     fn it_works() {
-//         let result = add(2, 2);
-//         assert_eq!(result, 4);
-//     }
-// }
+use convert::MaybeFrom;
+use core::fmt;
+use diagnostics::{Diagnostic, ToDiagnostic};
+use expression::{
+    binary::{ArithmeticOperator, BinaryOperator, EqualityOperator, OrderingOperatorNode},
+    class::{ClassDeclaration, Get, Set, Super},
+    function::{Call, FunctionDeclaration, MAX_ARITY},
+    Assignment, Identifier, LogicalOperator, Node, Statement, UnaryNodeOperator, UnaryOperator,
+    Variable,
+};
+use std::rc::Rc;
+use token::{ComparatorType, Direction, KeywordType, Span, Token, TokenType};
+
+/// How deep the parser may recurse into statements in blocks and
+/// bodies, functions, groupings and unary operators, so that it can't
+/// overflow the stack. Each link in a chain of binary operators, calls
+/// or property accesses counts too, while the chain lasts, as it nests
+/// the syntax tree just as deep.
+pub const MAX_NESTING: usize = 64;
+
+#[derive(Debug)]
+pub struct ParseError {
+    pub span: Span,
+    pub lexeme: String,
+    pub message: String,
+}
+impl ParseError {
+    fn new(token: &Token, message: impl ToString) -> Self {
+        Self {
+            span: token.span,
+            lexeme: token.lexeme.clone(),
+            message: message.to_string(),
+        }
+    }
+}
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.lexeme.is_empty() {
+            f.write_fmt(format_args!(
+                "[line {}] Error at end: {}",
+                self.span, self.message
+            ))
+        } else {
+            f.write_fmt(format_args!(
+                "[line {}] Error at '{}': {}",
+                self.span, self.lexeme, self.message
+            ))
+        }
+    }
+}
+impl std::error::Error for ParseError {}
+impl ToDiagnostic for ParseError {
+    fn to_diagnostic(&self) -> Diagnostic {
+        Diagnostic::error(&self.message).at(self.span)
+    }
+}
+pub struct Parser {
+    tokens: Vec<Token>,
+    current: usize,
+    stringify_concatenation: bool,
+    trailing_expression: bool,
+    /// How deep the parser currently is, up to [`MAX_NESTING`]
+    depth: usize,
+}
+impl Parser {
+    pub fn new(tokens: Vec<Token>) -> Self {
+        Self {
+            tokens,
+            current: 0,
+            stringify_concatenation: false,
+            trailing_expression: false,
+            depth: 0,
+        }
+    }
+    /// Opts into the dialect where `+` stringifies a number or bool
+    /// when the other operand is a string.
+    pub fn stringify_concatenation(self, enabled: bool) -> Self {
+        Self {
+            stringify_concatenation: enabled,
+            ..self
+        }
+    }
+    /// Parses a whole program, collecting every error encountered
+    /// rather than stopping at the first.
+    pub fn parse(&mut self) -> Result<Vec<Statement>, Vec<ParseError>> {
+        let mut statements = vec![];
+        let mut errors = vec![];
+        while !self.is_at_end() {
+            match self.declaration() {
+                Ok(statement) => statements.push(statement),
+                Err(error) => {
+                    errors.push(error);
+                    self.synchronize();
+                }
+            }
+        }
+        if errors.is_empty() {
+            Ok(statements)
+        } else {
+            Err(errors)
+        }
+    }
+    /// Opts into letting an expression statement at the very end leave
+    /// out its semicolon, as is handy when typing at a prompt.
+    pub fn trailing_expression(self, enabled: bool) -> Self {
+        Self {
+            trailing_expression: enabled,
+            ..self
+        }
+    }
+    /// Parses a single expression spanning all of the tokens.
+    pub fn parse_expression(&mut self) -> Result<Node, ParseError> {
+        let expression = self.expression()?;
+        if !self.is_at_end() {
+            return Err(ParseError::new(self.peek(), "Expect end of expression."));
+        }
+        Ok(expression)
+    }
+    fn declaration(&mut self) -> Result<Statement, ParseError> {
+        if self.r#match(&[TokenType::Keyword(KeywordType::Class)]) {
+            self.class_declaration()
+        } else if self.r#match(&[TokenType::Keyword(KeywordType::Fun)]) {
+            self.function("function").map(Statement::Function)
+        } else if self.r#match(&[TokenType::Keyword(KeywordType::Var)]) {
+            self.var_declaration()
+        } else {
+            self.statement()
+        }
+    }
+    fn class_declaration(&mut self) -> Result<Statement, ParseError> {
+        let name = self.identifier("Expect class name.")?;
+        let superclass = if self.r#match(&[ComparatorType::Less.r#match(false)]) {
+            let superclass = self.consume(TokenType::Identifier, "Expect superclass name.")?;
+            Some(Variable::new(&superclass.lexeme, superclass.span))
+        } else {
+            None
+        };
+        self.consume(
+            TokenType::Brace(Direction::Left),
+            "Expect '{' before class body.",
+        )?;
+        let mut methods = vec![];
+        while !self.check(TokenType::Brace(Direction::Right)) && !self.is_at_end() {
+            methods.push(self.function("method")?);
+        }
+        self.consume(
+            TokenType::Brace(Direction::Right),
+            "Expect '}' after class body.",
+        )?;
+        Ok(Statement::Class(Rc::new(ClassDeclaration {
+            name,
+            superclass,
+            methods,
+        })))
+    }
+    /// Parses the name, parameters and body of a function, where `kind`
+    /// describes it for error messages.
+    fn function(&mut self, kind: &str) -> Result<Rc<FunctionDeclaration>, ParseError> {
+        self.nested(|parser| parser.function_declaration(kind))
+    }
+    fn function_declaration(&mut self, kind: &str) -> Result<Rc<FunctionDeclaration>, ParseError> {
+        let name = self.identifier(&format!("Expect {kind} name."))?;
+        self.consume(
+            TokenType::Paren(Direction::Left),
+            &format!("Expect '(' after {kind} name."),
+        )?;
+        let mut params = vec![];
+        if !self.check(TokenType::Paren(Direction::Right)) {
+            loop {
+                if params.len() >= MAX_ARITY {
+                    return Err(ParseError::new(
+                        self.peek(),
+                        format!("Can't have more than {MAX_ARITY} parameters."),
+                    ));
+                }
+                params.push(self.identifier("Expect parameter name.")?);
+                if !self.r#match(&[TokenType::Comma]) {
+                    break;
+                }
+            }
+        }
+        self.consume(
+            TokenType::Paren(Direction::Right),
+            "Expect ')' after parameters.",
+        )?;
+        self.consume(
+            TokenType::Brace(Direction::Left),
+            &format!("Expect '{{' before {kind} body."),
+        )?;
+        let body = self.block()?;
+        Ok(Rc::new(FunctionDeclaration { name, params, body }))
+    }
+    fn var_declaration(&mut self) -> Result<Statement, ParseError> {
+        let name = self.identifier("Expect variable name.")?;
+        let initializer = if self.r#match(&[ComparatorType::Equal.r#match(false)]) {
+            Some(self.expression()?)
+        } else {
+            None
+        };
+        self.consume(
+            TokenType::Semicolon,
+            "Expect ';' after variable declaration.",
+        )?;
+        Ok(Statement::Var { name, initializer })
+    }
+    fn statement(&mut self) -> Result<Statement, ParseError> {
+        self.nested(Self::nested_statement)
+    }
+    fn nested_statement(&mut self) -> Result<Statement, ParseError> {
+        match self.peek().r#type {
+            TokenType::Keyword(KeywordType::For) => {
+                self.advance();
+                self.for_statement()
+            }
+            TokenType::Keyword(KeywordType::If) => {
+                self.advance();
+                self.if_statement()
+            }
+            TokenType::Keyword(KeywordType::Print) => {
+                self.advance();
+                self.print_statement()
+            }
+            TokenType::Keyword(KeywordType::Return) => {
+                let span = self.advance().span;
+                self.return_statement(span)
+            }
+            TokenType::Keyword(KeywordType::While) => {
+                self.advance();
+                self.while_statement()
+            }
+            TokenType::Brace(Direction::Left) => {
+                self.advance();
+                self.block().map(Statement::Block)
+            }
+            _ => self.expression_statement(),
+        }
+    }
+    /// Desugars `for (initializer; condition; increment) body` into
+    /// `{ initializer; while (condition) { body; increment; } }`.
+    fn for_statement(&mut self) -> Result<Statement, ParseError> {
+        self.consume(TokenType::Paren(Direction::Left), "Expect '(' after 'for'.")?;
+        let initializer = if self.r#match(&[TokenType::Semicolon]) {
+            None
+        } else if self.r#match(&[TokenType::Keyword(KeywordType::Var)]) {
+            Some(self.var_declaration()?)
+        } else {
+            Some(self.expression_statement()?)
+        };
+        let condition = if self.check(TokenType::Semicolon) {
+            Node::from(true)
+        } else {
+            self.expression()?
+        };
+        self.consume(TokenType::Semicolon, "Expect ';' after loop condition.")?;
+        let increment = if self.check(TokenType::Paren(Direction::Right)) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+        self.consume(
+            TokenType::Paren(Direction::Right),
+            "Expect ')' after for clauses.",
+        )?;
+
+        let mut body = self.statement()?;
+        if let Some(increment) = increment {
+            body = Statement::Block(vec![body, Statement::Expression(increment)]);
+        }
+        body = Statement::While {
+            condition,
+            body: Box::new(body),
+        };
+        if let Some(initializer) = initializer {
+            body = Statement::Block(vec![initializer, body]);
+        }
+        Ok(body)
+    }
+    fn if_statement(&mut self) -> Result<Statement, ParseError> {
+        self.consume(TokenType::Paren(Direction::Left), "Expect '(' after 'if'.")?;
+        let condition = self.expression()?;
+        self.consume(
+            TokenType::Paren(Direction::Right),
+            "Expect ')' after if condition.",
+        )?;
+        let then_branch = Box::new(self.statement()?);
+        let else_branch = if self.r#match(&[TokenType::Keyword(KeywordType::Else)]) {
+            Some(Box::new(self.statement()?))
+        } else {
+            None
+        };
+        Ok(Statement::If {
+            condition,
+            then_branch,
+            else_branch,
+        })
+    }
+    fn print_statement(&mut self) -> Result<Statement, ParseError> {
+        let value = self.expression()?;
+        self.consume(TokenType::Semicolon, "Expect ';' after value.")?;
+        Ok(Statement::Print(value))
+    }
+    fn return_statement(&mut self, span: Span) -> Result<Statement, ParseError> {
+        let value = if self.check(TokenType::Semicolon) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+        self.consume(TokenType::Semicolon, "Expect ';' after return value.")?;
+        Ok(Statement::Return { value, span })
+    }
+    fn while_statement(&mut self) -> Result<Statement, ParseError> {
+        self.consume(
+            TokenType::Paren(Direction::Left),
+            "Expect '(' after 'while'.",
+        )?;
+        let condition = self.expression()?;
+        self.consume(
+            TokenType::Paren(Direction::Right),
+            "Expect ')' after condition.",
+        )?;
+        let body = Box::new(self.statement()?);
+        Ok(Statement::While { condition, body })
+    }
+    fn block(&mut self) -> Result<Vec<Statement>, ParseError> {
+        let mut statements = vec![];
+        while !self.check(TokenType::Brace(Direction::Right)) && !self.is_at_end() {
+            statements.push(self.declaration()?);
+        }
+        self.consume(
+            TokenType::Brace(Direction::Right),
+            "Expect '}' after block.",
+        )?;
+        Ok(statements)
+    }
+    fn expression_statement(&mut self) -> Result<Statement, ParseError> {
+        let expression = self.expression()?;
+        if !(self.trailing_expression && self.is_at_end()) {
+            self.consume(TokenType::Semicolon, "Expect ';' after expression.")?;
+        }
+        Ok(Statement::Expression(expression))
+    }
+    /// Discards tokens until the start of the next statement, so that
+    /// a single mistake does not cascade into further errors.
+    fn synchronize(&mut self) {
+        self.advance();
+        while !self.is_at_end() {
+            if self.previous().r#type == TokenType::Semicolon {
+                return;
+            }
+            if let TokenType::Keyword(
+                KeywordType::Class
+                | KeywordType::Fun
+                | KeywordType::Var
+                | KeywordType::For
+                | KeywordType::If
+                | KeywordType::While
+                | KeywordType::Print
+                | KeywordType::Return,
+            ) = self.peek().r#type
+            {
+                return;
+            }
+            self.advance();
+        }
+    }
+    fn expression(&mut self) -> Result<Node, ParseError> {
+        self.nested(Self::assignment)
+    }
+    /// Runs `parse` a level deeper, failing instead if that would be
+    /// deeper than [`MAX_NESTING`]. Any levels `parse` adds with
+    /// [`Parser::deepen`] are given back once it returns, even if it
+    /// fails.
+    fn nested<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<T, ParseError>,
+    ) -> Result<T, ParseError> {
+        let depth = self.depth;
+        self.deepen()?;
+        let result = parse(self);
+        self.depth = depth;
+        result
+    }
+    /// Goes a level deeper, e.g. for another link in a chain.
+    fn deepen(&mut self) -> Result<(), ParseError> {
+        if self.depth == MAX_NESTING {
+            return Err(ParseError::new(self.peek(), "Too much nesting."));
+        }
+        self.depth += 1;
+        Ok(())
+    }
+    fn assignment(&mut self) -> Result<Node, ParseError> {
+        let expression = self.or()?;
+        if !self.check(ComparatorType::Equal.r#match(false)) {
+            return Ok(expression);
+        }
+        let invalid_target = ParseError::new(self.advance(), "Invalid assignment target.");
+        let value = self.nested(Self::assignment)?;
+        match expression {
+            Node::Variable(variable) => Ok(Assignment::new(variable, value).into()),
+            Node::Get(get) => Ok(Set::new(*get, value).into()),
+            _ => Err(invalid_target),
+        }
+    }
+    fn or(&mut self) -> Result<Node, ParseError> {
+        let depth = self.depth;
+        let mut expression = self.and()?;
+        while self.r#match(&[TokenType::Keyword(KeywordType::Or)]) {
+            self.deepen()?;
+            let right = self.and()?;
+            expression = LogicalOperator::Or.express(expression, right).into();
+        }
+        self.depth = depth;
+        Ok(expression)
+    }
+    fn and(&mut self) -> Result<Node, ParseError> {
+        let depth = self.depth;
+        let mut expression = self.equality()?;
+        while self.r#match(&[TokenType::Keyword(KeywordType::And)]) {
+            self.deepen()?;
+            let right = self.equality()?;
+            expression = LogicalOperator::And.express(expression, right).into();
+        }
+        self.depth = depth;
+        Ok(expression)
+    }
+    fn equality(&mut self) -> Result<Node, ParseError> {
+        let depth = self.depth;
+        let mut expression = self.comparison()?;
+        while let Some((operator, span)) = self.equality_operator() {
+            self.deepen()?;
+            let right = self.comparison()?;
+            expression = operator.express(expression, right).at(span).into();
+        }
+        self.depth = depth;
+        Ok(expression)
+    }
+    fn comparison(&mut self) -> Result<Node, ParseError> {
+        let depth = self.depth;
+        let mut expression = self.term()?;
+        while let Some((operator, span)) = self.ordering_operator() {
+            self.deepen()?;
+            let right = self.term()?;
+            expression = operator.express(expression, right).at(span).into();
+        }
+        self.depth = depth;
+        Ok(expression)
+    }
+    fn term(&mut self) -> Result<Node, ParseError> {
+        let depth = self.depth;
+        let mut expression = self.factor()?;
+        while let Some((operator, span)) = self.term_operator() {
+            self.deepen()?;
+            let right = self.factor()?;
+            expression = operator.express(expression, right).at(span).into();
+        }
+        self.depth = depth;
+        Ok(expression)
+    }
+    fn factor(&mut self) -> Result<Node, ParseError> {
+        let depth = self.depth;
+        let mut expression = self.unary()?;
+        while let Some((operator, span)) = self.factor_operator() {
+            self.deepen()?;
+            let right = self.unary()?;
+            expression = operator.express(expression, right).at(span).into();
+        }
+        self.depth = depth;
+        Ok(expression)
+    }
+    fn unary(&mut self) -> Result<Node, ParseError> {
+        if let Some((operator, span)) = self.unary_operator() {
+            let right = self.nested(Self::unary)?;
+            return Ok(operator.express(right).at(span).into());
+        }
+        self.call()
+    }
+    fn call(&mut self) -> Result<Node, ParseError> {
+        let depth = self.depth;
+        let mut expression = self.primary()?;
+        loop {
+            if self.r#match(&[TokenType::Paren(Direction::Left)]) {
+                self.deepen()?;
+                expression = self.finish_call(expression)?;
+            } else if self.r#match(&[TokenType::Dot]) {
+                self.deepen()?;
+                let name =
+                    self.consume(TokenType::Identifier, "Expect property name after '.'.")?;
+                expression = Get::new(expression, &name.lexeme, name.span).into();
+            } else {
+                self.depth = depth;
+                return Ok(expression);
+            }
+        }
+    }
+    fn finish_call(&mut self, callee: Node) -> Result<Node, ParseError> {
+        let mut arguments = vec![];
+        if !self.check(TokenType::Paren(Direction::Right)) {
+            loop {
+                if arguments.len() >= MAX_ARITY {
+                    return Err(ParseError::new(
+                        self.peek(),
+                        format!("Can't have more than {MAX_ARITY} arguments."),
+                    ));
+                }
+                arguments.push(self.expression()?);
+                if !self.r#match(&[TokenType::Comma]) {
+                    break;
+                }
+            }
+        }
+        let span = self
+            .consume(
+                TokenType::Paren(Direction::Right),
+                "Expect ')' after arguments.",
+            )?
+            .span;
+        Ok(Call::new(callee, arguments, span).into())
+    }
+    fn primary(&mut self) -> Result<Node, ParseError> {
+        if self.r#match(&[TokenType::Paren(Direction::Left)]) {
+            let expression = self.expression()?;
+            self.consume(
+                TokenType::Paren(Direction::Right),
+                "Expect ')' after expression.",
+            )?;
+            return Ok(Node::Grouping(Box::new(expression)));
+        }
+        if self.check(TokenType::Keyword(KeywordType::This)) {
+            let keyword = self.advance();
+            return Ok(Node::This(Variable::new("this", keyword.span)));
+        }
+        if self.check(TokenType::Keyword(KeywordType::Super)) {
+            let keyword = self.advance().span;
+            self.consume(TokenType::Dot, "Expect '.' after 'super'.")?;
+            let method = self.consume(TokenType::Identifier, "Expect superclass method name.")?;
+            return Ok(Super::new(keyword, &method.lexeme, method.span).into());
+        }
+        if self.check(TokenType::Identifier) {
+            let name = self.advance();
+            return Ok(Variable::new(&name.lexeme, name.span).into());
+        }
+        let Some(node) = Node::maybe_from(self.peek()) else {
+            return Err(ParseError::new(self.peek(), "Expect expression."));
+        };
+        self.advance();
+        Ok(node)
+    }
+    fn equality_operator(&mut self) -> Option<(EqualityOperator, Span)> {
+        let operator = match self.peek().r#type {
+            TokenType::Comparator {
+                r#type: ComparatorType::Equal,
+                equal: true,
+            } => EqualityOperator::Eq,
+            TokenType::Comparator {
+                r#type: ComparatorType::Bang,
+                equal: true,
+            } => EqualityOperator::Ne,
+            _ => return None,
+        };
+        let span = self.advance().span;
+        Some((operator, span))
+    }
+    fn ordering_operator(&mut self) -> Option<(OrderingOperatorNode, Span)> {
+        let operator = match self.peek().r#type {
+            TokenType::Comparator {
+                r#type: ComparatorType::Greater,
+                equal,
+            } => OrderingOperatorNode::Gt { equal },
+            TokenType::Comparator {
+                r#type: ComparatorType::Less,
+                equal,
+            } => OrderingOperatorNode::Lt { equal },
+            _ => return None,
+        };
+        let span = self.advance().span;
+        Some((operator, span))
+    }
+    fn term_operator(&mut self) -> Option<(ArithmeticOperator, Span)> {
+        let operator = match self.peek().r#type {
+            TokenType::Plus if self.stringify_concatenation => ArithmeticOperator::Concatenate,
+            TokenType::Plus => ArithmeticOperator::Plus,
+            TokenType::Minus => ArithmeticOperator::Minus,
+            _ => return None,
+        };
+        let span = self.advance().span;
+        Some((operator, span))
+    }
+    fn factor_operator(&mut self) -> Option<(ArithmeticOperator, Span)> {
+        let operator = match self.peek().r#type {
+            TokenType::Star => ArithmeticOperator::Times,
+            TokenType::Slash => ArithmeticOperator::Divides,
+            _ => return None,
+        };
+        let span = self.advance().span;
+        Some((operator, span))
+    }
+    fn unary_operator(&mut self) -> Option<(UnaryOperator, Span)> {
+        let operator = match self.peek().r#type {
+            TokenType::Comparator {
+                r#type: ComparatorType::Bang,
+                equal: false,
+            } => UnaryOperator::Not,
+            TokenType::Minus => UnaryOperator::Neg,
+            _ => return None,
+        };
+        let span = self.advance().span;
+        Some((operator, span))
+    }
+    fn r#match(&mut self, types: &[TokenType]) -> bool {
+        if types.iter().any(|r#type| self.check(*r#type)) {
+            self.advance();
+            true
+        } else {
+            false
+        }
+    }
+    fn consume(&mut self, r#type: TokenType, message: &str) -> Result<&Token, ParseError> {
+        if self.check(r#type) {
+            Ok(self.advance())
+        } else {
+            Err(ParseError::new(self.peek(), message))
+        }
+    }
+    /// Consumes the name being declared.
+    fn identifier(&mut self, message: &str) -> Result<Identifier, ParseError> {
+        let token = self.consume(TokenType::Identifier, message)?;
+        Ok(Identifier::new(&token.lexeme, token.span))
+    }
+    fn check(&self, r#type: TokenType) -> bool {
+        !self.is_at_end() && self.peek().r#type == r#type
+    }
+    fn advance(&mut self) -> &Token {
+        if !self.is_at_end() {
+            self.current += 1;
+        }
+        self.previous()
+    }
+    fn is_at_end(&self) -> bool {
+        self.peek().r#type == TokenType::Eof
+    }
+    fn peek(&self) -> &Token {
+        &self.tokens[self.current]
+    }
+    fn previous(&self) -> &Token {
+        &self.tokens[self.current.saturating_sub(1)]
+    }
+}
+
+#[cfg(test)]
+mod parser_tests {
+    use super::*;
+    use expression::{Environment, Expression};
+    fn parse(source: &str) -> Result<Node, ParseError> {
+        Parser::new(scanner::Scanner::new(source).scan_tokens().0).parse_expression()
+    }
+    fn parse_program(source: &str) -> Result<Vec<Statement>, Vec<ParseError>> {
+        Parser::new(scanner::Scanner::new(source).scan_tokens().0).parse()
+    }
+    #[test]
+    fn grouped_literal() {
+        let e = parse("((\"lox\"))")
+            .ok()
+            .and_then(|node| node.eval(&Environment::new()).ok());
+        assert_eq!(e, Some("lox".into()));
+    }
+    fn eval(source: &str) -> Option<expression::Value> {
+        parse(source)
+            .ok()
+            .and_then(|node| node.eval(&Environment::new()).ok())
+    }
+    #[test]
+    fn precedence() {
+        assert_eq!(eval("2 + 3 * 4"), Some(14.0.into()));
+        assert_eq!(eval("(2 + 3) * 4"), Some(20.0.into()));
+        assert_eq!(eval("-2 * 3 + 10 / 2"), Some((-1.0).into()));
+        assert_eq!(eval("1 + 2 < 4 == true"), Some(true.into()));
+        assert_eq!(eval("!(1 >= 2)"), Some(true.into()));
+    }
+    #[test]
+    fn literals() {
+        assert_eq!(eval("nil"), Some(expression::Value::Nil));
+        assert_eq!(eval("true"), Some(true.into()));
+        assert_eq!(eval("false"), Some(false.into()));
+        assert_eq!(eval("12.5"), Some(12.5.into()));
+        assert_eq!(eval("\"lox\""), Some("lox".into()));
+    }
+    #[test]
+    fn left_associativity() {
+        assert_eq!(eval("1 - 2 - 3"), Some((-4.0).into()));
+        assert_eq!(eval("8 / 4 / 2"), Some(1.0.into()));
+        assert_eq!(eval("1 == 1 != false"), Some(true.into()));
+    }
+    #[test]
+    fn right_associative_unary() {
+        assert_eq!(eval("- -3"), Some(3.0.into()));
+        assert_eq!(eval("!!true"), Some(true.into()));
+    }
+    #[test]
+    fn runtime_errors_point_at_the_operator() {
+        let error = parse("1 + (2 * -\"x\")")
+            .unwrap()
+            .eval(&Environment::new())
+            .unwrap_err();
+        assert_eq!(error.message, "Operand must be a number.");
+        assert_eq!(error.span.map(|span| span.range()), Some(9..10));
+        let error = parse("\"a\" - 1")
+            .unwrap()
+            .eval(&Environment::new())
+            .unwrap_err();
+        assert_eq!(error.message, "Operands must be numbers.");
+        assert_eq!(error.span.map(|span| span.range()), Some(4..5));
+    }
+    #[test]
+    fn stringify_concatenation_is_opt_in() {
+        let parse = |source, enabled| {
+            Parser::new(scanner::Scanner::new(source).scan_tokens().0)
+                .stringify_concatenation(enabled)
+                .parse_expression()
+                .ok()
+                .and_then(|node| node.eval(&Environment::new()).ok())
+        };
+        assert_eq!(parse("\"a\" + \"b\"", false), Some("ab".into()));
+        assert_eq!(parse("\"n\" + 1", false), None);
+        assert_eq!(parse("\"n\" + 1", true), Some("n1".into()));
+        assert_eq!(parse("true + \"!\"", true), Some("true!".into()));
+    }
+    #[test]
+    fn trailing_expression_is_opt_in() {
+        let parse = |source, enabled| {
+            Parser::new(scanner::Scanner::new(source).scan_tokens().0)
+                .trailing_expression(enabled)
+                .parse()
+                .map(|statements| statements.len())
+                .ok()
+        };
+        assert_eq!(parse("var a = 1; a", false), None);
+        assert_eq!(parse("var a = 1; a", true), Some(2));
+        assert_eq!(parse("1 + 2", true), Some(1));
+        assert_eq!(parse("a b", true), None);
+        assert_eq!(parse("print 1", true), None);
+    }
+    #[test]
+    fn deep_nesting_is_an_error() {
+        let nesting = |source: String| match parse_program(&source) {
+            Ok(_) => None,
+            Err(errors) => Some(errors[0].message.clone()),
+        };
+        let too_much = Some("Too much nesting.".to_string());
+        let parens = |n| format!("print {}1{};", "(".repeat(n), ")".repeat(n));
+        assert_eq!(nesting(parens(MAX_NESTING - 2)), None);
+        assert_eq!(nesting(parens(5000)), too_much);
+        let blocks = |n| "{".repeat(n) + &"}".repeat(n);
+        assert_eq!(nesting(blocks(MAX_NESTING)), None);
+        assert_eq!(nesting(blocks(20000)), too_much);
+        assert_eq!(nesting(format!("print {}1;", "-".repeat(200000))), too_much);
+        let chain = |n| format!("print 1{};", " + 1".repeat(n));
+        assert_eq!(nesting(chain(MAX_NESTING - 2)), None);
+        assert_eq!(nesting(chain(200000)), too_much);
+        assert_eq!(nesting(format!("f{};", "()".repeat(200000))), too_much);
+        // Links only count while their chain lasts
+        let terms = ["1 * 1 * 1"; MAX_NESTING / 2].join(" - ");
+        assert_eq!(nesting(format!("print {terms};")), None);
+    }
+    #[test]
+    fn missing_operand() {
+        assert!(parse("1 +").is_err());
+        assert!(parse("* 2").is_err());
+    }
+    #[test]
+    fn unclosed_grouping() {
+        assert!(parse("(true").is_err());
+    }
+    #[test]
+    fn statements() {
+        let statements = parse_program("var a = 1; var b; print 2; { 3; }").unwrap();
+        assert!(matches!(
+            statements.as_slice(),
+            [
+                Statement::Var {
+                    initializer: Some(_),
+                    ..
+                },
+                Statement::Var {
+                    initializer: None,
+                    ..
+                },
+                Statement::Print(_),
+                Statement::Block(_),
+            ]
+        ));
+    }
+    #[test]
+    fn dangling_else_binds_to_nearest_if() {
+        let statements = parse_program("if (true) if (false) 1; else 2;").unwrap();
+        let [Statement::If {
+            then_branch,
+            else_branch: None,
+            ..
+        }] = statements.as_slice()
+        else {
+            panic!("expected a single if-statement, got {statements:?}")
+        };
+        assert!(matches!(
+            **then_branch,
+            Statement::If {
+                else_branch: Some(_),
+                ..
+            }
+        ));
+    }
+    #[test]
+    fn for_desugars_to_while() {
+        let statements = parse_program("for (var i = 0; true; 1) print 2;").unwrap();
+        let [Statement::Block(block)] = statements.as_slice() else {
+            panic!("expected a single block, got {statements:?}")
+        };
+        let [Statement::Var { .. }, Statement::While { body, .. }] = block.as_slice() else {
+            panic!("expected an initializer and a loop, got {block:?}")
+        };
+        assert!(matches!(
+            body.as_ref(),
+            Statement::Block(inner)
+                if matches!(inner.as_slice(), [Statement::Print(_), Statement::Expression(_)])
+        ));
+        let statements = parse_program("for (;;) print 1;").unwrap();
+        assert!(matches!(statements.as_slice(), [Statement::While { .. }]));
+    }
+    #[test]
+    fn collects_every_error() {
+        let errors = parse_program("print 1; var = 2; print ; print 3;").unwrap_err();
+        assert_eq!(errors.len(), 2);
+    }
+    fn run(source: &str) -> Environment {
+        let environment = Environment::new();
+        for statement in parse_program(source).unwrap() {
+            statement.execute(&environment).unwrap();
+        }
+        environment
+    }
+    #[test]
+    fn assignment_is_right_associative() {
+        let environment = run("var a; var b; a = b = 1 + 2;");
+        assert_eq!(environment.get("a"), Ok(3.0.into()));
+        assert_eq!(environment.get("b"), Ok(3.0.into()));
+    }
+    #[test]
+    fn invalid_assignment_target() {
+        let error = parse("1 + a = 2").unwrap_err();
+        assert_eq!(error.message, "Invalid assignment target.");
+        assert_eq!(error.lexeme, "=");
+    }
+    #[test]
+    fn block_scoping_and_shadowing() {
+        let environment = run("var a = 1; var b = 0; { var a = a + 10; b = a; } var c = a;");
+        assert_eq!(environment.get("b"), Ok(11.0.into()));
+        assert_eq!(environment.get("c"), Ok(1.0.into()));
+    }
+    #[test]
+    fn for_loop_counts() {
+        let environment = run("var sum = 0; for (var i = 1; i <= 4; i = i + 1) sum = sum + i;");
+        assert_eq!(environment.get("sum"), Ok(10.0.into()));
+        assert!(!environment.is_defined("i"));
+    }
+    #[test]
+    fn undefined_variable_is_a_runtime_error() {
+        let environment = Environment::new();
+        let error = parse("1 + missing")
+            .unwrap()
+            .eval(&environment)
+            .unwrap_err();
+        assert_eq!(error.message, "Undefined variable 'missing'.");
+        assert_eq!(error.span.map(|span| span.range()), Some(4..11));
+    }
+    #[test]
+    fn logical_precedence() {
+        assert_eq!(eval("true or false and false"), Some(true.into()));
+        assert_eq!(eval("(true or false) and false"), Some(false.into()));
+        assert_eq!(eval("1 == 2 or 3"), Some(3.0.into()));
+        assert_eq!(eval("nil and 1 == 1"), Some(expression::Value::Nil));
+        assert_eq!(eval("\"a\" or \"b\" and \"c\""), Some("a".into()));
+    }
+    #[test]
+    fn logical_operators_short_circuit() {
+        let environment = run("var a = 0; true or (a = 1); false and (a = 2); var b = nil or a;");
+        assert_eq!(environment.get("a"), Ok(0.0.into()));
+        assert_eq!(environment.get("b"), Ok(0.0.into()));
+    }
+    #[test]
+    fn recursive_functions() {
+        let environment = run("
+            fun fib(n) {
+                if (n < 2) return n;
+                return fib(n - 2) + fib(n - 1);
+            }
+            var result = fib(10);
+        ");
+        assert_eq!(environment.get("result"), Ok(55.0.into()));
+    }
+    #[test]
+    fn closures_capture_their_environment() {
+        let environment = run("
+            fun makeCounter() {
+                var i = 0;
+                fun count() {
+                    i = i + 1;
+                    return i;
+                }
+                return count;
+            }
+            var counter = makeCounter();
+            counter();
+            var result = counter();
+        ");
+        assert_eq!(environment.get("result"), Ok(2.0.into()));
+        assert!(!environment.is_defined("i"));
+    }
+    #[test]
+    fn functions_without_return_give_nil() {
+        let environment = run("fun f() { 1; } var a = f(); fun g() { return; } var b = g();");
+        assert_eq!(environment.get("a"), Ok(expression::Value::Nil));
+        assert_eq!(environment.get("b"), Ok(expression::Value::Nil));
+    }
+    #[test]
+    fn arity_mismatch_points_at_the_call() {
+        let environment = run("fun f(a, b) {}");
+        let error = parse("f(1)").unwrap().eval(&environment).unwrap_err();
+        assert_eq!(error.message, "Expected 2 arguments but got 1.");
+        assert_eq!(error.span.map(|span| span.range()), Some(3..4));
+    }
+    #[test]
+    fn argument_limit() {
+        let arguments = vec!["1"; 256].join(", ");
+        let error = parse(&format!("f({arguments})")).unwrap_err();
+        assert_eq!(error.message, "Can't have more than 255 arguments.");
+        let params = (0..256).map(|i| format!("p{i}")).collect::<Vec<_>>();
+        let errors = parse_program(&format!("fun f({}) {{}}", params.join(", "))).unwrap_err();
+        assert_eq!(errors[0].message, "Can't have more than 255 parameters.");
+        assert!(parse(&format!("f({})", vec!["1"; 255].join(", "))).is_ok());
+    }
+    #[test]
+    fn fields_and_methods() {
+        let environment = run("
+            class Point {
+                init(x, y) {
+                    this.x = x;
+                    this.y = y;
+                }
+                sum() { return this.x + this.y; }
+            }
+            var p = Point(1, 2);
+            p.y = 10;
+            var sum = p.sum();
+            var method = p.sum;
+            p.x = 5;
+            var bound = method();
+        ");
+        assert_eq!(environment.get("sum"), Ok(11.0.into()));
+        assert_eq!(environment.get("bound"), Ok(15.0.into()));
+        assert_eq!(environment.get("p").unwrap().to_string(), "Point instance");
+        assert_eq!(environment.get("Point").unwrap().to_string(), "Point");
+    }
+    #[test]
+    fn initializer_returns_the_instance() {
+        let environment = run("
+            class A { init() { this.a = 1; return; } }
+            var a = A();
+            var same = a.init() == a;
+        ");
+        assert_eq!(environment.get("same"), Ok(true.into()));
+    }
+    #[test]
+    fn inheritance_and_super() {
+        let environment = run("
+            class A {
+                name() { return \"A\"; }
+                greet() { return \"I am \" + this.name(); }
+            }
+            class B < A {
+                name() { return \"B, not \" + super.name(); }
+            }
+            var greeting = B().greet();
+        ");
+        assert_eq!(environment.get("greeting"), Ok("I am B, not A".into()));
+    }
+    #[test]
+    fn class_misuse() {
+        let message = |source: &str| {
+            let environment = Environment::new();
+            parse_program(source)
+                .unwrap()
+                .iter()
+                .try_for_each(|statement| statement.execute(&environment))
+                .unwrap_err()
+                .message
+        };
+        assert_eq!(message("1.x;"), "Only instances have properties.");
+        assert_eq!(message("\"s\".x = 1;"), "Only instances have fields.");
+        assert_eq!(message("class A {} A().x;"), "Undefined property 'x'.");
+        assert_eq!(
+            message("var B = 1; class A < B {}"),
+            "Superclass must be a class."
+        );
+        assert_eq!(
+            message("class A {} A(1);"),
+            "Expected 0 arguments but got 1."
+        );
+        assert_eq!(
+            message("class A {} class B < A { f() { super.f(); } } B().f();"),
+            "Undefined property 'f'."
+        );
+    }
+    #[test]
+    fn invalid_property_syntax() {
+        assert_eq!(
+            parse_program("a.;").unwrap_err()[0].message,
+            "Expect property name after '.'."
+        );
+        assert_eq!(
+            parse_program("super;").unwrap_err()[0].message,
+            "Expect '.' after 'super'."
+        );
+    }
+}
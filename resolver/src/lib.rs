@@ -0,0 +1,477 @@
+use core::fmt;
+use diagnostics::{Diagnostic, ToDiagnostic};
+use expression::{function::FunctionDeclaration, Identifier, Node, Statement, Variable};
+use std::collections::HashMap;
+use token::Span;
+
+/// How deep the resolver may walk into the syntax tree, so that it can't
+/// overflow the stack on trees the parser had no reason to refuse.
+pub const MAX_DEPTH: usize = 1024;
+
+#[derive(Debug)]
+pub struct ResolveError {
+    pub span: Span,
+    pub lexeme: String,
+    pub message: String,
+}
+impl ResolveError {
+    fn new(span: Span, lexeme: impl ToString, message: impl ToString) -> Self {
+        Self {
+            span,
+            lexeme: lexeme.to_string(),
+            message: message.to_string(),
+        }
+    }
+}
+impl fmt::Display for ResolveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_fmt(format_args!(
+            "[line {}] Error at '{}': {}",
+            self.span, self.lexeme, self.message
+        ))
+    }
+}
+impl std::error::Error for ResolveError {}
+impl ToDiagnostic for ResolveError {
+    fn to_diagnostic(&self) -> Diagnostic {
+        Diagnostic::error(&self.message).at(self.span)
+    }
+}
+/// The kind of function whose body is being resolved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum FunctionType {
+    #[default]
+    None,
+    Function,
+    Initializer,
+    Method,
+}
+/// The kind of class whose body is being resolved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum ClassType {
+    #[default]
+    None,
+    Class,
+    Subclass,
+}
+/// Works out which declaration every variable refers to before the
+/// program runs, so that closures keep seeing the variables they were
+/// declared next to, and reports the mistakes that can be caught
+/// without running anything.
+#[derive(Debug, Default)]
+pub struct Resolver {
+    /// The local scopes, innermost last, mapping each name to whether
+    /// its initializer has finished
+    scopes: Vec<HashMap<String, bool>>,
+    function: FunctionType,
+    class: ClassType,
+    errors: Vec<ResolveError>,
+    /// How deep the resolver currently is, up to [`MAX_DEPTH`]
+    depth: usize,
+    /// Where the last name or operator resolved appears, to locate
+    /// errors in nodes that don't carry a span of their own
+    span: Span,
+}
+impl Resolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Resolves a whole program, collecting every error encountered.
+    pub fn resolve(&mut self, statements: &[Statement]) -> Result<(), Vec<ResolveError>> {
+        statements
+            .iter()
+            .for_each(|statement| self.statement(statement));
+        self.finish()
+    }
+    /// Resolves a single expression, as evaluated at the top level.
+    pub fn resolve_expression(&mut self, expression: &Node) -> Result<(), Vec<ResolveError>> {
+        self.expression(expression);
+        self.finish()
+    }
+    fn finish(&mut self) -> Result<(), Vec<ResolveError>> {
+        match std::mem::take(&mut self.errors) {
+            errors if errors.is_empty() => Ok(()),
+            errors => Err(errors),
+        }
+    }
+    fn statement(&mut self, statement: &Statement) {
+        self.nested(|resolver| resolver.nested_statement(statement));
+    }
+    fn nested_statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Expression(expression) | Statement::Print(expression) => {
+                self.expression(expression)
+            }
+            Statement::Var { name, initializer } => {
+                self.declare(name);
+                if let Some(initializer) = initializer {
+                    self.expression(initializer);
+                }
+                self.define(&name.lexeme);
+            }
+            Statement::Block(statements) => {
+                self.begin_scope();
+                statements
+                    .iter()
+                    .for_each(|statement| self.statement(statement));
+                self.end_scope();
+            }
+            Statement::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.expression(condition);
+                self.statement(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.statement(else_branch);
+                }
+            }
+            Statement::While { condition, body } => {
+                self.expression(condition);
+                self.statement(body);
+            }
+            Statement::Function(declaration) => {
+                self.declare(&declaration.name);
+                self.define(&declaration.name.lexeme);
+                self.function(declaration, FunctionType::Function);
+            }
+            Statement::Class(declaration) => {
+                let enclosing = std::mem::replace(&mut self.class, ClassType::Class);
+                self.declare(&declaration.name);
+                self.define(&declaration.name.lexeme);
+                if let Some(superclass) = &declaration.superclass {
+                    if superclass.name() == declaration.name.lexeme {
+                        self.error(
+                            superclass.span(),
+                            superclass.name(),
+                            "A class can't inherit from itself.",
+                        );
+                    }
+                    self.class = ClassType::Subclass;
+                    self.resolve_local(superclass);
+                    self.begin_scope();
+                    self.define("super");
+                }
+                self.begin_scope();
+                self.define("this");
+                for method in &declaration.methods {
+                    let kind = if method.name.lexeme == "init" {
+                        FunctionType::Initializer
+                    } else {
+                        FunctionType::Method
+                    };
+                    self.function(method, kind);
+                }
+                self.end_scope();
+                if declaration.superclass.is_some() {
+                    self.end_scope();
+                }
+                self.class = enclosing;
+            }
+            Statement::Return { value, span } => {
+                if self.function == FunctionType::None {
+                    self.error(*span, "return", "Can't return from top-level code.");
+                }
+                if let Some(value) = value {
+                    if self.function == FunctionType::Initializer {
+                        self.error(*span, "return", "Can't return a value from an initializer.");
+                    }
+                    self.expression(value);
+                }
+            }
+        }
+    }
+    fn function(&mut self, declaration: &FunctionDeclaration, kind: FunctionType) {
+        let enclosing = std::mem::replace(&mut self.function, kind);
+        self.begin_scope();
+        for param in &declaration.params {
+            self.declare(param);
+            self.define(&param.lexeme);
+        }
+        declaration
+            .body
+            .iter()
+            .for_each(|statement| self.statement(statement));
+        self.end_scope();
+        self.function = enclosing;
+    }
+    fn expression(&mut self, expression: &Node) {
+        self.nested(|resolver| resolver.nested_expression(expression));
+    }
+    /// Runs `resolve` a level deeper, reporting an error instead the
+    /// first time that would be deeper than [`MAX_DEPTH`].
+    fn nested(&mut self, resolve: impl FnOnce(&mut Self)) {
+        if self.depth == MAX_DEPTH {
+            let message = "Too much nesting.";
+            if self
+                .errors
+                .last()
+                .is_none_or(|error| error.message != message)
+            {
+                self.error(self.span, "", message);
+            }
+            return;
+        }
+        self.depth += 1;
+        resolve(self);
+        self.depth -= 1;
+    }
+    fn nested_expression(&mut self, expression: &Node) {
+        match expression {
+            Node::Literal(_) => {}
+            Node::Unary(unary) => {
+                self.span = unary.span();
+                self.expression(unary.operand())
+            }
+            Node::Binary(binary) => {
+                self.span = binary.span();
+                let (a, b) = binary.operands();
+                self.expression(a);
+                self.expression(b);
+            }
+            Node::Logical(logical) => {
+                let (a, b) = logical.operands();
+                self.expression(a);
+                self.expression(b);
+            }
+            Node::Grouping(expression) => self.expression(expression),
+            Node::Variable(variable) => {
+                let scope = self.scopes.last();
+                if scope.and_then(|scope| scope.get(variable.name())) == Some(&false) {
+                    self.error(
+                        variable.span(),
+                        variable.name(),
+                        "Can't read local variable in its own initializer.",
+                    );
+                }
+                self.resolve_local(variable);
+            }
+            Node::Assign(assignment) => {
+                self.expression(assignment.value());
+                self.resolve_local(assignment.variable());
+            }
+            Node::Call(call) => {
+                self.expression(call.callee());
+                call.arguments()
+                    .iter()
+                    .for_each(|argument| self.expression(argument));
+            }
+            Node::Get(get) => self.expression(get.object()),
+            Node::Set(set) => {
+                self.expression(set.value());
+                self.expression(set.object());
+            }
+            Node::This(this) => {
+                if self.class == ClassType::None {
+                    self.error(this.span(), "this", "Can't use 'this' outside of a class.");
+                    return;
+                }
+                self.resolve_local(this);
+            }
+            Node::Super(expression) => {
+                let keyword = expression.keyword();
+                match self.class {
+                    ClassType::None => self.error(
+                        keyword.span(),
+                        "super",
+                        "Can't use 'super' outside of a class.",
+                    ),
+                    ClassType::Class => self.error(
+                        keyword.span(),
+                        "super",
+                        "Can't use 'super' in a class with no superclass.",
+                    ),
+                    ClassType::Subclass => self.resolve_local(keyword),
+                }
+            }
+        }
+    }
+    fn begin_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+    fn end_scope(&mut self) {
+        self.scopes.pop();
+    }
+    /// Adds `name` to the innermost scope, not yet usable until its
+    /// initializer has been resolved.
+    fn declare(&mut self, name: &Identifier) {
+        self.span = name.span;
+        let Some(scope) = self.scopes.last_mut() else {
+            return;
+        };
+        if scope.insert(name.lexeme.clone(), false).is_some() {
+            self.error(
+                name.span,
+                &name.lexeme,
+                "Already a variable with this name in this scope.",
+            );
+        }
+    }
+    fn define(&mut self, name: &str) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string(), true);
+        }
+    }
+    /// Binds `variable` to the innermost scope declaring it, or to the
+    /// globals if no local scope does.
+    fn resolve_local(&mut self, variable: &Variable) {
+        self.span = variable.span();
+        let depth = self
+            .scopes
+            .iter()
+            .rev()
+            .position(|scope| scope.contains_key(variable.name()));
+        match depth {
+            Some(depth) => variable.resolve_local(depth),
+            None => variable.resolve_global(),
+        }
+    }
+    fn error(&mut self, span: Span, lexeme: impl ToString, message: &str) {
+        self.errors.push(ResolveError::new(span, lexeme, message));
+    }
+}
+
+#[cfg(test)]
+mod resolver_tests {
+    use super::*;
+    use expression::Environment;
+    fn parse(source: &str) -> Vec<Statement> {
+        let (tokens, errors) = scanner::Scanner::new(source).scan_tokens();
+        assert!(errors.is_empty());
+        parser::Parser::new(tokens).parse().unwrap()
+    }
+    fn errors(source: &str) -> Vec<(String, std::ops::Range<usize>)> {
+        Resolver::new()
+            .resolve(&parse(source))
+            .unwrap_err()
+            .into_iter()
+            .map(|error| (error.message, error.span.range()))
+            .collect()
+    }
+    fn run(source: &str) -> Environment {
+        let statements = parse(source);
+        Resolver::new().resolve(&statements).unwrap();
+        let environment = Environment::new();
+        for statement in &statements {
+            statement.execute(&environment).unwrap();
+        }
+        environment
+    }
+    #[test]
+    fn closures_bind_where_they_are_declared() {
+        let environment = run("
+            var a = \"global\";
+            var first;
+            var second;
+            {
+                fun show() { return a; }
+                first = show();
+                var a = \"block\";
+                second = show();
+            }
+        ");
+        assert_eq!(environment.get("first"), Ok("global".into()));
+        assert_eq!(environment.get("second"), Ok("global".into()));
+    }
+    #[test]
+    fn super_skips_to_the_superclass() {
+        let environment = run("
+            class A { method() { return \"A\"; } }
+            class B < A { method() { return \"B\"; } test() { return super.method(); } }
+            class C < B {}
+            var result = C().test();
+        ");
+        assert_eq!(environment.get("result"), Ok("A".into()));
+    }
+    #[test]
+    fn own_initializer() {
+        assert_eq!(
+            errors("{ var a = 1; { var a = a; } }"),
+            [(
+                "Can't read local variable in its own initializer.".into(),
+                23..24
+            )]
+        );
+        // Globals may refer to themselves, as they are late bound
+        assert!(Resolver::new().resolve(&parse("var a = a;")).is_ok());
+    }
+    #[test]
+    fn redeclaration() {
+        assert_eq!(
+            errors("fun f(a, a) { var b; var b; }"),
+            [
+                (
+                    "Already a variable with this name in this scope.".into(),
+                    9..10
+                ),
+                (
+                    "Already a variable with this name in this scope.".into(),
+                    25..26
+                ),
+            ]
+        );
+        assert!(Resolver::new().resolve(&parse("var a; var a;")).is_ok());
+    }
+    #[test]
+    fn misplaced_return() {
+        assert_eq!(
+            errors("return 1;"),
+            [("Can't return from top-level code.".into(), 0..6)]
+        );
+        assert_eq!(
+            errors("class A { init() { return 1; } }"),
+            [("Can't return a value from an initializer.".into(), 19..25)]
+        );
+        assert!(Resolver::new()
+            .resolve(&parse("class A { init() { return; } }"))
+            .is_ok());
+    }
+    #[test]
+    fn misplaced_this_and_super() {
+        assert_eq!(
+            errors("print this; fun f() { this; }"),
+            [
+                ("Can't use 'this' outside of a class.".into(), 6..10),
+                ("Can't use 'this' outside of a class.".into(), 22..26),
+            ]
+        );
+        assert_eq!(
+            errors("super.f();"),
+            [("Can't use 'super' outside of a class.".into(), 0..5)]
+        );
+        assert_eq!(
+            errors("class A { f() { super.f(); } }"),
+            [(
+                "Can't use 'super' in a class with no superclass.".into(),
+                16..21
+            )]
+        );
+        assert_eq!(
+            errors("class A < A {}"),
+            [("A class can't inherit from itself.".into(), 10..11)]
+        );
+    }
+    #[test]
+    fn deep_nesting_is_an_error() {
+        let nested = |depth| {
+            let mut statements = parse("var a; a;");
+            let Some(Statement::Expression(mut expression)) = statements.pop() else {
+                unreachable!()
+            };
+            for _ in 0..depth {
+                expression = Node::Grouping(Box::new(expression));
+            }
+            statements.push(Statement::Print(expression));
+            Resolver::new().resolve(&statements)
+        };
+        // The statement takes a level, as does the variable itself
+        assert!(nested(MAX_DEPTH - 2).is_ok());
+        let errors: Vec<_> = nested(MAX_DEPTH * 4)
+            .unwrap_err()
+            .into_iter()
+            .map(|error| (error.message, error.span.range()))
+            .collect();
+        assert_eq!(errors, [("Too much nesting.".into(), 4..5)]);
+    }
+}
@@ -0,0 +1,52 @@
+use core::fmt;
+use diagnostics::{Diagnostic, ToDiagnostic};
+use token::Span;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScanErrorKind {
+    UnexpectedCharacter,
+    UnterminatedString,
+}
+impl fmt::Display for ScanErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::UnexpectedCharacter => "Unexpected character",
+            Self::UnterminatedString => "Unterminated string",
+        })
+    }
+}
+/// A problem found while scanning, located by the line and column
+/// (both starting at 1) of the first offending character.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScanError {
+    pub kind: ScanErrorKind,
+    pub line: usize,
+    pub column: usize,
+    /// The source text the error covers.
+    pub text: String,
+    pub span: Span,
+}
+impl fmt::Display for ScanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            ScanErrorKind::UnexpectedCharacter => f.write_fmt(format_args!(
+                "[line {}:{}] Error: {} '{}'.",
+                self.line, self.column, self.kind, self.text
+            )),
+            ScanErrorKind::UnterminatedString => f.write_fmt(format_args!(
+                "[line {}:{}] Error: {}.",
+                self.line, self.column, self.kind
+            )),
+        }
+    }
+}
+impl std::error::Error for ScanError {}
+impl ToDiagnostic for ScanError {
+    fn to_diagnostic(&self) -> Diagnostic {
+        let message = match self.kind {
+            ScanErrorKind::UnexpectedCharacter => format!("{} '{}'.", self.kind, self.text),
+            ScanErrorKind::UnterminatedString => format!("{}.", self.kind),
+        };
+        Diagnostic::error(message).at(self.span)
+    }
+}
@@ -1,22 +1,30 @@
 pub fn add(left: u64, right: u64) -> u64 {
     left + right
 }
-use convert::MaybeInto;
 use core::str;
 use std::sync::atomic::AtomicUsize;
 use token::{
-    KeywordType, LiteralValue, StringIdentifier, StringValue, Token, TokenCharacter, TokenType,
+    KeywordType, LiteralValue, Location, Span, StringValue, Token, TokenCharacter, TokenType,
 };
+mod error;
+pub use error::{ScanError, ScanErrorKind};
 
 #[derive(Default, Debug)]
 pub struct Indices {
     pub start: usize,
     pub current: AtomicUsize,
     pub line: AtomicUsize,
+    /// Where the current line begins
+    pub line_start: AtomicUsize,
+    /// Where the current token begins
+    pub start_location: Location,
 }
 pub struct TokenSource {
     pub source: String,
     pub chars: Vec<char>,
+    /// The byte offset of each character, followed by the length of the
+    /// source, as the indices count characters rather than bytes.
+    pub offsets: Vec<usize>,
     pub indices: Indices,
 }
 fn is_digit(c: &char) -> bool {
@@ -28,42 +36,101 @@ fn is_alpha(c: &char) -> bool {
 fn is_alpha_numeric(c: &char) -> bool {
     is_digit(c) || is_alpha(c)
 }
-fn evaluate_keyword(s: &str) -> Result<KeywordType, String> {
-    s.maybe_into().ok_or(s.into())
-}
 impl TokenSource {
     fn new(source: &impl ToString) -> Self {
         let source = source.to_string();
         let chars = source.chars().collect();
+        let offsets = source
+            .char_indices()
+            .map(|(offset, _)| offset)
+            .chain([source.len()])
+            .collect();
         Self {
             source,
             chars,
-            indices: Default::default(),
+            offsets,
+            indices: Indices {
+                line: AtomicUsize::new(1),
+                ..Default::default()
+            },
         }
     }
-    fn make_token(&self, r#type: TokenType, literal: Option<LiteralValue>) -> Option<Token> {
+    /// Moves on to the next line, which begins at `line_start`.
+    fn newline(&self, line_start: usize) -> usize {
+        self.indices
+            .line_start
+            .store(line_start, std::sync::atomic::Ordering::Release);
+        self.indices
+            .line
+            .fetch_add(1, std::sync::atomic::Ordering::Acquire)
+            + 1
+    }
+    /// The column of `index` on the current line, starting at 1.
+    fn column(&self, index: usize) -> usize {
+        index
+            - self
+                .indices
+                .line_start
+                .load(std::sync::atomic::Ordering::Acquire)
+            + 1
+    }
+    /// The location of the character at `index` on the current line.
+    fn location(&self, index: usize) -> Location {
+        let index = index.min(self.chars.len());
+        Location {
+            offset: self.offsets[index],
+            line: self.indices.line.load(std::sync::atomic::Ordering::Acquire),
+            column: self.column(index),
+        }
+    }
+    /// Starts a new token at the current character.
+    fn begin_token(&mut self) {
+        self.indices.start = self
+            .indices
+            .current
+            .load(std::sync::atomic::Ordering::Acquire);
+        self.indices.start_location = self.location(self.indices.start);
+    }
+    /// The span from the start of the current token up to the current
+    /// character.
+    fn span(&self) -> Span {
+        Span::new(
+            self.indices.start_location,
+            self.location(
+                self.indices
+                    .current
+                    .load(std::sync::atomic::Ordering::Acquire),
+            ),
+        )
+    }
+    /// The source text between the characters at `start` and `end`.
+    fn text(&self, start: usize, end: usize) -> Option<&str> {
+        let end = end.min(self.chars.len());
         self.source
-            .get(
-                self.indices.start
-                    ..self
-                        .indices
-                        .current
-                        .load(std::sync::atomic::Ordering::Acquire),
-            )
-            .map(|lexeme| {
-                Token::new(
-                    r#type,
-                    lexeme,
-                    literal,
-                    self.indices.line.load(std::sync::atomic::Ordering::Acquire),
-                )
-            })
+            .get(*self.offsets.get(start)?..*self.offsets.get(end)?)
+    }
+    /// The source text of the current token.
+    fn lexeme(&self) -> Option<&str> {
+        self.text(
+            self.indices.start,
+            self.indices
+                .current
+                .load(std::sync::atomic::Ordering::Acquire),
+        )
     }
-    fn add_token(&self, tokens: &mut Vec<Token>, r#type: TokenType, literal: Option<LiteralValue>) {
-        if let Some(token) = self.make_token(r#type, literal) {
-            tokens.push(token)
+    fn error(&self, kind: ScanErrorKind) -> ScanError {
+        ScanError {
+            kind,
+            line: self.indices.start_location.line,
+            column: self.indices.start_location.column,
+            text: self.lexeme().unwrap_or_default().into(),
+            span: self.span(),
         }
     }
+    fn make_token(&self, r#type: TokenType, literal: Option<LiteralValue>) -> Option<Token> {
+        self.lexeme()
+            .map(|lexeme| Token::new(r#type, lexeme, literal, self.span()))
+    }
     pub fn advance(&self) -> Option<&char> {
         self.chars.get(
             self.indices
@@ -120,41 +187,39 @@ impl TokenSource {
         }
     }
 
-    pub fn string(&self) -> Option<Token> {
+    pub fn string(&self) -> Option<Result<Token, ScanError>> {
         let mut _current;
         let mut _previous;
-        let mut line = 0;
         while {
             _current = self.peek();
             _current.is_some_and(|c| c.ne(&'"')) && !self.is_at_end()
         } {
             if _current.is_some_and(|c| c.eq(&'\n')) {
-                line = self
-                    .indices
-                    .line
-                    .fetch_add(1, std::sync::atomic::Ordering::Acquire)
-                    + 1;
+                self.newline(
+                    self.indices
+                        .current
+                        .load(std::sync::atomic::Ordering::Acquire)
+                        + 1,
+                );
             }
             _previous = self.advance();
         }
 
         if self.is_at_end() {
-            eprintln!("{} Unterminated string", line);
-            return None;
+            return Some(Err(self.error(ScanErrorKind::UnterminatedString)));
         }
 
         // The closing "
         _previous = _current;
         _current = self.advance();
 
-        let literal_string = self.source.get(
+        let literal_string = self.text(
             // Trim the surrounding quoates
-            self.indices.start + 1
-                ..self
-                    .indices
-                    .current
-                    .load(std::sync::atomic::Ordering::Acquire)
-                    - 1,
+            self.indices.start + 1,
+            self.indices
+                .current
+                .load(std::sync::atomic::Ordering::Acquire)
+                - 1,
         );
 
         let make_token = |s: &str| {
@@ -163,7 +228,7 @@ impl TokenSource {
                 Some(LiteralValue::String(StringValue(s.into()))),
             )
         };
-        literal_string.and_then(make_token)
+        literal_string.and_then(make_token).map(Ok)
     }
     pub fn identifier(&self) -> Option<Token> {
         let mut _current;
@@ -174,13 +239,7 @@ impl TokenSource {
         } {
             _previous = self.advance();
         }
-        let text = self.source.get(
-            self.indices.start
-                ..self
-                    .indices
-                    .current
-                    .load(std::sync::atomic::Ordering::Acquire),
-        );
+        let text = self.lexeme();
         match text
             .map(KeywordType::try_from)
             .transpose()
@@ -218,18 +277,15 @@ impl TokenSource {
             // Consume the '.'
             _previous = _current;
             _current = self.advance();
+            // And the fractional part
+            while self.peek().is_some_and(|c| c.is_numeric()) {
+                _previous = self.advance();
+            }
         }
         let literal = self
-            .source
-            .get(
-                self.indices.start
-                    ..self
-                        .indices
-                        .current
-                        .load(std::sync::atomic::Ordering::Acquire),
-            )
+            .lexeme()
             .and_then(|sub| {
-                let sub: Result<f32, <f32 as str::FromStr>::Err> = sub.parse();
+                let sub: Result<f64, <f64 as str::FromStr>::Err> = sub.parse();
                 sub.ok()
             })
             .map(LiteralValue::Number);
@@ -241,8 +297,10 @@ pub struct Tokenizer {
     pub indices: Indices,
     pub len: usize,
 }
+/// Yields `None` for input that produces no token, such as whitespace
+/// and comments, so the end of input is signalled by the source.
 impl Iterator for Tokenizer {
-    type Item = Token;
+    type Item = Result<Token, ScanError>;
     fn next(&mut self) -> Option<Self::Item> {
         // let line = self
         //     .source
@@ -252,10 +310,11 @@ impl Iterator for Tokenizer {
         let next = self.source.advance()?;
         let next = TokenCharacter::try_from(next);
         match next {
-            Ok(TokenCharacter::Type(r#type)) => self.source.make_token(r#type, None),
+            Ok(TokenCharacter::Type(r#type)) => self.source.make_token(r#type, None).map(Ok),
             Ok(TokenCharacter::Comparator(comparator)) => self
                 .source
-                .make_token(comparator.r#match(self.source.r#match('=')), None),
+                .make_token(comparator.r#match(self.source.r#match('=')), None)
+                .map(Ok),
             Ok(TokenCharacter::Delimiter(delimiter)) => match delimiter {
                 token::DelimiterType::Comment => {
                     if self.source.r#match('/') {
@@ -269,33 +328,27 @@ impl Iterator for Tokenizer {
                         }
                         None
                     } else {
-                        self.source.make_token(TokenType::Slash, None)
+                        self.source.make_token(TokenType::Slash, None).map(Ok)
                     }
                 }
                 token::DelimiterType::Literal(literal) => match literal {
                     token::LiteralType::String => self.source.string(),
-                    token::LiteralType::Number => self.source.number(),
-                    token::LiteralType::Identifier => self.source.identifier(),
+                    token::LiteralType::Number => self.source.number().map(Ok),
+                    token::LiteralType::Identifier => self.source.identifier().map(Ok),
                 },
             },
             Ok(TokenCharacter::Newline) => {
-                self.source
-                    .indices
-                    .line
-                    .fetch_add(1, std::sync::atomic::Ordering::Acquire);
-                None
-            }
-            Ok(_) => None,
-            Err(token::Unexpected(unexpected)) => {
-                eprintln!(
-                    "{} Unexpected character: {}",
+                self.source.newline(
                     self.source
                         .indices
-                        .line
+                        .current
                         .load(std::sync::atomic::Ordering::Acquire),
-                    unexpected
                 );
                 None
+            }
+            Ok(_) => None,
+            Err(token::Unexpected(_)) => {
+                Some(Err(self.source.error(ScanErrorKind::UnexpectedCharacter)))
             } // '!' => self.source.make_token(
               //     self.source
               //         .r#match('=')
@@ -380,7 +433,7 @@ impl Tokenizer {
             len,
         }
     }
-    pub fn scan_token(&mut self, tokens: &mut Vec<Token>) {
+    pub fn scan_token(&mut self, tokens: &mut Vec<Token>, errors: &mut Vec<ScanError>) {
         // if let Some(next) = self.source.advance() {
         //     match next {
         //         '(' => {
@@ -480,8 +533,10 @@ impl Tokenizer {
         //         }
         //     }
         // };
-        if let Some(token) = self.next() {
-            tokens.push(token)
+        match self.next() {
+            Some(Ok(token)) => tokens.push(token),
+            Some(Err(error)) => errors.push(error),
+            None => {}
         }
     }
     // fn advance(&mut self) -> Option<&char> {
@@ -559,31 +614,27 @@ impl Scanner {
     //         None => {}
     //     }
     // }
-    pub fn scan_tokens(&self) -> Vec<Token> {
+    /// Scans the whole source, collecting the errors rather than stopping
+    /// at the first. The tokens always end with an EOF-token.
+    pub fn scan_tokens(&self) -> (Vec<Token>, Vec<ScanError>) {
         let mut tokens = vec![];
+        let mut errors = vec![];
         let mut tokenizer = Tokenizer::new(&self.source);
         while !tokenizer.source.is_at_end() {
-            tokenizer.source.indices.start = tokenizer
-                .source
-                .indices
-                .current
-                .load(std::sync::atomic::Ordering::Acquire);
-            tokenizer.scan_token(&mut tokens);
+            tokenizer.source.begin_token();
+            tokenizer.scan_token(&mut tokens, &mut errors);
         }
+        tokenizer.source.begin_token();
         tokens.push(Token::new(
             token::TokenType::Eof,
             "",
             None,
-            tokenizer
-                .source
-                .indices
-                .line
-                .load(std::sync::atomic::Ordering::Acquire),
+            tokenizer.source.span(),
         ));
         // tokenizer
         //     .source
         //     .add_token(&mut tokens, token::TokenType::Eof, None);
-        tokens
+        (tokens, errors)
     }
 }
 
@@ -595,8 +646,106 @@ mod tests {
     fn it_works() {
         let source = "var language = \"lox\";";
         let scanner = Scanner::new(source);
-        let tokens = scanner.scan_tokens();
+        let (tokens, errors) = scanner.scan_tokens();
         assert!(!tokens.is_empty());
+        assert!(errors.is_empty());
         tokens.iter().for_each(|token| println!("{token}"))
     }
+    #[test]
+    fn fractional_number() {
+        let (tokens, _) = Scanner::new("1.5").scan_tokens();
+        assert_eq!(tokens.len(), 2);
+        assert_eq!(tokens[0].lexeme, "1.5");
+    }
+    #[test]
+    fn unexpected_characters() {
+        let (tokens, errors) = Scanner::new("1 +\n  @ 2 #").scan_tokens();
+        assert_eq!(tokens.len(), 4);
+        assert_eq!(
+            errors,
+            vec![
+                ScanError {
+                    kind: ScanErrorKind::UnexpectedCharacter,
+                    line: 2,
+                    column: 3,
+                    text: "@".into(),
+                    span: Span::new(
+                        Location {
+                            offset: 6,
+                            line: 2,
+                            column: 3,
+                        },
+                        Location {
+                            offset: 7,
+                            line: 2,
+                            column: 4,
+                        },
+                    ),
+                },
+                ScanError {
+                    kind: ScanErrorKind::UnexpectedCharacter,
+                    line: 2,
+                    column: 7,
+                    text: "#".into(),
+                    span: Span::new(
+                        Location {
+                            offset: 10,
+                            line: 2,
+                            column: 7,
+                        },
+                        Location {
+                            offset: 11,
+                            line: 2,
+                            column: 8,
+                        },
+                    ),
+                },
+            ]
+        );
+    }
+    #[test]
+    fn unterminated_string() {
+        let (tokens, errors) = Scanner::new("print \"lox\n;").scan_tokens();
+        assert_eq!(tokens.len(), 2);
+        let [error] = errors.as_slice() else {
+            panic!("expected a single error, got {errors:?}")
+        };
+        assert_eq!(error.kind, ScanErrorKind::UnterminatedString);
+        assert_eq!((error.line, error.column), (1, 7));
+        assert_eq!(error.text, "\"lox\n;");
+        assert_eq!(error.span.range(), 6..12);
+    }
+    #[test]
+    fn lines_start_at_one() {
+        let (tokens, _) = Scanner::new("a\n\"b\nc\"\nd").scan_tokens();
+        let lines: Vec<usize> = tokens.iter().map(Token::line).collect();
+        assert_eq!(lines, vec![1, 2, 4, 4]);
+    }
+    #[test]
+    fn token_spans() {
+        let (tokens, _) = Scanner::new("var x =\n  \"é\" >= 10.5;").scan_tokens();
+        let spans: Vec<(std::ops::Range<usize>, String, String)> = tokens
+            .iter()
+            .map(|token| {
+                (
+                    token.span.range(),
+                    token.span.start.to_string(),
+                    token.span.end.to_string(),
+                )
+            })
+            .collect();
+        let expected = [
+            (0..3, "1:1", "1:4"),
+            (4..5, "1:5", "1:6"),
+            (6..7, "1:7", "1:8"),
+            (10..14, "2:3", "2:6"),
+            (15..17, "2:7", "2:9"),
+            (18..22, "2:10", "2:14"),
+            (22..23, "2:14", "2:15"),
+            (23..23, "2:15", "2:15"),
+        ]
+        .map(|(range, start, end)| (range, start.to_string(), end.to_string()));
+        assert_eq!(spans, expected);
+        assert_eq!(tokens[3].lexeme, "\"é\"");
+    }
 }
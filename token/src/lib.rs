@@ -1,10 +1,12 @@
 use convert::{MaybeFrom, MaybeInto};
 use core::fmt;
 use std::ops;
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Direction {
     Left,
     Right,
 }
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum TokenType {
     // Single-character tokens
     Paren(Direction),
@@ -29,6 +31,7 @@ pub enum TokenType {
 
     Eof,
 }
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ComparatorType {
     Bang,
     Equal,
@@ -57,6 +60,7 @@ impl ComparatorType {
 // pub enum LiteralType {
 
 // }
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum KeywordType {
     And,
     Class,
@@ -77,7 +81,7 @@ pub enum KeywordType {
 }
 impl MaybeFrom<&str> for KeywordType {
     fn maybe_from(value: &str) -> Option<Self> {
-        match value.as_ref() {
+        match value {
             "and" => Some(KeywordType::And),
             "class" => Some(KeywordType::Class),
             "else" => Some(KeywordType::Else),
@@ -257,7 +261,7 @@ impl From<&str> for StringValue {
 }
 impl fmt::Display for StringValue {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.write_str(&self)
+        f.write_str(self)
     }
 }
 impl Literal for StringValue {}
@@ -276,7 +280,7 @@ impl ops::Deref for StringIdentifier {
 }
 impl fmt::Display for StringIdentifier {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.write_str(&self)
+        f.write_str(self)
     }
 }
 impl Literal for StringIdentifier {}
@@ -285,8 +289,8 @@ impl PrimaryLiteral for StringIdentifier {
         LiteralValue::Identifier(self.clone())
     }
 }
-impl Literal for f32 {}
-impl PrimaryLiteral for f32 {
+impl Literal for f64 {}
+impl PrimaryLiteral for f64 {
     fn to_literal_value(&self) -> LiteralValue {
         LiteralValue::Number(*self)
     }
@@ -294,7 +298,7 @@ impl PrimaryLiteral for f32 {
 pub enum LiteralValue {
     Identifier(StringIdentifier),
     String(StringValue),
-    Number(f32),
+    Number(f64),
 }
 // impl From<Option<Box<dyn Literal>>> for LiteralValue {
 //     fn from(value: Option<Box<dyn Literal>>) -> Self {
@@ -332,9 +336,14 @@ impl NullableLiteral {
     }
 }
 impl Literal for NullableLiteral {}
-impl From<NullableLiteral> for LiteralValue {
+impl From<&NullableLiteral> for Option<LiteralValue> {
+    fn from(value: &NullableLiteral) -> Self {
+        value.as_ref().map(|literal| literal.to_literal_value())
+    }
+}
+impl From<NullableLiteral> for Option<LiteralValue> {
     fn from(value: NullableLiteral) -> Self {
-        value.into()
+        (&value).into()
     }
 }
 // impl From<&dyn PrimaryLiteral> for NullableLiteral {
@@ -351,26 +360,79 @@ impl From<LiteralValue> for NullableLiteral {
         }
     }
 }
+/// A position in the source, both as a byte offset and as a line and
+/// column (both starting at 1).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct Location {
+    pub offset: usize,
+    pub line: usize,
+    pub column: usize,
+}
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_fmt(format_args!("{}:{}", self.line, self.column))
+    }
+}
+/// The stretch of source between `start` and the exclusive `end`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    pub start: Location,
+    pub end: Location,
+}
+impl Span {
+    pub fn new(start: Location, end: Location) -> Self {
+        Self { start, end }
+    }
+    /// The byte range of the span in the source.
+    #[inline]
+    pub fn range(&self) -> ops::Range<usize> {
+        self.start.offset..self.end.offset
+    }
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.end.offset - self.start.offset
+    }
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// The smallest span covering both `self` and `other`.
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            start: self.start.min(other.start),
+            end: self.end.max(other.end),
+        }
+    }
+}
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_fmt(format_args!("{}", self.start))
+    }
+}
 pub struct Token {
     pub r#type: TokenType,
     pub lexeme: String,
     pub literal: NullableLiteral,
-    pub line: usize,
+    pub span: Span,
 }
 impl Token {
     pub fn new(
         r#type: TokenType,
         lexeme: impl ToString,
         literal: Option<LiteralValue>,
-        line: usize,
+        span: Span,
     ) -> Self {
         Self {
             r#type,
             lexeme: lexeme.to_string(),
             literal: literal.map_or(Default::default(), NullableLiteral::from),
-            line,
+            span,
         }
     }
+    #[inline]
+    pub fn line(&self) -> usize {
+        self.span.start.line
+    }
 }
 impl fmt::Display for NullableLiteral {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
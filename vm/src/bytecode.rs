@@ -0,0 +1,646 @@
+//! The `.loxc` format for compiled scripts.
+//!
+//! A file is a header followed by the top-level function. All integers
+//! are little-endian.
+//!
+//! ```text
+//! header    = "LOXC" version:u16 length:u32 checksum:u32
+//! function  = name arity:u8 upvalues:u16 constants code lines
+//! name      = 0 | 1 string
+//! constants = count:u32 (0x00 number:f64 | 0x01 string | 0x02 function)*
+//! code      = length:u32 byte*
+//! lines     = (line:u32)*, one for every byte of code
+//! string    = length:u32 utf8*
+//! ```
+//!
+//! `length` and `checksum` cover everything after the header, the
+//! checksum being the CRC-32 of it.
+use crate::{
+    chunk::{Chunk, OpCode},
+    compiler::MAX_FUNCTION_DEPTH,
+    heap::Heap,
+    object::{Function, Object},
+    ObjRef, Value,
+};
+use core::fmt;
+use diagnostics::{Diagnostic, ToDiagnostic};
+
+const MAGIC: &[u8; 4] = b"LOXC";
+/// Bumped whenever the format or the instruction set changes.
+pub const VERSION: u16 = 1;
+const HEADER_LEN: usize = MAGIC.len() + 2 + 4 + 4;
+
+const NUMBER: u8 = 0x00;
+const STRING: u8 = 0x01;
+const FUNCTION: u8 = 0x02;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LoadError {
+    /// The file doesn't start with the `.loxc` magic number
+    NotBytecode,
+    UnsupportedVersion(u16),
+    /// The file is shorter than its header says
+    Truncated,
+    /// The contents don't match the checksum in the header
+    Corrupted,
+    /// The contents are intact but don't describe a valid function
+    Malformed(String),
+}
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotBytecode => f.write_str("Not a compiled Lox file."),
+            Self::UnsupportedVersion(version) => f.write_fmt(format_args!(
+                "Unsupported bytecode version {version}, expected {VERSION}."
+            )),
+            Self::Truncated => f.write_str("Compiled file is truncated."),
+            Self::Corrupted => f.write_str("Compiled file failed its integrity check."),
+            Self::Malformed(message) => {
+                f.write_fmt(format_args!("Malformed compiled file: {message}"))
+            }
+        }
+    }
+}
+impl std::error::Error for LoadError {}
+impl ToDiagnostic for LoadError {
+    fn to_diagnostic(&self) -> Diagnostic {
+        Diagnostic::error(self)
+    }
+}
+/// Serializes the compiled script `function`.
+pub fn save(heap: &Heap, function: ObjRef) -> Vec<u8> {
+    let mut body = vec![];
+    write_function(heap, function, &mut body);
+    let mut out = Vec::with_capacity(HEADER_LEN + body.len());
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&VERSION.to_le_bytes());
+    out.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    out.extend_from_slice(&crc32(&body).to_le_bytes());
+    out.extend_from_slice(&body);
+    out
+}
+/// Loads a script saved by [`save`] onto `heap`, checking that it can be
+/// run safely.
+pub fn load(bytes: &[u8], heap: &mut Heap) -> Result<ObjRef, LoadError> {
+    if bytes.len() < MAGIC.len() || &bytes[..MAGIC.len()] != MAGIC {
+        return Err(LoadError::NotBytecode);
+    }
+    let mut header = Reader {
+        bytes: &bytes[MAGIC.len()..],
+    };
+    let version = header.u16().map_err(|_| LoadError::Truncated)?;
+    if version != VERSION {
+        return Err(LoadError::UnsupportedVersion(version));
+    }
+    let length = header.u32().map_err(|_| LoadError::Truncated)? as usize;
+    let checksum = header.u32().map_err(|_| LoadError::Truncated)?;
+    let body = header.bytes;
+    if body.len() < length {
+        return Err(LoadError::Truncated);
+    }
+    if body.len() > length || crc32(body) != checksum {
+        return Err(LoadError::Corrupted);
+    }
+    let mut reader = Reader { bytes: body };
+    let function = reader.function(heap, 1)?;
+    if !reader.bytes.is_empty() {
+        return Err(malformed("trailing bytes after the script"));
+    }
+    if function.name.is_some() || function.arity != 0 || function.upvalue_count != 0 {
+        return Err(malformed("the top level is not a script"));
+    }
+    Ok(heap.alloc(Object::Function(function)))
+}
+fn malformed(message: impl ToString) -> LoadError {
+    LoadError::Malformed(message.to_string())
+}
+fn write_string(s: &str, out: &mut Vec<u8>) {
+    out.extend_from_slice(&(s.len() as u32).to_le_bytes());
+    out.extend_from_slice(s.as_bytes());
+}
+fn write_function(heap: &Heap, function: ObjRef, out: &mut Vec<u8>) {
+    let function = heap.function(function);
+    match function.name {
+        Some(name) => {
+            out.push(1);
+            write_string(heap.string(name), out);
+        }
+        None => out.push(0),
+    }
+    out.push(function.arity as u8);
+    out.extend_from_slice(&(function.upvalue_count as u16).to_le_bytes());
+    let chunk = &function.chunk;
+    out.extend_from_slice(&(chunk.constants.len() as u32).to_le_bytes());
+    for constant in &chunk.constants {
+        match *constant {
+            Value::Number(n) => {
+                out.push(NUMBER);
+                out.extend_from_slice(&n.to_le_bytes());
+            }
+            Value::Object(object) => match heap.get(object) {
+                Object::String(s) => {
+                    out.push(STRING);
+                    write_string(s, out);
+                }
+                Object::Function(_) => {
+                    out.push(FUNCTION);
+                    write_function(heap, object, out);
+                }
+                other => unreachable!("the compiler never makes a {other:?} constant"),
+            },
+            other => unreachable!("the compiler never makes a {other:?} constant"),
+        }
+    }
+    out.extend_from_slice(&(chunk.code.len() as u32).to_le_bytes());
+    out.extend_from_slice(&chunk.code);
+    for line in &chunk.lines {
+        out.extend_from_slice(&(*line as u32).to_le_bytes());
+    }
+}
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], LoadError> {
+        if self.bytes.len() < n {
+            return Err(malformed("unexpected end of data"));
+        }
+        let (taken, rest) = self.bytes.split_at(n);
+        self.bytes = rest;
+        Ok(taken)
+    }
+    fn u8(&mut self) -> Result<u8, LoadError> {
+        Ok(self.take(1)?[0])
+    }
+    fn u16(&mut self) -> Result<u16, LoadError> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+    fn u32(&mut self) -> Result<u32, LoadError> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+    fn f64(&mut self) -> Result<f64, LoadError> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(f64::from_le_bytes(bytes))
+    }
+    fn string(&mut self) -> Result<String, LoadError> {
+        let length = self.u32()? as usize;
+        let bytes = self.take(length)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| malformed("a string is not valid UTF-8"))
+    }
+    /// Reads a function nested `depth` deep, the script being the first.
+    fn function(&mut self, heap: &mut Heap, depth: usize) -> Result<Function, LoadError> {
+        if depth > MAX_FUNCTION_DEPTH {
+            return Err(malformed("functions are nested too deeply"));
+        }
+        let name = match self.u8()? {
+            0 => None,
+            1 => Some(heap.intern(&self.string()?)),
+            other => return Err(malformed(format!("bad name tag {other}"))),
+        };
+        let arity = self.u8()? as usize;
+        let upvalue_count = self.u16()? as usize;
+        let mut chunk = Chunk::default();
+        let count = self.u32()?;
+        for _ in 0..count {
+            let constant = match self.u8()? {
+                NUMBER => Value::Number(self.f64()?),
+                STRING => Value::Object(heap.intern(&self.string()?)),
+                FUNCTION => {
+                    let function = self.function(heap, depth + 1)?;
+                    Value::Object(heap.alloc(Object::Function(function)))
+                }
+                other => return Err(malformed(format!("bad constant tag {other}"))),
+            };
+            chunk.add_constant(constant);
+        }
+        let length = self.u32()? as usize;
+        chunk.code = self.take(length)?.to_vec();
+        for _ in 0..length {
+            chunk.lines.push(self.u32()? as usize);
+        }
+        let function = Function {
+            name,
+            arity,
+            upvalue_count,
+            chunk,
+        };
+        verify(heap, &function)?;
+        Ok(function)
+    }
+}
+/// An instruction of a chunk being verified, with its operands decoded.
+struct Instruction {
+    op: OpCode,
+    /// The first operand, if any: a slot, count, index or constant
+    operand: u8,
+    /// The argument count of an invoke
+    count: u8,
+    /// Where a jump goes
+    target: Option<usize>,
+    /// The `is_local` and index of each upvalue a closure captures
+    captures: Vec<(bool, u8)>,
+    /// Where the next instruction starts
+    next: usize,
+}
+/// Checks that the code of `function` can be run without going out of
+/// bounds: that every instruction is known and its operands are in
+/// range, that jumps land on an instruction, and that on every path
+/// through the code the stack holds whatever each instruction pops,
+/// peeks at or reads as a local. Only what the VM would otherwise
+/// trust is checked; type errors are still caught at run time.
+fn verify(heap: &Heap, function: &Function) -> Result<(), LoadError> {
+    let instructions = decode(heap, function)?;
+    // The stack depth of the frame before each instruction, once reached
+    let mut depths: Vec<Option<usize>> = vec![None; function.chunk.code.len()];
+    // The callee and its arguments
+    let mut pending = vec![(0, function.arity + 1)];
+    while let Some((offset, depth)) = pending.pop() {
+        let Some(instruction) = instructions.get(offset).and_then(Option::as_ref) else {
+            return Err(malformed(format!("no instruction at {offset}")));
+        };
+        match depths[offset] {
+            Some(reached) if reached == depth => continue,
+            Some(_) => {
+                return Err(malformed(format!(
+                    "the stack depth at {offset} depends on the path taken"
+                )))
+            }
+            None => depths[offset] = Some(depth),
+        }
+        let after = effect(instruction, depth)
+            .map_err(|message| malformed(format!("{message} at {offset}")))?;
+        let falls_through = !matches!(instruction.op, OpCode::Return | OpCode::Jump | OpCode::Loop);
+        if falls_through {
+            pending.push((instruction.next, after));
+        }
+        if let Some(target) = instruction.target {
+            pending.push((target, after));
+        }
+    }
+    Ok(())
+}
+/// Decodes every instruction of `function`, indexed by offset, checking
+/// operands but not yet the stack.
+fn decode(heap: &Heap, function: &Function) -> Result<Vec<Option<Instruction>>, LoadError> {
+    let chunk = &function.chunk;
+    let code = &chunk.code;
+    let operand = |offset: usize| {
+        code.get(offset)
+            .copied()
+            .ok_or_else(|| malformed(format!("missing operand at {offset}")))
+    };
+    let constant = |offset: usize| -> Result<Value, LoadError> {
+        let index = operand(offset)? as usize;
+        chunk
+            .constants
+            .get(index)
+            .copied()
+            .ok_or_else(|| malformed(format!("constant {index} out of range at {offset}")))
+    };
+    let name = |offset: usize| match constant(offset)? {
+        Value::Object(object) if heap.is_string(object) => operand(offset),
+        _ => Err(malformed(format!("expected a name constant at {offset}"))),
+    };
+    let mut instructions: Vec<Option<Instruction>> = code.iter().map(|_| None).collect();
+    let mut offset = 0;
+    while offset < code.len() {
+        let start = offset;
+        let op = OpCode::try_from(code[offset])
+            .map_err(|byte| malformed(format!("unknown opcode {byte} at {offset}")))?;
+        let mut instruction = Instruction {
+            op,
+            operand: 0,
+            count: 0,
+            target: None,
+            captures: vec![],
+            next: offset + 1,
+        };
+        match op {
+            OpCode::Constant => {
+                constant(offset + 1)?;
+                instruction.operand = operand(offset + 1)?;
+                instruction.next += 1;
+            }
+            OpCode::GetGlobal
+            | OpCode::DefineGlobal
+            | OpCode::SetGlobal
+            | OpCode::GetProperty
+            | OpCode::SetProperty
+            | OpCode::GetSuper
+            | OpCode::Class
+            | OpCode::Method => {
+                instruction.operand = name(offset + 1)?;
+                instruction.next += 1;
+            }
+            OpCode::GetUpvalue | OpCode::SetUpvalue => {
+                instruction.operand = operand(offset + 1)?;
+                if instruction.operand as usize >= function.upvalue_count {
+                    return Err(malformed(format!("upvalue out of range at {offset}")));
+                }
+                instruction.next += 1;
+            }
+            OpCode::GetLocal | OpCode::SetLocal | OpCode::Call => {
+                instruction.operand = operand(offset + 1)?;
+                instruction.next += 1;
+            }
+            OpCode::Invoke | OpCode::SuperInvoke => {
+                instruction.operand = name(offset + 1)?;
+                instruction.count = operand(offset + 2)?;
+                instruction.next += 2;
+            }
+            OpCode::Jump | OpCode::JumpIfFalse | OpCode::Loop => {
+                let jump = u16::from_be_bytes([operand(offset + 1)?, operand(offset + 2)?]);
+                let after = offset + 3;
+                let target = if op == OpCode::Loop {
+                    after.checked_sub(jump as usize)
+                } else {
+                    Some(after + jump as usize)
+                };
+                // Whether it lands on an instruction is checked once
+                // they're all decoded
+                instruction.target = Some(
+                    target.ok_or_else(|| malformed(format!("jump out of range at {offset}")))?,
+                );
+                instruction.next += 2;
+            }
+            OpCode::Closure => {
+                let upvalues = match constant(offset + 1)? {
+                    Value::Object(object) => match heap.get(object) {
+                        Object::Function(function) => function.upvalue_count,
+                        _ => return Err(malformed(format!("expected a function at {offset}"))),
+                    },
+                    _ => return Err(malformed(format!("expected a function at {offset}"))),
+                };
+                for upvalue in 0..upvalues {
+                    let is_local = operand(offset + 2 + upvalue * 2)?;
+                    let index = operand(offset + 3 + upvalue * 2)?;
+                    if is_local > 1 || (is_local == 0 && index as usize >= function.upvalue_count) {
+                        return Err(malformed(format!("bad upvalue capture at {offset}")));
+                    }
+                    instruction.captures.push((is_local == 1, index));
+                }
+                instruction.next += 1 + upvalues * 2;
+            }
+            _ => {}
+        }
+        offset = instruction.next;
+        instructions[start] = Some(instruction);
+    }
+    Ok(instructions)
+}
+/// The stack depth after `instruction` runs from `depth`, or why it
+/// can't.
+fn effect(instruction: &Instruction, depth: usize) -> Result<usize, &'static str> {
+    let needs = |n: usize| {
+        if depth < n {
+            Err("stack underflow")
+        } else {
+            Ok(())
+        }
+    };
+    let local = |slot: u8| {
+        if slot as usize >= depth {
+            Err("local out of range")
+        } else {
+            Ok(())
+        }
+    };
+    match instruction.op {
+        OpCode::Constant
+        | OpCode::Nil
+        | OpCode::True
+        | OpCode::False
+        | OpCode::GetGlobal
+        | OpCode::GetUpvalue
+        | OpCode::Class => Ok(depth + 1),
+        OpCode::GetLocal => local(instruction.operand).map(|()| depth + 1),
+        OpCode::SetLocal => {
+            needs(1)?;
+            local(instruction.operand).map(|()| depth)
+        }
+        OpCode::Closure => {
+            for &(is_local, index) in &instruction.captures {
+                if is_local {
+                    local(index)?;
+                }
+            }
+            Ok(depth + 1)
+        }
+        OpCode::SetGlobal
+        | OpCode::SetUpvalue
+        | OpCode::GetProperty
+        | OpCode::Not
+        | OpCode::Negate
+        | OpCode::JumpIfFalse => needs(1).map(|()| depth),
+        OpCode::Jump | OpCode::Loop => Ok(depth),
+        OpCode::Pop
+        | OpCode::DefineGlobal
+        | OpCode::Print
+        | OpCode::CloseUpvalue
+        | OpCode::Return => needs(1).map(|()| depth - 1),
+        OpCode::SetProperty
+        | OpCode::GetSuper
+        | OpCode::Equal
+        | OpCode::Greater
+        | OpCode::Less
+        | OpCode::Add
+        | OpCode::Subtract
+        | OpCode::Multiply
+        | OpCode::Divide
+        | OpCode::Inherit
+        | OpCode::Method => needs(2).map(|()| depth - 1),
+        // The callee and arguments are replaced by the result
+        OpCode::Call => {
+            let count = instruction.operand as usize;
+            needs(count + 1).map(|()| depth - count)
+        }
+        OpCode::Invoke => {
+            let count = instruction.count as usize;
+            needs(count + 1).map(|()| depth - count)
+        }
+        // As an invoke, with the superclass on top
+        OpCode::SuperInvoke => {
+            let count = instruction.count as usize;
+            needs(count + 2).map(|()| depth - count - 1)
+        }
+    }
+}
+/// The CRC-32 (IEEE) of `bytes`.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod bytecode_tests {
+    use super::*;
+    use crate::Vm;
+    use scanner::Scanner;
+    const SOURCE: &str = "
+        class Greeter { init(name) { this.name = name; } greet() { return \"hi \" + this.name; } }
+        fun make() { var n = 0; fun next() { n = n + 1.5; return n; } return next; }
+        var counter = make();
+        counter();
+        var count = counter();
+        var greeting = Greeter(\"lox\").greet();
+        class Loud < Greeter { greet() { return super.greet() + \"!\"; } }
+        var loud = Loud(\"lox\").greet();
+        var total = 0;
+        for (var i = 0; i < 3; i = i + 1) {
+            if (i == 1 and true) total = total + 10; else total = total + i;
+        }
+        while (total < 20 or false) { var step = 1; total = total + step; }
+    ";
+    fn compiled() -> Vec<u8> {
+        let (tokens, _) = Scanner::new(SOURCE).scan_tokens();
+        let mut vm = Vm::new();
+        let function = vm.compile(tokens).expect("source to compile");
+        save(vm.heap(), function)
+    }
+    #[test]
+    fn round_trips_and_runs() {
+        let bytes = compiled();
+        let mut vm = Vm::new();
+        let function = vm.load(&bytes).unwrap();
+        vm.run(function).unwrap();
+        let global = |name| vm.get_global(name).unwrap().display(vm.heap()).to_string();
+        assert_eq!(global("count"), "3");
+        assert_eq!(global("greeting"), "hi lox");
+        assert_eq!(global("loud"), "hi lox!");
+        assert_eq!(global("total"), "20");
+    }
+    #[test]
+    fn checks_the_crc() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+    #[test]
+    fn rejects_bad_headers() {
+        let bytes = compiled();
+        let mut heap = Heap::new();
+        assert_eq!(load(b"print 1;", &mut heap), Err(LoadError::NotBytecode));
+        let mut future = bytes.clone();
+        future[4] = VERSION as u8 + 1;
+        assert_eq!(
+            load(&future, &mut heap),
+            Err(LoadError::UnsupportedVersion(VERSION + 1))
+        );
+        assert_eq!(
+            load(&bytes[..bytes.len() - 1], &mut heap),
+            Err(LoadError::Truncated)
+        );
+        assert_eq!(load(&bytes[..7], &mut heap), Err(LoadError::Truncated));
+    }
+    #[test]
+    fn rejects_corrupted_contents() {
+        let bytes = compiled();
+        let mut heap = Heap::new();
+        let mut flipped = bytes.clone();
+        *flipped.last_mut().unwrap() ^= 1;
+        assert_eq!(load(&flipped, &mut heap), Err(LoadError::Corrupted));
+        let mut extended = bytes;
+        extended.push(0);
+        assert_eq!(load(&extended, &mut heap), Err(LoadError::Corrupted));
+    }
+    #[test]
+    fn rejects_malformed_code() {
+        let mut heap = Heap::new();
+        let mut script = Function::default();
+        // A constant that isn't in the pool
+        script.chunk.write(OpCode::Constant as u8, 1);
+        script.chunk.write(3, 1);
+        script.chunk.write(OpCode::Return as u8, 1);
+        let function = heap.alloc(Object::Function(script));
+        let bytes = save(&heap, function);
+        assert!(matches!(
+            load(&bytes, &mut heap),
+            Err(LoadError::Malformed(message)) if message.contains("out of range")
+        ));
+    }
+    #[test]
+    fn rejects_functions_nested_too_deeply() {
+        // Written by hand, as saving recurses just like loading
+        let nested = |depth: usize| {
+            let code = |out: &mut Vec<u8>| {
+                out.extend_from_slice(&2u32.to_le_bytes());
+                out.extend_from_slice(&[OpCode::Nil as u8, OpCode::Return as u8]);
+                out.extend_from_slice(&[1, 0, 0, 0, 1, 0, 0, 0]);
+            };
+            let mut body = vec![];
+            for _ in 1..depth {
+                body.extend_from_slice(&[0, 0, 0, 0, 1, 0, 0, 0, FUNCTION]);
+            }
+            body.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0]);
+            (0..depth).for_each(|_| code(&mut body));
+            let mut bytes = MAGIC.to_vec();
+            bytes.extend_from_slice(&VERSION.to_le_bytes());
+            bytes.extend_from_slice(&(body.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(&crc32(&body).to_le_bytes());
+            bytes.extend_from_slice(&body);
+            load(&bytes, &mut Heap::new())
+        };
+        assert!(nested(MAX_FUNCTION_DEPTH).is_ok());
+        assert!(matches!(
+            nested(100_000),
+            Err(LoadError::Malformed(message)) if message.contains("nested too deeply")
+        ));
+    }
+    /// Why a script of `code`, with no constants, fails to load.
+    fn rejection(code: &[OpCode]) -> String {
+        rejection_of_bytes(&code.iter().map(|&op| op as u8).collect::<Vec<_>>())
+    }
+    fn rejection_of_bytes(code: &[u8]) -> String {
+        let mut heap = Heap::new();
+        let mut script = Function::default();
+        for &byte in code {
+            script.chunk.write(byte, 1);
+        }
+        let function = heap.alloc(Object::Function(script));
+        let bytes = save(&heap, function);
+        match load(&bytes, &mut heap) {
+            Err(LoadError::Malformed(message)) => message,
+            other => panic!("expected {code:?} to be rejected, got {other:?}"),
+        }
+    }
+    #[test]
+    fn rejects_locals_off_the_stack() {
+        let message = rejection_of_bytes(&[OpCode::GetLocal as u8, 200, OpCode::Return as u8]);
+        assert!(message.contains("local out of range"), "{message}");
+    }
+    #[test]
+    fn rejects_popping_an_empty_stack() {
+        use OpCode::*;
+        let message = rejection(&[Pop, Pop, Pop, Return]);
+        assert!(message.contains("stack underflow"), "{message}");
+        let message = rejection(&[Add, Return]);
+        assert!(message.contains("stack underflow"), "{message}");
+    }
+    #[test]
+    fn rejects_jumps_into_an_operand() {
+        use OpCode::*;
+        let code = [Jump as u8, 0, 1, GetLocal as u8, 0, Nil as u8, Return as u8];
+        let message = rejection_of_bytes(&code);
+        assert!(message.contains("no instruction at 4"), "{message}");
+    }
+    #[test]
+    fn rejects_running_off_the_end() {
+        let message = rejection(&[OpCode::Nil, OpCode::Pop]);
+        assert!(message.contains("no instruction at 2"), "{message}");
+    }
+    #[test]
+    fn rejects_paths_that_disagree_on_the_stack() {
+        use OpCode::*;
+        let code = [Nil as u8, JumpIfFalse as u8, 0, 1, Nil as u8, Return as u8];
+        let message = rejection_of_bytes(&code);
+        assert!(message.contains("depends on the path"), "{message}");
+    }
+}
@@ -0,0 +1,124 @@
+use crate::Value;
+
+macro_rules! opcodes {
+    ($($(#[$doc:meta])* $op:ident,)*) => {
+        /// A single instruction, followed in the code by its operands.
+        #[repr(u8)]
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub enum OpCode {
+            $($(#[$doc])* $op,)*
+        }
+        impl OpCode {
+            const ALL: &'static [OpCode] = &[$(OpCode::$op,)*];
+        }
+    };
+}
+opcodes! {
+    /// Pushes the constant at the one-byte index
+    Constant,
+    Nil,
+    True,
+    False,
+    Pop,
+    /// Pushes the local at the one-byte stack slot
+    GetLocal,
+    SetLocal,
+    /// Pushes the global named by the one-byte constant
+    GetGlobal,
+    DefineGlobal,
+    SetGlobal,
+    /// Pushes the one-byte indexed upvalue of the current closure
+    GetUpvalue,
+    SetUpvalue,
+    /// Replaces the instance on top with its property named by the
+    /// one-byte constant
+    GetProperty,
+    SetProperty,
+    /// Replaces the superclass and receiver on top with the superclass
+    /// method named by the one-byte constant, bound to the receiver
+    GetSuper,
+    Equal,
+    Greater,
+    Less,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Not,
+    Negate,
+    Print,
+    /// Jumps forward by the two-byte offset
+    Jump,
+    /// Jumps forward by the two-byte offset if the top is falsey
+    JumpIfFalse,
+    /// Jumps back by the two-byte offset
+    Loop,
+    /// Calls the callee below the one-byte count of arguments
+    Call,
+    /// Calls the method named by the one-byte constant on the receiver
+    /// below the one-byte count of arguments
+    Invoke,
+    SuperInvoke,
+    /// Wraps the function in the one-byte constant in a closure, followed
+    /// by an `is_local` and an index byte for each of its upvalues
+    Closure,
+    CloseUpvalue,
+    Return,
+    /// Pushes a new class named by the one-byte constant
+    Class,
+    /// Copies the methods of the superclass below the subclass on top
+    Inherit,
+    /// Adds the closure on top to the class below it, under the name in
+    /// the one-byte constant
+    Method,
+}
+impl TryFrom<u8> for OpCode {
+    type Error = u8;
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Self::ALL.get(value as usize).copied().ok_or(value)
+    }
+}
+/// Compiled code along with the constants it refers to and the source
+/// line of every byte.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Chunk {
+    pub code: Vec<u8>,
+    pub constants: Vec<Value>,
+    pub lines: Vec<usize>,
+}
+impl Chunk {
+    pub fn write(&mut self, byte: u8, line: usize) {
+        self.code.push(byte);
+        self.lines.push(line);
+    }
+    /// Adds `value` to the constant pool, returning its index.
+    pub fn add_constant(&mut self, value: Value) -> usize {
+        self.constants.push(value);
+        self.constants.len() - 1
+    }
+}
+
+#[cfg(test)]
+mod chunk_tests {
+    use super::*;
+    #[test]
+    fn opcodes_round_trip() {
+        for op in OpCode::ALL {
+            assert_eq!(OpCode::try_from(*op as u8), Ok(*op));
+        }
+        assert_eq!(
+            OpCode::try_from(OpCode::ALL.len() as u8),
+            Err(OpCode::ALL.len() as u8)
+        );
+    }
+    #[test]
+    fn lines_follow_code() {
+        let mut chunk = Chunk::default();
+        let constant = chunk.add_constant(Value::Number(1.0));
+        chunk.write(OpCode::Constant as u8, 1);
+        chunk.write(constant as u8, 1);
+        chunk.write(OpCode::Return as u8, 2);
+        assert_eq!(chunk.code.len(), chunk.lines.len());
+        assert_eq!(chunk.lines, [1, 1, 2]);
+    }
+}
@@ -0,0 +1,916 @@
+use crate::{
+    chunk::{Chunk, OpCode},
+    heap::Heap,
+    object::{Function, Object},
+    ObjRef, Value,
+};
+use core::fmt;
+use diagnostics::{Diagnostic, ToDiagnostic};
+use token::{ComparatorType, Direction, KeywordType, Span, Token, TokenType};
+
+/// The most arguments a call may pass, and so the most parameters a
+/// function may declare.
+pub const MAX_ARITY: usize = 255;
+/// How deep functions may nest, the top-level script included. Loading
+/// bytecode recurses into nested functions, so it holds to this too.
+pub const MAX_FUNCTION_DEPTH: usize = 256;
+/// Locals, upvalues and constants are all addressed by a single byte.
+const MAX_INDEX: usize = u8::MAX as usize + 1;
+
+#[derive(Debug)]
+pub struct CompileError {
+    pub span: Span,
+    pub lexeme: String,
+    pub message: String,
+}
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.lexeme.is_empty() {
+            f.write_fmt(format_args!(
+                "[line {}] Error at end: {}",
+                self.span, self.message
+            ))
+        } else {
+            f.write_fmt(format_args!(
+                "[line {}] Error at '{}': {}",
+                self.span, self.lexeme, self.message
+            ))
+        }
+    }
+}
+impl std::error::Error for CompileError {}
+impl ToDiagnostic for CompileError {
+    fn to_diagnostic(&self) -> Diagnostic {
+        Diagnostic::error(&self.message).at(self.span)
+    }
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Precedence {
+    None,
+    Assignment,
+    Or,
+    And,
+    Equality,
+    Comparison,
+    Term,
+    Factor,
+    Unary,
+    Call,
+    Primary,
+}
+impl Precedence {
+    fn next(self) -> Self {
+        match self {
+            Self::None => Self::Assignment,
+            Self::Assignment => Self::Or,
+            Self::Or => Self::And,
+            Self::And => Self::Equality,
+            Self::Equality => Self::Comparison,
+            Self::Comparison => Self::Term,
+            Self::Term => Self::Factor,
+            Self::Factor => Self::Unary,
+            Self::Unary => Self::Call,
+            Self::Call | Self::Primary => Self::Primary,
+        }
+    }
+}
+type ParseFn<'h> = fn(&mut Compiler<'h>, bool);
+struct Rule<'h> {
+    prefix: Option<ParseFn<'h>>,
+    infix: Option<ParseFn<'h>>,
+    precedence: Precedence,
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FunctionKind {
+    Script,
+    Function,
+    Method,
+    Initializer,
+}
+struct Local {
+    name: String,
+    /// `None` until the variable's initializer has been compiled
+    depth: Option<usize>,
+    is_captured: bool,
+}
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct UpvalueRef {
+    index: u8,
+    /// Whether this captures a local of the enclosing function, rather
+    /// than one of its upvalues
+    is_local: bool,
+}
+/// The function currently being compiled.
+struct FunctionState {
+    function: Function,
+    kind: FunctionKind,
+    locals: Vec<Local>,
+    upvalues: Vec<UpvalueRef>,
+    scope_depth: usize,
+}
+impl FunctionState {
+    fn new(kind: FunctionKind, name: Option<ObjRef>) -> Self {
+        // The first slot holds the callee, or the receiver of a method
+        let receiver = match kind {
+            FunctionKind::Method | FunctionKind::Initializer => "this",
+            FunctionKind::Script | FunctionKind::Function => "",
+        };
+        Self {
+            function: Function {
+                name,
+                ..Function::default()
+            },
+            kind,
+            locals: vec![Local {
+                name: receiver.into(),
+                depth: Some(0),
+                is_captured: false,
+            }],
+            upvalues: vec![],
+            scope_depth: 0,
+        }
+    }
+}
+struct ClassState {
+    has_superclass: bool,
+}
+/// A single-pass compiler from tokens straight to bytecode.
+pub struct Compiler<'h> {
+    tokens: Vec<Token>,
+    current: usize,
+    heap: &'h mut Heap,
+    /// The function being compiled last, enclosed by those before it
+    functions: Vec<FunctionState>,
+    classes: Vec<ClassState>,
+    errors: Vec<CompileError>,
+    /// Set after an error until the next statement, so that a single
+    /// mistake does not cascade into further errors
+    panic_mode: bool,
+}
+impl<'h> Compiler<'h> {
+    /// `tokens` should end with [`TokenType::Eof`], as scanned.
+    pub fn new(tokens: Vec<Token>, heap: &'h mut Heap) -> Self {
+        Self {
+            tokens,
+            current: 0,
+            heap,
+            functions: vec![FunctionState::new(FunctionKind::Script, None)],
+            classes: vec![],
+            errors: vec![],
+            panic_mode: false,
+        }
+    }
+    /// Compiles the whole program into a function for the top-level
+    /// script, collecting every error encountered.
+    pub fn compile(mut self) -> Result<ObjRef, Vec<CompileError>> {
+        while !self.r#match(TokenType::Eof) {
+            self.declaration();
+        }
+        let (function, _) = self.end_function();
+        if self.errors.is_empty() {
+            Ok(self.heap.alloc(Object::Function(function)))
+        } else {
+            Err(self.errors)
+        }
+    }
+    fn state(&mut self) -> &mut FunctionState {
+        self.functions.last_mut().expect("a function to compile")
+    }
+    fn chunk(&mut self) -> &mut Chunk {
+        &mut self.state().function.chunk
+    }
+    fn declaration(&mut self) {
+        if self.r#match(TokenType::Keyword(KeywordType::Class)) {
+            self.class_declaration();
+        } else if self.r#match(TokenType::Keyword(KeywordType::Fun)) {
+            self.fun_declaration();
+        } else if self.r#match(TokenType::Keyword(KeywordType::Var)) {
+            self.var_declaration();
+        } else {
+            self.statement();
+        }
+        if self.panic_mode {
+            self.synchronize();
+        }
+    }
+    fn class_declaration(&mut self) {
+        self.consume(TokenType::Identifier, "Expect class name.");
+        let class_name = self.previous().lexeme.clone();
+        let name_constant = self.identifier_constant(&class_name);
+        self.declare_variable();
+        self.emit_bytes(OpCode::Class as u8, name_constant);
+        self.define_variable(name_constant);
+        self.classes.push(ClassState {
+            has_superclass: false,
+        });
+        if self.r#match(ComparatorType::Less.r#match(false)) {
+            self.consume(TokenType::Identifier, "Expect superclass name.");
+            let superclass = self.previous().lexeme.clone();
+            self.named_variable(&superclass, false);
+            if superclass == class_name {
+                self.error("A class can't inherit from itself.");
+            }
+            self.begin_scope();
+            self.add_local("super");
+            self.define_variable(0);
+            self.named_variable(&class_name, false);
+            self.emit_op(OpCode::Inherit);
+            if let Some(class) = self.classes.last_mut() {
+                class.has_superclass = true;
+            }
+        }
+        self.named_variable(&class_name, false);
+        self.consume(
+            TokenType::Brace(Direction::Left),
+            "Expect '{' before class body.",
+        );
+        while !self.check(TokenType::Brace(Direction::Right)) && !self.check(TokenType::Eof) {
+            self.method();
+        }
+        self.consume(
+            TokenType::Brace(Direction::Right),
+            "Expect '}' after class body.",
+        );
+        self.emit_op(OpCode::Pop);
+        if self.classes.pop().is_some_and(|class| class.has_superclass) {
+            self.end_scope();
+        }
+    }
+    fn method(&mut self) {
+        self.consume(TokenType::Identifier, "Expect method name.");
+        let name = self.previous().lexeme.clone();
+        let constant = self.identifier_constant(&name);
+        let kind = if name == "init" {
+            FunctionKind::Initializer
+        } else {
+            FunctionKind::Method
+        };
+        self.function(kind);
+        self.emit_bytes(OpCode::Method as u8, constant);
+    }
+    fn fun_declaration(&mut self) {
+        let global = self.parse_variable("Expect function name.");
+        // A function may refer to itself
+        self.mark_initialized();
+        self.function(FunctionKind::Function);
+        self.define_variable(global);
+    }
+    fn function(&mut self, kind: FunctionKind) {
+        let name = self.previous().lexeme.clone();
+        let name = self.heap.intern(&name);
+        self.functions.push(FunctionState::new(kind, Some(name)));
+        if self.functions.len() > MAX_FUNCTION_DEPTH {
+            self.error("Too many nested functions.");
+        }
+        self.begin_scope();
+        self.consume(
+            TokenType::Paren(Direction::Left),
+            "Expect '(' after function name.",
+        );
+        if !self.check(TokenType::Paren(Direction::Right)) {
+            loop {
+                self.state().function.arity += 1;
+                if self.state().function.arity > MAX_ARITY {
+                    self.error_at_current(&format!("Can't have more than {MAX_ARITY} parameters."));
+                }
+                let constant = self.parse_variable("Expect parameter name.");
+                self.define_variable(constant);
+                if !self.r#match(TokenType::Comma) {
+                    break;
+                }
+            }
+        }
+        self.consume(
+            TokenType::Paren(Direction::Right),
+            "Expect ')' after parameters.",
+        );
+        self.consume(
+            TokenType::Brace(Direction::Left),
+            "Expect '{' before function body.",
+        );
+        self.block();
+        let (function, upvalues) = self.end_function();
+        let function = self.heap.alloc(Object::Function(function));
+        let constant = self.make_constant(Value::Object(function));
+        self.emit_bytes(OpCode::Closure as u8, constant);
+        for upvalue in upvalues {
+            self.emit_bytes(upvalue.is_local as u8, upvalue.index);
+        }
+    }
+    fn end_function(&mut self) -> (Function, Vec<UpvalueRef>) {
+        self.emit_return();
+        let state = self.functions.pop().expect("a function to end");
+        let mut function = state.function;
+        function.upvalue_count = state.upvalues.len();
+        (function, state.upvalues)
+    }
+    fn var_declaration(&mut self) {
+        let global = self.parse_variable("Expect variable name.");
+        if self.r#match(ComparatorType::Equal.r#match(false)) {
+            self.expression();
+        } else {
+            self.emit_op(OpCode::Nil);
+        }
+        self.consume(
+            TokenType::Semicolon,
+            "Expect ';' after variable declaration.",
+        );
+        self.define_variable(global);
+    }
+    fn statement(&mut self) {
+        if self.r#match(TokenType::Keyword(KeywordType::Print)) {
+            self.expression();
+            self.consume(TokenType::Semicolon, "Expect ';' after value.");
+            self.emit_op(OpCode::Print);
+        } else if self.r#match(TokenType::Keyword(KeywordType::For)) {
+            self.for_statement();
+        } else if self.r#match(TokenType::Keyword(KeywordType::If)) {
+            self.if_statement();
+        } else if self.r#match(TokenType::Keyword(KeywordType::Return)) {
+            self.return_statement();
+        } else if self.r#match(TokenType::Keyword(KeywordType::While)) {
+            self.while_statement();
+        } else if self.r#match(TokenType::Brace(Direction::Left)) {
+            self.begin_scope();
+            self.block();
+            self.end_scope();
+        } else {
+            self.expression_statement();
+        }
+    }
+    fn expression_statement(&mut self) {
+        self.expression();
+        self.consume(TokenType::Semicolon, "Expect ';' after expression.");
+        self.emit_op(OpCode::Pop);
+    }
+    fn block(&mut self) {
+        while !self.check(TokenType::Brace(Direction::Right)) && !self.check(TokenType::Eof) {
+            self.declaration();
+        }
+        self.consume(
+            TokenType::Brace(Direction::Right),
+            "Expect '}' after block.",
+        );
+    }
+    fn for_statement(&mut self) {
+        self.begin_scope();
+        self.consume(TokenType::Paren(Direction::Left), "Expect '(' after 'for'.");
+        if self.r#match(TokenType::Semicolon) {
+            // No initializer
+        } else if self.r#match(TokenType::Keyword(KeywordType::Var)) {
+            self.var_declaration();
+        } else {
+            self.expression_statement();
+        }
+        let mut loop_start = self.chunk().code.len();
+        let mut exit_jump = None;
+        if !self.r#match(TokenType::Semicolon) {
+            self.expression();
+            self.consume(TokenType::Semicolon, "Expect ';' after loop condition.");
+            exit_jump = Some(self.emit_jump(OpCode::JumpIfFalse));
+            self.emit_op(OpCode::Pop);
+        }
+        if !self.r#match(TokenType::Paren(Direction::Right)) {
+            let body_jump = self.emit_jump(OpCode::Jump);
+            let increment_start = self.chunk().code.len();
+            self.expression();
+            self.emit_op(OpCode::Pop);
+            self.consume(
+                TokenType::Paren(Direction::Right),
+                "Expect ')' after for clauses.",
+            );
+            self.emit_loop(loop_start);
+            loop_start = increment_start;
+            self.patch_jump(body_jump);
+        }
+        self.statement();
+        self.emit_loop(loop_start);
+        if let Some(exit_jump) = exit_jump {
+            self.patch_jump(exit_jump);
+            self.emit_op(OpCode::Pop);
+        }
+        self.end_scope();
+    }
+    fn if_statement(&mut self) {
+        self.consume(TokenType::Paren(Direction::Left), "Expect '(' after 'if'.");
+        self.expression();
+        self.consume(
+            TokenType::Paren(Direction::Right),
+            "Expect ')' after condition.",
+        );
+        let then_jump = self.emit_jump(OpCode::JumpIfFalse);
+        self.emit_op(OpCode::Pop);
+        self.statement();
+        let else_jump = self.emit_jump(OpCode::Jump);
+        self.patch_jump(then_jump);
+        self.emit_op(OpCode::Pop);
+        if self.r#match(TokenType::Keyword(KeywordType::Else)) {
+            self.statement();
+        }
+        self.patch_jump(else_jump);
+    }
+    fn return_statement(&mut self) {
+        if self.state().kind == FunctionKind::Script {
+            self.error("Can't return from top-level code.");
+        }
+        if self.r#match(TokenType::Semicolon) {
+            self.emit_return();
+            return;
+        }
+        if self.state().kind == FunctionKind::Initializer {
+            self.error("Can't return a value from an initializer.");
+        }
+        self.expression();
+        self.consume(TokenType::Semicolon, "Expect ';' after return value.");
+        self.emit_op(OpCode::Return);
+    }
+    fn while_statement(&mut self) {
+        let loop_start = self.chunk().code.len();
+        self.consume(
+            TokenType::Paren(Direction::Left),
+            "Expect '(' after 'while'.",
+        );
+        self.expression();
+        self.consume(
+            TokenType::Paren(Direction::Right),
+            "Expect ')' after condition.",
+        );
+        let exit_jump = self.emit_jump(OpCode::JumpIfFalse);
+        self.emit_op(OpCode::Pop);
+        self.statement();
+        self.emit_loop(loop_start);
+        self.patch_jump(exit_jump);
+        self.emit_op(OpCode::Pop);
+    }
+    fn expression(&mut self) {
+        self.parse_precedence(Precedence::Assignment);
+    }
+    fn parse_precedence(&mut self, precedence: Precedence) {
+        self.advance();
+        let Some(prefix) = Self::rule(self.previous().r#type).prefix else {
+            self.error("Expect expression.");
+            return;
+        };
+        let can_assign = precedence <= Precedence::Assignment;
+        prefix(self, can_assign);
+        while precedence <= Self::rule(self.peek().r#type).precedence {
+            self.advance();
+            if let Some(infix) = Self::rule(self.previous().r#type).infix {
+                infix(self, can_assign);
+            }
+        }
+        if can_assign && self.r#match(ComparatorType::Equal.r#match(false)) {
+            self.error("Invalid assignment target.");
+        }
+    }
+    fn rule(r#type: TokenType) -> Rule<'h> {
+        let rule = |prefix, infix, precedence| Rule {
+            prefix,
+            infix,
+            precedence,
+        };
+        let binary: Option<ParseFn<'h>> = Some(Self::binary);
+        match r#type {
+            TokenType::Paren(Direction::Left) => {
+                rule(Some(Self::grouping), Some(Self::call), Precedence::Call)
+            }
+            TokenType::Dot => rule(None, Some(Self::dot), Precedence::Call),
+            TokenType::Minus => rule(Some(Self::unary), binary, Precedence::Term),
+            TokenType::Plus => rule(None, binary, Precedence::Term),
+            TokenType::Slash | TokenType::Star => rule(None, binary, Precedence::Factor),
+            TokenType::Comparator {
+                r#type: ComparatorType::Bang,
+                equal: false,
+            } => rule(Some(Self::unary), None, Precedence::None),
+            TokenType::Comparator {
+                r#type: ComparatorType::Bang | ComparatorType::Equal,
+                equal: true,
+            } => rule(None, binary, Precedence::Equality),
+            TokenType::Comparator {
+                r#type: ComparatorType::Greater | ComparatorType::Less,
+                ..
+            } => rule(None, binary, Precedence::Comparison),
+            TokenType::Identifier => rule(Some(Self::variable), None, Precedence::None),
+            TokenType::String => rule(Some(Self::string), None, Precedence::None),
+            TokenType::Number => rule(Some(Self::number), None, Precedence::None),
+            TokenType::Keyword(KeywordType::And) => rule(None, Some(Self::and), Precedence::And),
+            TokenType::Keyword(KeywordType::Or) => rule(None, Some(Self::or), Precedence::Or),
+            TokenType::Keyword(KeywordType::False | KeywordType::True | KeywordType::Nil) => {
+                rule(Some(Self::literal), None, Precedence::None)
+            }
+            TokenType::Keyword(KeywordType::Super) => {
+                rule(Some(Self::super_), None, Precedence::None)
+            }
+            TokenType::Keyword(KeywordType::This) => rule(Some(Self::this), None, Precedence::None),
+            _ => rule(None, None, Precedence::None),
+        }
+    }
+    fn grouping(&mut self, _can_assign: bool) {
+        self.expression();
+        self.consume(
+            TokenType::Paren(Direction::Right),
+            "Expect ')' after expression.",
+        );
+    }
+    fn number(&mut self, _can_assign: bool) {
+        match self.previous().lexeme.parse::<f64>() {
+            Ok(number) => self.emit_constant(Value::Number(number)),
+            Err(_) => self.error("Invalid number."),
+        }
+    }
+    fn string(&mut self, _can_assign: bool) {
+        let lexeme = &self.previous().lexeme;
+        let contents = lexeme[1..lexeme.len() - 1].to_string();
+        let string = self.heap.intern(&contents);
+        self.emit_constant(Value::Object(string));
+    }
+    fn literal(&mut self, _can_assign: bool) {
+        match self.previous().r#type {
+            TokenType::Keyword(KeywordType::False) => self.emit_op(OpCode::False),
+            TokenType::Keyword(KeywordType::True) => self.emit_op(OpCode::True),
+            _ => self.emit_op(OpCode::Nil),
+        }
+    }
+    fn variable(&mut self, can_assign: bool) {
+        let name = self.previous().lexeme.clone();
+        self.named_variable(&name, can_assign);
+    }
+    fn this(&mut self, _can_assign: bool) {
+        if self.classes.is_empty() {
+            self.error("Can't use 'this' outside of a class.");
+            return;
+        }
+        self.named_variable("this", false);
+    }
+    fn super_(&mut self, _can_assign: bool) {
+        match self.classes.last() {
+            None => self.error("Can't use 'super' outside of a class."),
+            Some(class) if !class.has_superclass => {
+                self.error("Can't use 'super' in a class with no superclass.")
+            }
+            _ => {}
+        }
+        self.consume(TokenType::Dot, "Expect '.' after 'super'.");
+        self.consume(TokenType::Identifier, "Expect superclass method name.");
+        let method = self.previous().lexeme.clone();
+        let name = self.identifier_constant(&method);
+        self.named_variable("this", false);
+        if self.r#match(TokenType::Paren(Direction::Left)) {
+            let count = self.argument_list();
+            self.named_variable("super", false);
+            self.emit_bytes(OpCode::SuperInvoke as u8, name);
+            self.emit_byte(count);
+        } else {
+            self.named_variable("super", false);
+            self.emit_bytes(OpCode::GetSuper as u8, name);
+        }
+    }
+    fn unary(&mut self, _can_assign: bool) {
+        let operator = self.previous().r#type;
+        self.parse_precedence(Precedence::Unary);
+        match operator {
+            TokenType::Minus => self.emit_op(OpCode::Negate),
+            _ => self.emit_op(OpCode::Not),
+        }
+    }
+    fn binary(&mut self, _can_assign: bool) {
+        let operator = self.previous().r#type;
+        self.parse_precedence(Self::rule(operator).precedence.next());
+        match operator {
+            TokenType::Plus => self.emit_op(OpCode::Add),
+            TokenType::Minus => self.emit_op(OpCode::Subtract),
+            TokenType::Star => self.emit_op(OpCode::Multiply),
+            TokenType::Slash => self.emit_op(OpCode::Divide),
+            TokenType::Comparator { r#type, equal } => match (r#type, equal) {
+                (ComparatorType::Equal, _) => self.emit_op(OpCode::Equal),
+                (ComparatorType::Bang, _) => self.emit_ops(OpCode::Equal, OpCode::Not),
+                (ComparatorType::Greater, false) => self.emit_op(OpCode::Greater),
+                (ComparatorType::Greater, true) => self.emit_ops(OpCode::Less, OpCode::Not),
+                (ComparatorType::Less, false) => self.emit_op(OpCode::Less),
+                (ComparatorType::Less, true) => self.emit_ops(OpCode::Greater, OpCode::Not),
+            },
+            _ => unreachable!("{operator:?} is not a binary operator"),
+        }
+    }
+    fn and(&mut self, _can_assign: bool) {
+        let end_jump = self.emit_jump(OpCode::JumpIfFalse);
+        self.emit_op(OpCode::Pop);
+        self.parse_precedence(Precedence::And);
+        self.patch_jump(end_jump);
+    }
+    fn or(&mut self, _can_assign: bool) {
+        let else_jump = self.emit_jump(OpCode::JumpIfFalse);
+        let end_jump = self.emit_jump(OpCode::Jump);
+        self.patch_jump(else_jump);
+        self.emit_op(OpCode::Pop);
+        self.parse_precedence(Precedence::Or);
+        self.patch_jump(end_jump);
+    }
+    fn call(&mut self, _can_assign: bool) {
+        let count = self.argument_list();
+        self.emit_bytes(OpCode::Call as u8, count);
+    }
+    fn dot(&mut self, can_assign: bool) {
+        self.consume(TokenType::Identifier, "Expect property name after '.'.");
+        let property = self.previous().lexeme.clone();
+        let name = self.identifier_constant(&property);
+        if can_assign && self.r#match(ComparatorType::Equal.r#match(false)) {
+            self.expression();
+            self.emit_bytes(OpCode::SetProperty as u8, name);
+        } else if self.r#match(TokenType::Paren(Direction::Left)) {
+            let count = self.argument_list();
+            self.emit_bytes(OpCode::Invoke as u8, name);
+            self.emit_byte(count);
+        } else {
+            self.emit_bytes(OpCode::GetProperty as u8, name);
+        }
+    }
+    fn argument_list(&mut self) -> u8 {
+        let mut count = 0;
+        if !self.check(TokenType::Paren(Direction::Right)) {
+            loop {
+                self.expression();
+                if count == MAX_ARITY {
+                    self.error(&format!("Can't have more than {MAX_ARITY} arguments."));
+                }
+                count += 1;
+                if !self.r#match(TokenType::Comma) {
+                    break;
+                }
+            }
+        }
+        self.consume(
+            TokenType::Paren(Direction::Right),
+            "Expect ')' after arguments.",
+        );
+        count.min(MAX_ARITY) as u8
+    }
+    fn named_variable(&mut self, name: &str, can_assign: bool) {
+        let depth = self.functions.len() - 1;
+        let (get, set, argument) = if let Some(slot) = self.resolve_local(depth, name) {
+            (OpCode::GetLocal, OpCode::SetLocal, slot)
+        } else if let Some(index) = self.resolve_upvalue(depth, name) {
+            (OpCode::GetUpvalue, OpCode::SetUpvalue, index)
+        } else {
+            let constant = self.identifier_constant(name);
+            (OpCode::GetGlobal, OpCode::SetGlobal, constant)
+        };
+        if can_assign && self.r#match(ComparatorType::Equal.r#match(false)) {
+            self.expression();
+            self.emit_bytes(set as u8, argument);
+        } else {
+            self.emit_bytes(get as u8, argument);
+        }
+    }
+    /// The stack slot of the local called `name` in the function at
+    /// `depth`.
+    fn resolve_local(&mut self, depth: usize, name: &str) -> Option<u8> {
+        let locals = &self.functions[depth].locals;
+        let (slot, local) = locals
+            .iter()
+            .enumerate()
+            .rev()
+            .find(|(_, local)| local.name == name)?;
+        if local.depth.is_none() {
+            self.error("Can't read local variable in its own initializer.");
+        }
+        Some(slot as u8)
+    }
+    /// The index of the upvalue capturing `name` in the function at
+    /// `depth`, adding it and any upvalues it in turn depends on.
+    fn resolve_upvalue(&mut self, depth: usize, name: &str) -> Option<u8> {
+        let enclosing = depth.checked_sub(1)?;
+        if let Some(slot) = self.resolve_local(enclosing, name) {
+            self.functions[enclosing].locals[slot as usize].is_captured = true;
+            return Some(self.add_upvalue(depth, slot, true));
+        }
+        let index = self.resolve_upvalue(enclosing, name)?;
+        Some(self.add_upvalue(depth, index, false))
+    }
+    fn add_upvalue(&mut self, depth: usize, index: u8, is_local: bool) -> u8 {
+        let upvalue = UpvalueRef { index, is_local };
+        let upvalues = &self.functions[depth].upvalues;
+        if let Some(existing) = upvalues.iter().position(|u| *u == upvalue) {
+            return existing as u8;
+        }
+        if upvalues.len() == MAX_INDEX {
+            self.error("Too many closure variables in function.");
+            return 0;
+        }
+        self.functions[depth].upvalues.push(upvalue);
+        (self.functions[depth].upvalues.len() - 1) as u8
+    }
+    fn identifier_constant(&mut self, name: &str) -> u8 {
+        let name = self.heap.intern(name);
+        self.make_constant(Value::Object(name))
+    }
+    /// Consumes the name of a variable being declared, returning the
+    /// constant naming it if it is a global.
+    fn parse_variable(&mut self, message: &str) -> u8 {
+        self.consume(TokenType::Identifier, message);
+        self.declare_variable();
+        if self.state().scope_depth > 0 {
+            return 0;
+        }
+        let name = self.previous().lexeme.clone();
+        self.identifier_constant(&name)
+    }
+    fn declare_variable(&mut self) {
+        let scope_depth = self.state().scope_depth;
+        if scope_depth == 0 {
+            return;
+        }
+        let name = self.previous().lexeme.clone();
+        let redeclared = self
+            .state()
+            .locals
+            .iter()
+            .rev()
+            .take_while(|local| local.depth.is_none_or(|depth| depth >= scope_depth))
+            .any(|local| local.name == name);
+        if redeclared {
+            self.error("Already a variable with this name in this scope.");
+        }
+        self.add_local(&name);
+    }
+    fn add_local(&mut self, name: &str) {
+        if self.state().locals.len() == MAX_INDEX {
+            self.error("Too many local variables in function.");
+            return;
+        }
+        self.state().locals.push(Local {
+            name: name.to_string(),
+            depth: None,
+            is_captured: false,
+        });
+    }
+    fn mark_initialized(&mut self) {
+        let state = self.state();
+        if state.scope_depth == 0 {
+            return;
+        }
+        let depth = state.scope_depth;
+        if let Some(local) = state.locals.last_mut() {
+            local.depth = Some(depth);
+        }
+    }
+    fn define_variable(&mut self, global: u8) {
+        if self.state().scope_depth > 0 {
+            self.mark_initialized();
+            return;
+        }
+        self.emit_bytes(OpCode::DefineGlobal as u8, global);
+    }
+    fn begin_scope(&mut self) {
+        self.state().scope_depth += 1;
+    }
+    fn end_scope(&mut self) {
+        self.state().scope_depth -= 1;
+        let depth = self.state().scope_depth;
+        while let Some(local) = self.state().locals.last() {
+            if local.depth.is_some_and(|d| d <= depth) {
+                break;
+            }
+            let op = if local.is_captured {
+                OpCode::CloseUpvalue
+            } else {
+                OpCode::Pop
+            };
+            self.state().locals.pop();
+            self.emit_op(op);
+        }
+    }
+    fn emit_byte(&mut self, byte: u8) {
+        let line = self.previous().line();
+        self.chunk().write(byte, line);
+    }
+    fn emit_bytes(&mut self, a: u8, b: u8) {
+        self.emit_byte(a);
+        self.emit_byte(b);
+    }
+    fn emit_op(&mut self, op: OpCode) {
+        self.emit_byte(op as u8);
+    }
+    fn emit_ops(&mut self, a: OpCode, b: OpCode) {
+        self.emit_bytes(a as u8, b as u8);
+    }
+    fn emit_return(&mut self) {
+        if self.state().kind == FunctionKind::Initializer {
+            self.emit_bytes(OpCode::GetLocal as u8, 0);
+        } else {
+            self.emit_op(OpCode::Nil);
+        }
+        self.emit_op(OpCode::Return);
+    }
+    fn make_constant(&mut self, value: Value) -> u8 {
+        let constant = self.chunk().add_constant(value);
+        if constant >= MAX_INDEX {
+            self.error("Too many constants in one chunk.");
+            return 0;
+        }
+        constant as u8
+    }
+    fn emit_constant(&mut self, value: Value) {
+        let constant = self.make_constant(value);
+        self.emit_bytes(OpCode::Constant as u8, constant);
+    }
+    /// Emits a jump with a placeholder offset, returning where the
+    /// offset is to be patched.
+    fn emit_jump(&mut self, op: OpCode) -> usize {
+        self.emit_op(op);
+        self.emit_bytes(0xff, 0xff);
+        self.chunk().code.len() - 2
+    }
+    fn patch_jump(&mut self, offset: usize) {
+        let jump = self.chunk().code.len() - offset - 2;
+        let Ok(jump) = u16::try_from(jump) else {
+            self.error("Too much code to jump over.");
+            return;
+        };
+        self.chunk().code[offset..offset + 2].copy_from_slice(&jump.to_be_bytes());
+    }
+    fn emit_loop(&mut self, loop_start: usize) {
+        self.emit_op(OpCode::Loop);
+        let offset = self.chunk().code.len() - loop_start + 2;
+        let Ok(offset) = u16::try_from(offset) else {
+            self.error("Loop body too large.");
+            return;
+        };
+        let [high, low] = offset.to_be_bytes();
+        self.emit_bytes(high, low);
+    }
+    /// Discards tokens until the start of the next statement.
+    fn synchronize(&mut self) {
+        self.panic_mode = false;
+        while !self.check(TokenType::Eof) {
+            if self.previous().r#type == TokenType::Semicolon {
+                return;
+            }
+            if let TokenType::Keyword(
+                KeywordType::Class
+                | KeywordType::Fun
+                | KeywordType::Var
+                | KeywordType::For
+                | KeywordType::If
+                | KeywordType::While
+                | KeywordType::Print
+                | KeywordType::Return,
+            ) = self.peek().r#type
+            {
+                return;
+            }
+            self.advance();
+        }
+    }
+    fn error(&mut self, message: &str) {
+        self.error_at(self.current.saturating_sub(1), message);
+    }
+    fn error_at_current(&mut self, message: &str) {
+        self.error_at(self.current, message);
+    }
+    fn error_at(&mut self, index: usize, message: &str) {
+        if self.panic_mode {
+            return;
+        }
+        self.panic_mode = true;
+        let token = &self.tokens[index];
+        let lexeme = match token.r#type {
+            TokenType::Eof => String::new(),
+            _ => token.lexeme.clone(),
+        };
+        self.errors.push(CompileError {
+            span: token.span,
+            lexeme,
+            message: message.to_string(),
+        });
+    }
+    fn advance(&mut self) {
+        if !self.check(TokenType::Eof) {
+            self.current += 1;
+        }
+    }
+    fn consume(&mut self, r#type: TokenType, message: &str) {
+        if self.check(r#type) {
+            self.advance();
+        } else {
+            self.error_at_current(message);
+        }
+    }
+    fn r#match(&mut self, r#type: TokenType) -> bool {
+        if self.check(r#type) {
+            self.advance();
+            true
+        } else {
+            false
+        }
+    }
+    fn check(&self, r#type: TokenType) -> bool {
+        self.peek().r#type == r#type
+    }
+    fn peek(&self) -> &Token {
+        &self.tokens[self.current]
+    }
+    fn previous(&self) -> &Token {
+        &self.tokens[self.current.saturating_sub(1)]
+    }
+}
@@ -0,0 +1,188 @@
+use crate::{
+    chunk::{Chunk, OpCode},
+    heap::Heap,
+    object::Object,
+    ObjRef, Value,
+};
+use core::fmt::Write;
+
+/// Renders the chunk of `function` as one instruction per line, followed
+/// by the chunks of every function it defines.
+pub fn disassemble(heap: &Heap, function: ObjRef) -> String {
+    let mut out = String::new();
+    let mut pending = vec![function];
+    while let Some(function) = pending.pop() {
+        let name = Value::Object(function).display(heap).to_string();
+        let chunk = &heap.function(function).chunk;
+        disassemble_chunk(heap, chunk, &name, &mut out);
+        // Nested functions are listed in the order they were defined
+        let nested = chunk
+            .constants
+            .iter()
+            .rev()
+            .filter_map(|constant| match constant {
+                Value::Object(object) if matches!(heap.get(*object), Object::Function(_)) => {
+                    Some(*object)
+                }
+                _ => None,
+            });
+        pending.extend(nested);
+    }
+    out
+}
+/// Renders every instruction of `chunk` under a `== name ==` header.
+pub fn disassemble_chunk(heap: &Heap, chunk: &Chunk, name: &str, out: &mut String) {
+    let _ = writeln!(out, "== {name} ==");
+    let mut offset = 0;
+    while offset < chunk.code.len() {
+        offset = disassemble_instruction(heap, chunk, offset, out);
+    }
+}
+/// Renders the instruction at `offset` as its offset, line, opcode and
+/// operands, returning the offset of the next instruction.
+pub fn disassemble_instruction(
+    heap: &Heap,
+    chunk: &Chunk,
+    offset: usize,
+    out: &mut String,
+) -> usize {
+    let _ = write!(out, "{offset:04} ");
+    if offset > 0 && chunk.lines[offset] == chunk.lines[offset - 1] {
+        let _ = write!(out, "   | ");
+    } else {
+        let _ = write!(out, "{:4} ", chunk.lines[offset]);
+    }
+    let op = match OpCode::try_from(chunk.code[offset]) {
+        Ok(op) => op,
+        Err(byte) => {
+            let _ = writeln!(out, "Unknown opcode {byte}");
+            return offset + 1;
+        }
+    };
+    let name = format!("{op:?}");
+    let byte = |index: usize| chunk.code.get(index).copied().unwrap_or_default();
+    let constant = |index: u8| match chunk.constants.get(index as usize) {
+        Some(value) => value.display(heap).to_string(),
+        None => "?".into(),
+    };
+    match op {
+        OpCode::Constant
+        | OpCode::GetGlobal
+        | OpCode::DefineGlobal
+        | OpCode::SetGlobal
+        | OpCode::GetProperty
+        | OpCode::SetProperty
+        | OpCode::GetSuper
+        | OpCode::Class
+        | OpCode::Method => {
+            let index = byte(offset + 1);
+            let _ = writeln!(out, "{name:<16} {index:4} '{}'", constant(index));
+            offset + 2
+        }
+        OpCode::GetLocal
+        | OpCode::SetLocal
+        | OpCode::GetUpvalue
+        | OpCode::SetUpvalue
+        | OpCode::Call => {
+            let _ = writeln!(out, "{name:<16} {:4}", byte(offset + 1));
+            offset + 2
+        }
+        OpCode::Jump | OpCode::JumpIfFalse | OpCode::Loop => {
+            let jump = u16::from_be_bytes([byte(offset + 1), byte(offset + 2)]) as usize;
+            let target = if op == OpCode::Loop {
+                (offset + 3).saturating_sub(jump)
+            } else {
+                offset + 3 + jump
+            };
+            let _ = writeln!(out, "{name:<16} {offset:4} -> {target}");
+            offset + 3
+        }
+        OpCode::Invoke | OpCode::SuperInvoke => {
+            let index = byte(offset + 1);
+            let count = byte(offset + 2);
+            let _ = writeln!(
+                out,
+                "{name:<16} ({count} args) {index:4} '{}'",
+                constant(index)
+            );
+            offset + 3
+        }
+        OpCode::Closure => {
+            let index = byte(offset + 1);
+            let _ = writeln!(out, "{name:<16} {index:4} '{}'", constant(index));
+            let upvalues = match chunk.constants.get(index as usize) {
+                Some(Value::Object(function)) => match heap.get(*function) {
+                    Object::Function(function) => function.upvalue_count,
+                    _ => 0,
+                },
+                _ => 0,
+            };
+            let mut offset = offset + 2;
+            for _ in 0..upvalues {
+                let kind = if byte(offset) == 1 {
+                    "local"
+                } else {
+                    "upvalue"
+                };
+                let _ = writeln!(
+                    out,
+                    "{offset:04}    |                  {kind} {}",
+                    byte(offset + 1)
+                );
+                offset += 2;
+            }
+            offset
+        }
+        _ => {
+            let _ = writeln!(out, "{name}");
+            offset + 1
+        }
+    }
+}
+/// Renders the stack as `[ a ][ b ]`, bottom first.
+pub fn stack(heap: &Heap, stack: &[Value]) -> String {
+    let mut out = String::from("          ");
+    for value in stack {
+        let _ = write!(out, "[ {} ]", value.display(heap));
+    }
+    out
+}
+
+#[cfg(test)]
+mod debug_tests {
+    use super::*;
+    use crate::Vm;
+    use scanner::Scanner;
+    fn disassembly(source: &str) -> String {
+        let (tokens, _) = Scanner::new(source).scan_tokens();
+        let mut vm = Vm::new();
+        let function = vm.compile(tokens).expect("source to compile");
+        disassemble(vm.heap(), function)
+    }
+    #[test]
+    fn shows_offsets_lines_and_constants() {
+        let listing = disassembly("print 1 + 2;\nvar a = \"x\";");
+        let expected = "\
+== <script> ==
+0000    1 Constant            0 '1'
+0002    | Constant            1 '2'
+0004    | Add
+0005    | Print
+0006    2 Constant            3 'x'
+0008    | DefineGlobal        2 'a'
+0010    | Nil
+0011    | Return
+";
+        assert_eq!(listing, expected);
+    }
+    #[test]
+    fn shows_jumps_and_nested_functions() {
+        let listing = disassembly("fun f(n) { var m = n; fun g() { return m; } while (n) n = n; }");
+        assert!(listing.contains("== <fn f> =="), "{listing}");
+        assert!(listing.contains("== <fn g> =="), "{listing}");
+        assert!(listing.contains("GetUpvalue          0"), "{listing}");
+        assert!(listing.contains("local 2"), "{listing}");
+        assert!(listing.contains("JumpIfFalse"), "{listing}");
+        assert!(listing.contains("Loop"), "{listing}");
+    }
+}
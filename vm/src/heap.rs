@@ -0,0 +1,307 @@
+use crate::{
+    object::{BoundMethod, Class, Closure, Function, Instance, Object, Upvalue},
+    Value,
+};
+use std::{collections::HashMap, mem};
+
+/// The allocation size the first collection waits for.
+const INITIAL_THRESHOLD: usize = 1024 * 1024;
+/// How much the heap may grow, relative to what survived the last
+/// collection, before the next one.
+const GROWTH_FACTOR: usize = 2;
+
+/// A reference to an object on the [`Heap`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ObjRef(usize);
+
+/// The arena holding every object the VM allocates, reclaimed by
+/// [`Heap::collect`].
+#[derive(Debug)]
+pub struct Heap {
+    objects: Vec<Option<Object>>,
+    /// The size each object was allocated with, by slot
+    sizes: Vec<usize>,
+    /// Slots freed for reuse
+    free: Vec<usize>,
+    /// Every string by its contents, so that equal strings are the same
+    /// object. Strings are only kept alive by other references.
+    strings: HashMap<String, ObjRef>,
+    bytes_allocated: usize,
+    /// The allocation size at which the next collection is due
+    next_gc: usize,
+    /// Whether a collection is due on every allocation
+    stress: bool,
+}
+impl Default for Heap {
+    fn default() -> Self {
+        Self {
+            objects: vec![],
+            sizes: vec![],
+            free: vec![],
+            strings: HashMap::new(),
+            bytes_allocated: 0,
+            next_gc: INITIAL_THRESHOLD,
+            stress: false,
+        }
+    }
+}
+macro_rules! accessors {
+    ($($variant:ident => $get:ident, $get_mut:ident;)*) => {
+        $(
+            pub fn $get(&self, object: ObjRef) -> &$variant {
+                match self.get(object) {
+                    Object::$variant(inner) => inner,
+                    other => panic!(concat!("expected a ", stringify!($get), ", got {:?}"), other),
+                }
+            }
+            pub fn $get_mut(&mut self, object: ObjRef) -> &mut $variant {
+                match self.get_mut(object) {
+                    Object::$variant(inner) => inner,
+                    other => panic!(concat!("expected a ", stringify!($get), ", got {:?}"), other),
+                }
+            }
+        )*
+    };
+}
+impl Heap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Opts into making a collection due on every allocation, to shake
+    /// out objects that aren't rooted.
+    pub fn stress(self, enabled: bool) -> Self {
+        Self {
+            stress: enabled,
+            ..self
+        }
+    }
+    /// Allocates `object`. This never collects by itself: the owner of
+    /// the roots checks [`Heap::should_collect`] first.
+    pub fn alloc(&mut self, object: Object) -> ObjRef {
+        let size = size_of(&object);
+        self.bytes_allocated += size;
+        match self.free.pop() {
+            Some(index) => {
+                self.objects[index] = Some(object);
+                self.sizes[index] = size;
+                ObjRef(index)
+            }
+            None => {
+                self.objects.push(Some(object));
+                self.sizes.push(size);
+                ObjRef(self.objects.len() - 1)
+            }
+        }
+    }
+    /// An estimate of the bytes taken by live objects.
+    pub fn bytes_allocated(&self) -> usize {
+        self.bytes_allocated
+    }
+    pub fn should_collect(&self) -> bool {
+        self.stress || self.bytes_allocated > self.next_gc
+    }
+    /// Frees every object not reachable from `roots`, returning how many
+    /// were freed, and sets the threshold for the next collection
+    /// relative to what survived.
+    pub fn collect(&mut self, roots: impl IntoIterator<Item = ObjRef>) -> usize {
+        let marked = self.mark(roots);
+        let mut freed = 0;
+        for (index, slot) in self.objects.iter_mut().enumerate() {
+            if slot.is_none() || marked[index] {
+                continue;
+            }
+            if let Some(Object::String(s)) = slot.take() {
+                self.strings.remove(&s);
+            }
+            self.bytes_allocated -= self.sizes[index];
+            self.free.push(index);
+            freed += 1;
+        }
+        self.next_gc = (self.bytes_allocated * GROWTH_FACTOR).max(INITIAL_THRESHOLD);
+        freed
+    }
+    /// Whether each slot is reachable from `roots`.
+    fn mark(&self, roots: impl IntoIterator<Item = ObjRef>) -> Vec<bool> {
+        let mut marked = vec![false; self.objects.len()];
+        let mut gray: Vec<ObjRef> = roots.into_iter().collect();
+        while let Some(object) = gray.pop() {
+            if mem::replace(&mut marked[object.0], true) {
+                continue;
+            }
+            let value = |value: &Value| match value {
+                Value::Object(object) => Some(*object),
+                _ => None,
+            };
+            match self.get(object) {
+                Object::String(_) => {}
+                Object::Function(function) => {
+                    gray.extend(function.name);
+                    gray.extend(function.chunk.constants.iter().filter_map(value));
+                }
+                Object::Closure(closure) => {
+                    gray.push(closure.function);
+                    gray.extend(&closure.upvalues);
+                }
+                Object::Upvalue(Upvalue::Open(_)) => {}
+                Object::Upvalue(Upvalue::Closed(closed)) => gray.extend(value(closed)),
+                Object::Class(class) => {
+                    gray.push(class.name);
+                    gray.extend(class.methods.iter().flat_map(|(k, v)| [*k, *v]));
+                }
+                Object::Instance(instance) => {
+                    gray.push(instance.class);
+                    gray.extend(instance.fields.keys());
+                    gray.extend(instance.fields.values().filter_map(value));
+                }
+                Object::BoundMethod(bound) => {
+                    gray.push(bound.method);
+                    gray.extend(value(&bound.receiver));
+                }
+                Object::Native(native) => gray.push(native.name),
+            }
+        }
+        marked
+    }
+    /// The string object with the contents `s`, allocating it only if
+    /// there isn't one already.
+    pub fn intern(&mut self, s: &str) -> ObjRef {
+        if let Some(&object) = self.strings.get(s) {
+            return object;
+        }
+        let object = self.alloc(Object::String(s.to_string()));
+        self.strings.insert(s.to_string(), object);
+        object
+    }
+    pub fn get(&self, object: ObjRef) -> &Object {
+        self.objects[object.0]
+            .as_ref()
+            .expect("reference to a freed object")
+    }
+    /// Whether `object` is still allocated.
+    pub fn contains(&self, object: ObjRef) -> bool {
+        self.objects.get(object.0).is_some_and(Option::is_some)
+    }
+    pub fn get_mut(&mut self, object: ObjRef) -> &mut Object {
+        self.objects[object.0]
+            .as_mut()
+            .expect("reference to a freed object")
+    }
+    /// The number of live objects.
+    pub fn len(&self) -> usize {
+        self.objects.len() - self.free.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    pub fn string(&self, object: ObjRef) -> &str {
+        match self.get(object) {
+            Object::String(s) => s,
+            other => panic!("expected a string, got {other:?}"),
+        }
+    }
+    /// Whether `object` is a string.
+    pub fn is_string(&self, object: ObjRef) -> bool {
+        matches!(self.get(object), Object::String(_))
+    }
+    accessors! {
+        Function => function, function_mut;
+        Closure => closure, closure_mut;
+        Upvalue => upvalue, upvalue_mut;
+        Class => class, class_mut;
+        Instance => instance, instance_mut;
+        BoundMethod => bound_method, bound_method_mut;
+    }
+}
+/// An estimate of the memory `object` takes, including what it owns.
+fn size_of(object: &Object) -> usize {
+    let owned = match object {
+        Object::String(s) => s.capacity(),
+        Object::Function(function) => {
+            let chunk = &function.chunk;
+            chunk.code.capacity()
+                + chunk.constants.capacity() * mem::size_of::<Value>()
+                + chunk.lines.capacity() * mem::size_of::<usize>()
+        }
+        Object::Closure(closure) => closure.upvalues.capacity() * mem::size_of::<ObjRef>(),
+        Object::Class(class) => class.methods.capacity() * mem::size_of::<(ObjRef, ObjRef)>(),
+        Object::Instance(instance) => {
+            instance.fields.capacity() * mem::size_of::<(ObjRef, Value)>()
+        }
+        Object::Upvalue(_) | Object::BoundMethod(_) | Object::Native(_) => 0,
+    };
+    mem::size_of::<Object>() + owned
+}
+
+#[cfg(test)]
+mod heap_tests {
+    use super::*;
+    #[test]
+    fn strings_are_interned() {
+        let mut heap = Heap::new();
+        let a = heap.intern("lox");
+        let b = heap.intern("lox");
+        let c = heap.intern("other");
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+        assert_eq!(heap.string(a), "lox");
+        assert_eq!(heap.len(), 2);
+    }
+    #[test]
+    fn collects_unreachable_objects() {
+        let mut heap = Heap::new();
+        let name = heap.intern("f");
+        let constant = heap.intern("kept");
+        let garbage = heap.intern("garbage");
+        let mut function = Function {
+            name: Some(name),
+            ..Function::default()
+        };
+        function.chunk.add_constant(Value::Object(constant));
+        let function = heap.alloc(Object::Function(function));
+        let closure = heap.alloc(Object::Closure(Closure {
+            function,
+            upvalues: vec![],
+        }));
+        let before = heap.bytes_allocated();
+        assert_eq!(heap.collect([closure]), 1);
+        assert!(heap.bytes_allocated() < before);
+        assert!(!heap.contains(garbage));
+        assert!([name, constant, function, closure]
+            .iter()
+            .all(|o| heap.contains(*o)));
+        // The freed string is no longer interned, and its slot is reused
+        assert_eq!(heap.intern("garbage"), garbage);
+        assert_eq!(heap.len(), 5);
+    }
+    #[test]
+    fn collects_cycles() {
+        let mut heap = Heap::new();
+        let name = heap.intern("A");
+        let class = heap.alloc(Object::Class(Class {
+            name,
+            methods: HashMap::new(),
+        }));
+        let instance = heap.alloc(Object::Instance(Instance {
+            class,
+            fields: HashMap::new(),
+        }));
+        let field = heap.intern("me");
+        heap.instance_mut(instance)
+            .fields
+            .insert(field, Value::Object(instance));
+        assert_eq!(heap.collect([]), 4);
+        assert!(heap.is_empty());
+        assert_eq!(heap.bytes_allocated(), 0);
+    }
+    #[test]
+    fn threshold_adapts_to_survivors() {
+        let mut heap = Heap::new();
+        assert!(!heap.should_collect());
+        let big = heap.intern(&"x".repeat(INITIAL_THRESHOLD));
+        assert!(heap.should_collect());
+        heap.collect([big]);
+        assert!(!heap.should_collect());
+        assert_eq!(heap.next_gc, heap.bytes_allocated() * GROWTH_FACTOR);
+        assert!(Heap::new().stress(true).should_collect());
+    }
+}
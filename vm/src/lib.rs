@@ -0,0 +1,18 @@
+pub mod bytecode;
+mod chunk;
+mod compiler;
+pub mod debug;
+mod heap;
+mod limits;
+pub mod native;
+pub mod object;
+mod value;
+mod vm;
+
+pub use bytecode::LoadError;
+pub use chunk::{Chunk, OpCode};
+pub use compiler::CompileError;
+pub use heap::{Heap, ObjRef};
+pub use limits::{Limit, Limits, DEFAULT_CALL_DEPTH};
+pub use value::Value;
+pub use vm::{RuntimeError, Vm};
@@ -0,0 +1,45 @@
+use core::fmt;
+use std::time::Duration;
+
+/// How deep calls may nest unless configured otherwise.
+pub const DEFAULT_CALL_DEPTH: usize = 64;
+
+/// One of the [`Limits`] on running a program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    Steps,
+    CallDepth,
+    Heap,
+    Time,
+}
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Steps => "Step limit exceeded.",
+            Self::CallDepth => "Stack overflow.",
+            Self::Heap => "Heap limit exceeded.",
+            Self::Time => "Time limit exceeded.",
+        })
+    }
+}
+/// Bounds on the resources a program may use, `None` being unbounded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// Instructions executed
+    pub steps: Option<u64>,
+    /// Calls in progress, the top-level script included
+    pub call_depth: Option<usize>,
+    /// Bytes the heap may hold once garbage has been collected
+    pub heap: Option<usize>,
+    pub time: Option<Duration>,
+}
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            steps: None,
+            call_depth: Some(DEFAULT_CALL_DEPTH),
+            heap: None,
+            time: None,
+        }
+    }
+}
@@ -0,0 +1,66 @@
+use crate::{Value, Vm};
+use std::time::Instant;
+
+/// Defines the natives every program can rely on:
+///
+/// - `clock()`, the seconds elapsed since they were defined
+pub fn define_builtins(vm: &mut Vm) {
+    let start = Instant::now();
+    vm.define_native("clock", 0, move |_| {
+        Ok(Value::Number(start.elapsed().as_secs_f64()))
+    });
+}
+
+#[cfg(test)]
+mod native_tests {
+    use super::*;
+    use output::Output;
+    fn run(vm: Vm, source: &str) -> Result<String, String> {
+        let (stdout, captured) = Output::capture();
+        let mut vm = vm.stdout(stdout).stress_gc(true);
+        let (tokens, _) = scanner::Scanner::new(source).scan_tokens();
+        match vm.interpret(tokens).unwrap() {
+            Ok(()) => Ok(captured.contents()),
+            Err(e) => Err(e.message),
+        }
+    }
+    #[test]
+    fn natives_are_called_with_their_arguments() {
+        let mut vm = Vm::new();
+        vm.define_native("hypot", 2, |arguments| match arguments {
+            [Value::Number(a), Value::Number(b)] => Ok(Value::Number(a.hypot(*b))),
+            _ => Err("Arguments must be numbers.".into()),
+        });
+        assert_eq!(
+            run(vm, "print hypot(3, 4); print 1 + hypot(0, 1);"),
+            Ok("5\n2\n".into())
+        );
+        let mut vm = Vm::new();
+        vm.define_native("hypot", 2, |_| Err("Arguments must be numbers.".into()));
+        assert_eq!(
+            run(vm, "hypot(nil, 1);"),
+            Err("Arguments must be numbers.".into())
+        );
+    }
+    #[test]
+    fn clock_counts_up() {
+        let mut vm = Vm::new();
+        define_builtins(&mut vm);
+        assert_eq!(
+            run(
+                vm,
+                "var a = clock(); var b = clock(); print a >= 0 and b >= a; print clock;"
+            ),
+            Ok("true\n<native fn>\n".into())
+        );
+    }
+    #[test]
+    fn arity_is_checked() {
+        let mut vm = Vm::new();
+        define_builtins(&mut vm);
+        assert_eq!(
+            run(vm, "clock(1);"),
+            Err("Expected 0 arguments but got 1.".into())
+        );
+    }
+}
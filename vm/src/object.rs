@@ -0,0 +1,74 @@
+use crate::{Chunk, ObjRef, Value};
+use core::fmt;
+use std::collections::HashMap;
+
+/// The host side of a native function, failing with the message for a
+/// runtime error.
+pub type NativeFn = dyn Fn(&[Value]) -> Result<Value, String>;
+
+/// Everything that lives on the heap.
+#[derive(Debug)]
+pub enum Object {
+    String(String),
+    Function(Function),
+    Closure(Closure),
+    Upvalue(Upvalue),
+    Class(Class),
+    Instance(Instance),
+    BoundMethod(BoundMethod),
+    Native(Native),
+}
+/// A compiled function, shared by every closure created from it.
+#[derive(Debug, Default)]
+pub struct Function {
+    /// `None` for the top-level script
+    pub name: Option<ObjRef>,
+    pub arity: usize,
+    pub upvalue_count: usize,
+    pub chunk: Chunk,
+}
+#[derive(Debug)]
+pub struct Closure {
+    pub function: ObjRef,
+    pub upvalues: Vec<ObjRef>,
+}
+/// A variable captured by a closure, which stays on the stack until
+/// the variable goes out of scope.
+#[derive(Debug, Clone, Copy)]
+pub enum Upvalue {
+    /// Still on the stack, at this slot
+    Open(usize),
+    Closed(Value),
+}
+#[derive(Debug)]
+pub struct Class {
+    pub name: ObjRef,
+    /// Closures by interned name
+    pub methods: HashMap<ObjRef, ObjRef>,
+}
+#[derive(Debug)]
+pub struct Instance {
+    pub class: ObjRef,
+    /// Values by interned name
+    pub fields: HashMap<ObjRef, Value>,
+}
+/// A method closure along with the instance it was accessed on.
+#[derive(Debug)]
+pub struct BoundMethod {
+    pub receiver: Value,
+    pub method: ObjRef,
+}
+/// A function implemented by the host rather than in Lox.
+pub struct Native {
+    pub name: ObjRef,
+    pub arity: usize,
+    pub function: Box<NativeFn>,
+}
+impl fmt::Debug for Native {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Native")
+            .field("name", &self.name)
+            .field("arity", &self.arity)
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,73 @@
+use crate::{heap::Heap, object::Object, ObjRef};
+use core::fmt;
+
+/// A value on the VM's stack, with anything bigger than a number
+/// living on the heap.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value {
+    Nil,
+    Bool(bool),
+    Number(f64),
+    /// Strings are interned, so equal strings share a reference
+    Object(ObjRef),
+}
+impl Value {
+    /// Only `nil` and `false` are falsey.
+    #[inline]
+    pub fn is_falsey(self) -> bool {
+        matches!(self, Self::Nil | Self::Bool(false))
+    }
+    /// Formats the value as `print` shows it.
+    pub fn display(self, heap: &Heap) -> impl fmt::Display + '_ {
+        Display { value: self, heap }
+    }
+}
+impl From<f64> for Value {
+    fn from(value: f64) -> Self {
+        Self::Number(value)
+    }
+}
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Self::Bool(value)
+    }
+}
+impl From<ObjRef> for Value {
+    fn from(value: ObjRef) -> Self {
+        Self::Object(value)
+    }
+}
+struct Display<'h> {
+    value: Value,
+    heap: &'h Heap,
+}
+impl fmt::Display for Display<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let object = match self.value {
+            Value::Nil => return f.write_str("nil"),
+            Value::Bool(b) => return f.write_fmt(format_args!("{b}")),
+            Value::Number(n) => return f.write_fmt(format_args!("{n}")),
+            Value::Object(object) => object,
+        };
+        let function_name = |function: ObjRef| match self.heap.function(function).name {
+            Some(name) => format!("<fn {}>", self.heap.string(name)),
+            None => "<script>".into(),
+        };
+        match self.heap.get(object) {
+            Object::String(s) => f.write_str(s),
+            Object::Function(_) => f.write_str(&function_name(object)),
+            Object::Closure(closure) => f.write_str(&function_name(closure.function)),
+            Object::BoundMethod(bound) => {
+                let closure = self.heap.closure(bound.method);
+                f.write_str(&function_name(closure.function))
+            }
+            Object::Native(_) => f.write_str("<native fn>"),
+            Object::Upvalue(_) => f.write_str("upvalue"),
+            Object::Class(class) => f.write_str(self.heap.string(class.name)),
+            Object::Instance(instance) => {
+                let class = self.heap.class(instance.class);
+                f.write_fmt(format_args!("{} instance", self.heap.string(class.name)))
+            }
+        }
+    }
+}
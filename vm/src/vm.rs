@@ -0,0 +1,965 @@
+use crate::{
+    bytecode::{self, LoadError},
+    chunk::OpCode,
+    compiler::{CompileError, Compiler},
+    debug,
+    heap::Heap,
+    limits::{Limit, Limits},
+    object::{BoundMethod, Class, Closure, Instance, Native, Object, Upvalue},
+    ObjRef, Value,
+};
+use core::fmt;
+use diagnostics::{Diagnostic, ToDiagnostic};
+use output::Output;
+use std::{collections::HashMap, time::Instant};
+use token::Token;
+
+/// How many instructions run between checks of the clock.
+const CLOCK_INTERVAL: u64 = 256;
+
+#[derive(Debug)]
+pub struct RuntimeError {
+    pub message: String,
+    pub line: usize,
+    /// The function of every active call when the error was raised,
+    /// innermost first, as `[line L] in f()`
+    pub trace: Vec<String>,
+    /// The limit the program ran into, if that's what stopped it
+    pub limit: Option<Limit>,
+}
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+impl std::error::Error for RuntimeError {}
+/// Points at the line, as only that is known, with the calls leading
+/// to it as notes.
+impl ToDiagnostic for RuntimeError {
+    fn to_diagnostic(&self) -> Diagnostic {
+        let diagnostic = Diagnostic::error(&self.message).at_line(self.line);
+        self.trace.iter().fold(diagnostic, Diagnostic::with_note)
+    }
+}
+/// A call in progress.
+#[derive(Debug)]
+struct CallFrame {
+    closure: ObjRef,
+    ip: usize,
+    /// Where the frame's window onto the stack begins, with the callee
+    /// in the first slot
+    slots: usize,
+}
+/// A stack-based virtual machine running compiled Lox.
+#[derive(Debug)]
+pub struct Vm {
+    heap: Heap,
+    stack: Vec<Value>,
+    frames: Vec<CallFrame>,
+    globals: HashMap<ObjRef, Value>,
+    /// Upvalues still pointing into the stack, ordered by slot
+    open_upvalues: Vec<ObjRef>,
+    init_string: ObjRef,
+    /// Whether to print the stack and each instruction as it runs
+    trace: bool,
+    /// Where `print` writes
+    stdout: Output,
+    /// Where tracing goes
+    stderr: Output,
+    limits: Limits,
+    /// Instructions executed in the current run
+    steps: u64,
+    started: Instant,
+    /// The limit behind the runtime error being raised, if any
+    exceeded: Option<Limit>,
+}
+impl Default for Vm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl Vm {
+    pub fn new() -> Self {
+        let mut heap = Heap::new();
+        let init_string = heap.intern("init");
+        Self {
+            heap,
+            stack: vec![],
+            frames: vec![],
+            globals: HashMap::new(),
+            open_upvalues: vec![],
+            init_string,
+            trace: false,
+            stdout: Output::stdout(),
+            stderr: Output::stderr(),
+            limits: Limits::default(),
+            steps: 0,
+            started: Instant::now(),
+            exceeded: None,
+        }
+    }
+    /// Opts into collecting garbage on every allocation.
+    pub fn stress_gc(self, enabled: bool) -> Self {
+        let heap = self.heap.stress(enabled);
+        Self { heap, ..self }
+    }
+    /// Opts into printing the stack before each instruction, along with
+    /// the instruction, to [`Vm::stderr`].
+    pub fn trace(self, enabled: bool) -> Self {
+        Self {
+            trace: enabled,
+            ..self
+        }
+    }
+    /// Sends what `print` writes to `output` instead of stdout.
+    pub fn stdout(self, output: Output) -> Self {
+        Self {
+            stdout: output,
+            ..self
+        }
+    }
+    /// Sends tracing to `output` instead of stderr.
+    pub fn stderr(self, output: Output) -> Self {
+        Self {
+            stderr: output,
+            ..self
+        }
+    }
+    /// Bounds what each run may use, the call depth included.
+    pub fn limits(self, limits: Limits) -> Self {
+        Self { limits, ..self }
+    }
+    pub fn heap(&self) -> &Heap {
+        &self.heap
+    }
+    /// Compiles `tokens` into the function for a top-level script.
+    pub fn compile(&mut self, tokens: Vec<Token>) -> Result<ObjRef, Vec<CompileError>> {
+        Compiler::new(tokens, &mut self.heap).compile()
+    }
+    /// Frees every object the program can no longer reach, returning how
+    /// many were freed.
+    pub fn collect_garbage(&mut self) -> usize {
+        let stack = self.stack.iter().filter_map(|value| match value {
+            Value::Object(object) => Some(*object),
+            _ => None,
+        });
+        let globals = self.globals.iter().flat_map(|(name, value)| match value {
+            Value::Object(object) => vec![*name, *object],
+            _ => vec![*name],
+        });
+        let frames = self.frames.iter().map(|frame| frame.closure);
+        let roots = stack
+            .chain(globals)
+            .chain(frames)
+            .chain(self.open_upvalues.iter().copied())
+            .chain([self.init_string])
+            .collect::<Vec<_>>();
+        self.heap.collect(roots)
+    }
+    /// Allocates `object`, first collecting garbage if it's due. Anything
+    /// the new object refers to must already be reachable.
+    fn alloc(&mut self, object: Object) -> Result<ObjRef, String> {
+        self.make_room()?;
+        Ok(self.heap.alloc(object))
+    }
+    fn intern(&mut self, s: &str) -> Result<ObjRef, String> {
+        self.make_room()?;
+        Ok(self.heap.intern(s))
+    }
+    /// Collects garbage if it's due, or if the heap is over its limit, in
+    /// which case only a collection bringing it back under will do. The
+    /// heap may then go over by the one allocation that follows.
+    fn make_room(&mut self) -> Result<(), String> {
+        let limit = self.limits.heap;
+        let over_limit = |heap: &Heap| limit.is_some_and(|max| heap.bytes_allocated() > max);
+        if self.heap.should_collect() || over_limit(&self.heap) {
+            self.collect_garbage();
+            if over_limit(&self.heap) {
+                return Err(self.exceed(Limit::Heap));
+            }
+        }
+        Ok(())
+    }
+    /// Counts an instruction against the limits.
+    fn step(&mut self) -> Result<(), String> {
+        self.steps += 1;
+        if self.limits.steps.is_some_and(|max| self.steps > max) {
+            return Err(self.exceed(Limit::Steps));
+        }
+        if self.steps.is_multiple_of(CLOCK_INTERVAL)
+            && self
+                .limits
+                .time
+                .is_some_and(|max| self.started.elapsed() > max)
+        {
+            return Err(self.exceed(Limit::Time));
+        }
+        Ok(())
+    }
+    /// The message for running into `limit`, noting it for the error.
+    fn exceed(&mut self, limit: Limit) -> String {
+        self.exceeded = Some(limit);
+        limit.to_string()
+    }
+    /// Loads a script compiled to the `.loxc` format.
+    pub fn load(&mut self, bytes: &[u8]) -> Result<ObjRef, LoadError> {
+        bytecode::load(bytes, &mut self.heap)
+    }
+    /// Runs the top-level script `function` to completion. Globals are
+    /// kept from one run to the next, but each run has its own limits.
+    pub fn run(&mut self, function: ObjRef) -> Result<(), RuntimeError> {
+        self.steps = 0;
+        self.started = Instant::now();
+        self.exceeded = None;
+        // Rooted until the closure takes its place
+        self.stack.push(Value::Object(function));
+        let result = self
+            .alloc(Object::Closure(Closure {
+                function,
+                upvalues: vec![],
+            }))
+            .and_then(|closure| {
+                self.stack[0] = Value::Object(closure);
+                self.call(closure, 0)
+            })
+            .and_then(|()| self.execute())
+            .map_err(|message| self.runtime_error(message));
+        self.stack.clear();
+        self.frames.clear();
+        self.open_upvalues.clear();
+        result
+    }
+    /// Compiles and runs `tokens`, returning whether it compiled.
+    pub fn interpret(
+        &mut self,
+        tokens: Vec<Token>,
+    ) -> Result<Result<(), RuntimeError>, Vec<CompileError>> {
+        let function = self.compile(tokens)?;
+        Ok(self.run(function))
+    }
+    /// Defines the global `name` as a function of `arity` arguments
+    /// implemented by `function`.
+    pub fn define_native(
+        &mut self,
+        name: &str,
+        arity: usize,
+        function: impl Fn(&[Value]) -> Result<Value, String> + 'static,
+    ) {
+        let name = self.heap.intern(name);
+        let native = self.heap.alloc(Object::Native(Native {
+            name,
+            arity,
+            function: Box::new(function),
+        }));
+        self.globals.insert(name, Value::Object(native));
+    }
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        self.globals
+            .iter()
+            .find(|(key, _)| self.heap.string(**key) == name)
+            .map(|(_, value)| *value)
+    }
+    fn runtime_error(&mut self, message: String) -> RuntimeError {
+        let trace: Vec<_> = self
+            .frames
+            .iter()
+            .rev()
+            .map(|frame| {
+                let function = self
+                    .heap
+                    .function(self.heap.closure(frame.closure).function);
+                let line = function.chunk.lines[frame.ip.saturating_sub(1)];
+                match function.name {
+                    Some(name) => format!("[line {line}] in {}()", self.heap.string(name)),
+                    None => format!("[line {line}] in script"),
+                }
+            })
+            .collect();
+        let line = self.frames.last().map_or(0, |frame| {
+            let function = self
+                .heap
+                .function(self.heap.closure(frame.closure).function);
+            function.chunk.lines[frame.ip.saturating_sub(1)]
+        });
+        RuntimeError {
+            message,
+            line,
+            trace,
+            limit: self.exceeded.take(),
+        }
+    }
+    fn frame(&self) -> &CallFrame {
+        self.frames.last().expect("a frame to run")
+    }
+    fn frame_mut(&mut self) -> &mut CallFrame {
+        self.frames.last_mut().expect("a frame to run")
+    }
+    fn read_byte(&mut self) -> u8 {
+        let frame = self.frame();
+        let function = self.heap.closure(frame.closure).function;
+        let byte = self.heap.function(function).chunk.code[frame.ip];
+        self.frame_mut().ip += 1;
+        byte
+    }
+    fn read_short(&mut self) -> u16 {
+        u16::from_be_bytes([self.read_byte(), self.read_byte()])
+    }
+    fn read_constant(&mut self) -> Value {
+        let index = self.read_byte() as usize;
+        let function = self.heap.closure(self.frame().closure).function;
+        self.heap.function(function).chunk.constants[index]
+    }
+    fn read_string(&mut self) -> ObjRef {
+        match self.read_constant() {
+            Value::Object(string) => string,
+            other => unreachable!("expected a name constant, got {other:?}"),
+        }
+    }
+    fn push(&mut self, value: Value) {
+        self.stack.push(value);
+    }
+    fn pop(&mut self) -> Value {
+        self.stack.pop().expect("a value on the stack")
+    }
+    fn peek(&self, distance: usize) -> Value {
+        self.stack[self.stack.len() - 1 - distance]
+    }
+    fn numbers(&mut self, message: &str) -> Result<(f64, f64), String> {
+        match (self.peek(1), self.peek(0)) {
+            (Value::Number(a), Value::Number(b)) => {
+                self.stack.truncate(self.stack.len() - 2);
+                Ok((a, b))
+            }
+            _ => Err(message.into()),
+        }
+    }
+    fn execute(&mut self) -> Result<(), String> {
+        loop {
+            if self.trace {
+                self.trace_instruction();
+            }
+            self.step()?;
+            let byte = self.read_byte();
+            let op = OpCode::try_from(byte).map_err(|byte| format!("Unknown opcode {byte}."))?;
+            match op {
+                OpCode::Constant => {
+                    let constant = self.read_constant();
+                    self.push(constant);
+                }
+                OpCode::Nil => self.push(Value::Nil),
+                OpCode::True => self.push(Value::Bool(true)),
+                OpCode::False => self.push(Value::Bool(false)),
+                OpCode::Pop => {
+                    self.pop();
+                }
+                OpCode::GetLocal => {
+                    let slot = self.read_byte() as usize;
+                    self.push(self.stack[self.frame().slots + slot]);
+                }
+                OpCode::SetLocal => {
+                    let slot = self.read_byte() as usize;
+                    let index = self.frame().slots + slot;
+                    self.stack[index] = self.peek(0);
+                }
+                OpCode::GetGlobal => {
+                    let name = self.read_string();
+                    match self.globals.get(&name) {
+                        Some(&value) => self.push(value),
+                        None => {
+                            return Err(format!("Undefined variable '{}'.", self.heap.string(name)))
+                        }
+                    }
+                }
+                OpCode::DefineGlobal => {
+                    let name = self.read_string();
+                    let value = self.pop();
+                    self.globals.insert(name, value);
+                }
+                OpCode::SetGlobal => {
+                    let name = self.read_string();
+                    let value = self.peek(0);
+                    match self.globals.get_mut(&name) {
+                        Some(global) => *global = value,
+                        None => {
+                            return Err(format!("Undefined variable '{}'.", self.heap.string(name)))
+                        }
+                    }
+                }
+                OpCode::GetUpvalue => {
+                    let slot = self.read_byte() as usize;
+                    let upvalue = self.heap.closure(self.frame().closure).upvalues[slot];
+                    let value = match *self.heap.upvalue(upvalue) {
+                        Upvalue::Open(index) => self.stack[index],
+                        Upvalue::Closed(value) => value,
+                    };
+                    self.push(value);
+                }
+                OpCode::SetUpvalue => {
+                    let slot = self.read_byte() as usize;
+                    let upvalue = self.heap.closure(self.frame().closure).upvalues[slot];
+                    let value = self.peek(0);
+                    match self.heap.upvalue_mut(upvalue) {
+                        Upvalue::Open(index) => {
+                            let index = *index;
+                            self.stack[index] = value;
+                        }
+                        Upvalue::Closed(closed) => *closed = value,
+                    }
+                }
+                OpCode::GetProperty => {
+                    let name = self.read_string();
+                    let instance = match self.peek(0) {
+                        Value::Object(object) if self.is_instance(object) => object,
+                        _ => return Err("Only instances have properties.".into()),
+                    };
+                    let field = self.heap.instance(instance).fields.get(&name).copied();
+                    if let Some(value) = field {
+                        self.pop();
+                        self.push(value);
+                    } else {
+                        let class = self.heap.instance(instance).class;
+                        self.bind_method(class, name)?;
+                    }
+                }
+                OpCode::SetProperty => {
+                    let name = self.read_string();
+                    let instance = match self.peek(1) {
+                        Value::Object(object) if self.is_instance(object) => object,
+                        _ => return Err("Only instances have fields.".into()),
+                    };
+                    let value = self.pop();
+                    self.heap.instance_mut(instance).fields.insert(name, value);
+                    self.pop();
+                    self.push(value);
+                }
+                OpCode::GetSuper => {
+                    let name = self.read_string();
+                    let superclass = self.pop_class()?;
+                    self.bind_method(superclass, name)?;
+                }
+                OpCode::Equal => {
+                    let b = self.pop();
+                    let a = self.pop();
+                    self.push(Value::Bool(a == b));
+                }
+                OpCode::Greater => {
+                    let (a, b) = self.numbers("Operands must be numbers.")?;
+                    self.push(Value::Bool(a > b));
+                }
+                OpCode::Less => {
+                    let (a, b) = self.numbers("Operands must be numbers.")?;
+                    self.push(Value::Bool(a < b));
+                }
+                OpCode::Add => match (self.peek(1), self.peek(0)) {
+                    (Value::Object(a), Value::Object(b))
+                        if self.heap.is_string(a) && self.heap.is_string(b) =>
+                    {
+                        let concatenated =
+                            format!("{}{}", self.heap.string(a), self.heap.string(b));
+                        let string = self.intern(&concatenated)?;
+                        self.stack.truncate(self.stack.len() - 2);
+                        self.push(Value::Object(string));
+                    }
+                    _ => {
+                        let (a, b) =
+                            self.numbers("Operands must be two numbers or two strings.")?;
+                        self.push(Value::Number(a + b));
+                    }
+                },
+                OpCode::Subtract => {
+                    let (a, b) = self.numbers("Operands must be numbers.")?;
+                    self.push(Value::Number(a - b));
+                }
+                OpCode::Multiply => {
+                    let (a, b) = self.numbers("Operands must be numbers.")?;
+                    self.push(Value::Number(a * b));
+                }
+                OpCode::Divide => {
+                    let (a, b) = self.numbers("Operands must be numbers.")?;
+                    self.push(Value::Number(a / b));
+                }
+                OpCode::Not => {
+                    let value = self.pop();
+                    self.push(Value::Bool(value.is_falsey()));
+                }
+                OpCode::Negate => match self.peek(0) {
+                    Value::Number(n) => {
+                        self.pop();
+                        self.push(Value::Number(-n));
+                    }
+                    _ => return Err("Operand must be a number.".into()),
+                },
+                OpCode::Print => {
+                    let value = self.pop();
+                    writeln!(self.stdout, "{}", value.display(&self.heap))
+                        .map_err(|e| e.to_string())?;
+                }
+                OpCode::Jump => {
+                    let offset = self.read_short() as usize;
+                    self.frame_mut().ip += offset;
+                }
+                OpCode::JumpIfFalse => {
+                    let offset = self.read_short() as usize;
+                    if self.peek(0).is_falsey() {
+                        self.frame_mut().ip += offset;
+                    }
+                }
+                OpCode::Loop => {
+                    let offset = self.read_short() as usize;
+                    self.frame_mut().ip -= offset;
+                }
+                OpCode::Call => {
+                    let count = self.read_byte() as usize;
+                    self.call_value(self.peek(count), count)?;
+                }
+                OpCode::Invoke => {
+                    let name = self.read_string();
+                    let count = self.read_byte() as usize;
+                    self.invoke(name, count)?;
+                }
+                OpCode::SuperInvoke => {
+                    let name = self.read_string();
+                    let count = self.read_byte() as usize;
+                    let superclass = self.pop_class()?;
+                    self.invoke_from_class(superclass, name, count)?;
+                }
+                OpCode::Closure => {
+                    let Value::Object(function) = self.read_constant() else {
+                        unreachable!("expected a function constant");
+                    };
+                    let count = self.heap.function(function).upvalue_count;
+                    let mut upvalues = Vec::with_capacity(count);
+                    for _ in 0..count {
+                        let is_local = self.read_byte() == 1;
+                        let index = self.read_byte() as usize;
+                        upvalues.push(if is_local {
+                            let slot = self.frame().slots + index;
+                            self.capture_upvalue(slot)?
+                        } else {
+                            self.heap.closure(self.frame().closure).upvalues[index]
+                        });
+                    }
+                    let closure = self.alloc(Object::Closure(Closure { function, upvalues }))?;
+                    self.push(Value::Object(closure));
+                }
+                OpCode::CloseUpvalue => {
+                    self.close_upvalues(self.stack.len() - 1);
+                    self.pop();
+                }
+                OpCode::Return => {
+                    let result = self.pop();
+                    let frame = self.frames.pop().expect("a frame to return from");
+                    self.close_upvalues(frame.slots);
+                    self.stack.truncate(frame.slots);
+                    if self.frames.is_empty() {
+                        return Ok(());
+                    }
+                    self.push(result);
+                }
+                OpCode::Class => {
+                    let name = self.read_string();
+                    let class = self.alloc(Object::Class(Class {
+                        name,
+                        methods: HashMap::new(),
+                    }))?;
+                    self.push(Value::Object(class));
+                }
+                OpCode::Inherit => {
+                    let superclass = match self.peek(1) {
+                        Value::Object(object)
+                            if matches!(self.heap.get(object), Object::Class(_)) =>
+                        {
+                            object
+                        }
+                        _ => return Err("Superclass must be a class.".into()),
+                    };
+                    let subclass = self.pop_class()?;
+                    let methods = self.heap.class(superclass).methods.clone();
+                    self.heap.class_mut(subclass).methods.extend(methods);
+                }
+                OpCode::Method => {
+                    let name = self.read_string();
+                    let method = match self.pop() {
+                        Value::Object(object)
+                            if matches!(self.heap.get(object), Object::Closure(_)) =>
+                        {
+                            object
+                        }
+                        _ => return Err("Expected a method closure.".into()),
+                    };
+                    let class = self.pop_class()?;
+                    self.push(Value::Object(class));
+                    self.heap.class_mut(class).methods.insert(name, method);
+                }
+            }
+        }
+    }
+    fn trace_instruction(&self) {
+        let frame = self.frame();
+        let function = self
+            .heap
+            .function(self.heap.closure(frame.closure).function);
+        let mut instruction = String::new();
+        debug::disassemble_instruction(&self.heap, &function.chunk, frame.ip, &mut instruction);
+        let stack = debug::stack(&self.heap, &self.stack);
+        let _ = write!(self.stderr, "{stack}\n{instruction}");
+    }
+    /// Pops the class the compiler always leaves on top, which only
+    /// hand-made bytecode could leave out.
+    fn pop_class(&mut self) -> Result<ObjRef, String> {
+        match self.pop() {
+            Value::Object(object) if matches!(self.heap.get(object), Object::Class(_)) => {
+                Ok(object)
+            }
+            _ => Err("Expected a class.".into()),
+        }
+    }
+    fn is_instance(&self, object: ObjRef) -> bool {
+        matches!(self.heap.get(object), Object::Instance(_))
+    }
+    fn call_value(&mut self, callee: Value, count: usize) -> Result<(), String> {
+        let Value::Object(object) = callee else {
+            return Err("Can only call functions and classes.".into());
+        };
+        match self.heap.get(object) {
+            Object::Closure(_) => self.call(object, count),
+            Object::BoundMethod(bound) => {
+                let BoundMethod { receiver, method } = *bound;
+                let slot = self.stack.len() - count - 1;
+                self.stack[slot] = receiver;
+                self.call(method, count)
+            }
+            Object::Class(class) => {
+                let initializer = class.methods.get(&self.init_string).copied();
+                let instance = self.alloc(Object::Instance(Instance {
+                    class: object,
+                    fields: HashMap::new(),
+                }))?;
+                let slot = self.stack.len() - count - 1;
+                self.stack[slot] = Value::Object(instance);
+                match initializer {
+                    Some(initializer) => self.call(initializer, count),
+                    None if count != 0 => Err(format!("Expected 0 arguments but got {count}.")),
+                    None => Ok(()),
+                }
+            }
+            Object::Native(native) => {
+                if count != native.arity {
+                    let arity = native.arity;
+                    return Err(format!("Expected {arity} arguments but got {count}."));
+                }
+                let callee = self.stack.len() - count - 1;
+                let result = (native.function)(&self.stack[callee + 1..])?;
+                self.stack.truncate(callee);
+                self.push(result);
+                Ok(())
+            }
+            _ => Err("Can only call functions and classes.".into()),
+        }
+    }
+    fn call(&mut self, closure: ObjRef, count: usize) -> Result<(), String> {
+        let arity = self
+            .heap
+            .function(self.heap.closure(closure).function)
+            .arity;
+        if count != arity {
+            return Err(format!("Expected {arity} arguments but got {count}."));
+        }
+        if self
+            .limits
+            .call_depth
+            .is_some_and(|max| self.frames.len() >= max)
+        {
+            return Err(self.exceed(Limit::CallDepth));
+        }
+        self.frames.push(CallFrame {
+            closure,
+            ip: 0,
+            slots: self.stack.len() - count - 1,
+        });
+        Ok(())
+    }
+    fn invoke(&mut self, name: ObjRef, count: usize) -> Result<(), String> {
+        let instance = match self.peek(count) {
+            Value::Object(object) if self.is_instance(object) => object,
+            _ => return Err("Only instances have methods.".into()),
+        };
+        let instance = self.heap.instance(instance);
+        if let Some(&field) = instance.fields.get(&name) {
+            let slot = self.stack.len() - count - 1;
+            self.stack[slot] = field;
+            return self.call_value(field, count);
+        }
+        self.invoke_from_class(instance.class, name, count)
+    }
+    fn invoke_from_class(
+        &mut self,
+        class: ObjRef,
+        name: ObjRef,
+        count: usize,
+    ) -> Result<(), String> {
+        match self.heap.class(class).methods.get(&name) {
+            Some(&method) => self.call(method, count),
+            None => Err(format!("Undefined property '{}'.", self.heap.string(name))),
+        }
+    }
+    /// Replaces the receiver on top with its method `name` from `class`.
+    fn bind_method(&mut self, class: ObjRef, name: ObjRef) -> Result<(), String> {
+        let Some(&method) = self.heap.class(class).methods.get(&name) else {
+            return Err(format!("Undefined property '{}'.", self.heap.string(name)));
+        };
+        // The receiver stays rooted on the stack while allocating
+        let receiver = self.peek(0);
+        let bound = self.alloc(Object::BoundMethod(BoundMethod { receiver, method }))?;
+        self.pop();
+        self.push(Value::Object(bound));
+        Ok(())
+    }
+    /// The upvalue for the stack slot `slot`, shared with any closure that
+    /// already captured it.
+    fn capture_upvalue(&mut self, slot: usize) -> Result<ObjRef, String> {
+        let existing = self.open_upvalues.iter().copied().find(
+            |&upvalue| matches!(*self.heap.upvalue(upvalue), Upvalue::Open(index) if index == slot),
+        );
+        if let Some(upvalue) = existing {
+            return Ok(upvalue);
+        }
+        let upvalue = self.alloc(Object::Upvalue(Upvalue::Open(slot)))?;
+        self.open_upvalues.push(upvalue);
+        Ok(upvalue)
+    }
+    /// Moves every open upvalue at or above `last` off the stack.
+    fn close_upvalues(&mut self, last: usize) {
+        let stack = &self.stack;
+        let heap = &mut self.heap;
+        self.open_upvalues.retain(|&upvalue| {
+            let upvalue = heap.upvalue_mut(upvalue);
+            match *upvalue {
+                Upvalue::Open(index) if index >= last => {
+                    *upvalue = Upvalue::Closed(stack[index]);
+                    false
+                }
+                _ => true,
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod vm_tests {
+    use super::*;
+    use scanner::Scanner;
+    fn run(source: &str) -> Result<Vm, RuntimeError> {
+        let (tokens, errors) = Scanner::new(source).scan_tokens();
+        assert!(errors.is_empty(), "{errors:?}");
+        // Collecting on every allocation catches anything left unrooted
+        let mut vm = Vm::new().stress_gc(true);
+        vm.interpret(tokens).expect("source to compile")?;
+        Ok(vm)
+    }
+    fn global(vm: &Vm, name: &str) -> String {
+        let value = vm.get_global(name).expect("global to be defined");
+        value.display(vm.heap()).to_string()
+    }
+    fn compile_errors(source: &str) -> Vec<String> {
+        let (tokens, _) = Scanner::new(source).scan_tokens();
+        match Vm::new().compile(tokens) {
+            Ok(_) => vec![],
+            Err(errors) => errors.into_iter().map(|e| e.message).collect(),
+        }
+    }
+    #[test]
+    fn arithmetic_and_precedence() {
+        let vm = run("var a = 1 + 2 * 3 - 4 / 2; var b = -(1 + 2); var c = !(1 < 2);").unwrap();
+        assert_eq!(global(&vm, "a"), "5");
+        assert_eq!(global(&vm, "b"), "-3");
+        assert_eq!(global(&vm, "c"), "false");
+    }
+    #[test]
+    fn strings_concatenate() {
+        let vm = run(r#"var s = "lo" + "x"; var same = s == "lox";"#).unwrap();
+        assert_eq!(global(&vm, "s"), "lox");
+        assert_eq!(global(&vm, "same"), "true");
+    }
+    #[test]
+    fn locals_and_control_flow() {
+        let source = "
+            var total = 0;
+            for (var i = 0; i < 5; i = i + 1) {
+                if (i == 2) total = total + 10; else total = total + i;
+            }
+            var n = 0;
+            while (n < 3) n = n + 1;
+            var either = nil or \"default\";
+        ";
+        let vm = run(source).unwrap();
+        assert_eq!(global(&vm, "total"), "18");
+        assert_eq!(global(&vm, "n"), "3");
+        assert_eq!(global(&vm, "either"), "default");
+    }
+    #[test]
+    fn closures_capture_variables() {
+        let source = "
+            fun counter() {
+                var count = 0;
+                fun increment() { count = count + 1; return count; }
+                return increment;
+            }
+            var next = counter();
+            next();
+            var second = next();
+            fun fib(n) { if (n < 2) return n; return fib(n - 1) + fib(n - 2); }
+            var f = fib(10);
+        ";
+        let vm = run(source).unwrap();
+        assert_eq!(global(&vm, "second"), "2");
+        assert_eq!(global(&vm, "f"), "55");
+    }
+    #[test]
+    fn classes_and_inheritance() {
+        let source = "
+            class A {
+                init(name) { this.name = name; }
+                greet() { return \"hi \" + this.name; }
+            }
+            class B < A {
+                greet() { return super.greet() + \"!\"; }
+            }
+            var b = B(\"lox\");
+            var greeting = b.greet();
+            var method = b.greet;
+            var again = method();
+        ";
+        let vm = run(source).unwrap();
+        assert_eq!(global(&vm, "greeting"), "hi lox!");
+        assert_eq!(global(&vm, "again"), "hi lox!");
+        assert_eq!(global(&vm, "b"), "B instance");
+    }
+    #[test]
+    fn collects_unreachable_cycles() {
+        let source = "
+            class Node { init() { var self = this; fun get() { return self; } this.get = get; } }
+            var kept = Node();
+            for (var i = 0; i < 10; i = i + 1) Node();
+            var alive = kept.get() == kept;
+        ";
+        let mut vm = run(source).unwrap();
+        // Only the finished script is left to free
+        vm.collect_garbage();
+        assert_eq!(vm.collect_garbage(), 0);
+        assert_eq!(global(&vm, "alive"), "true");
+        // Dropping the last global frees its instance, closure and upvalue
+        let (tokens, _) = Scanner::new("kept = nil;").scan_tokens();
+        vm.interpret(tokens).unwrap().unwrap();
+        assert!(vm.collect_garbage() >= 3);
+    }
+    #[test]
+    fn runtime_errors() {
+        let message = |source| run(source).err().map(|e| e.message);
+        assert_eq!(
+            message("-\"a\";").as_deref(),
+            Some("Operand must be a number.")
+        );
+        assert_eq!(
+            message("1 + \"a\";").as_deref(),
+            Some("Operands must be two numbers or two strings.")
+        );
+        assert_eq!(message("x;").as_deref(), Some("Undefined variable 'x'."));
+        assert_eq!(
+            message("fun f(a) {} f();").as_deref(),
+            Some("Expected 1 arguments but got 0.")
+        );
+        assert_eq!(
+            message("fun f() { f(); } f();").as_deref(),
+            Some("Stack overflow.")
+        );
+    }
+    #[test]
+    fn output_is_pluggable() {
+        let (tokens, _) = Scanner::new("print 1 + 2; print \"lox\";").scan_tokens();
+        let (stdout, printed) = Output::capture();
+        let (stderr, traced) = Output::capture();
+        let mut vm = Vm::new().stdout(stdout).stderr(stderr).trace(true);
+        vm.interpret(tokens).unwrap().unwrap();
+        assert_eq!(printed.contents(), "3\nlox\n");
+        assert!(traced.contents().contains("Print"), "{}", traced.contents());
+    }
+    #[test]
+    fn limits_stop_runaway_programs() {
+        let limit = |limits: Limits, source: &str| {
+            let (tokens, _) = Scanner::new(source).scan_tokens();
+            let mut vm = Vm::new().limits(limits);
+            let error = vm.interpret(tokens).unwrap().unwrap_err();
+            (error.limit, error.message)
+        };
+        let recurse = "fun f() { f(); } f();";
+        assert_eq!(
+            limit(Limits::default(), recurse),
+            (Some(Limit::CallDepth), "Stack overflow.".into())
+        );
+        let deep = Limits {
+            call_depth: Some(10),
+            ..Limits::default()
+        };
+        let (tokens, _) = Scanner::new("fun f(n) { if (n > 0) f(n - 1); } f(8);").scan_tokens();
+        assert!(Vm::new().limits(deep).interpret(tokens).unwrap().is_ok());
+        assert_eq!(limit(deep, recurse).0, Some(Limit::CallDepth));
+        let forever = "while (true) {}";
+        let steps = Limits {
+            steps: Some(1000),
+            ..Limits::default()
+        };
+        assert_eq!(limit(steps, forever).0, Some(Limit::Steps));
+        let time = Limits {
+            time: Some(std::time::Duration::from_millis(10)),
+            ..Limits::default()
+        };
+        assert_eq!(limit(time, forever).0, Some(Limit::Time));
+        let heap = Limits {
+            heap: Some(64 * 1024),
+            ..Limits::default()
+        };
+        let (error, message) = limit(heap, "var s = \"x\"; while (true) s = s + s;");
+        assert_eq!(error, Some(Limit::Heap));
+        assert_eq!(message, "Heap limit exceeded.");
+        // Garbage doesn't count against the heap
+        let (tokens, _) =
+            Scanner::new("for (var i = 0; i < 10000; i = i + 1) \"a\" + \"b\";").scan_tokens();
+        assert!(Vm::new().limits(heap).interpret(tokens).unwrap().is_ok());
+        assert_eq!(run("-nil;").err().unwrap().limit, None);
+    }
+    #[test]
+    fn errors_point_at_the_line_and_trace_the_calls() {
+        let source = "fun f() { return -nil; }\nfun g() { f(); }\ng();";
+        let error = run(source).err().unwrap();
+        assert_eq!(error.to_string(), "Operand must be a number.");
+        let diagnostic = error.to_diagnostic();
+        assert_eq!(diagnostic.span.map(|span| span.start.line), Some(1));
+        assert_eq!(
+            diagnostic.notes,
+            ["[line 1] in f()", "[line 2] in g()", "[line 3] in script"]
+        );
+    }
+    #[test]
+    fn static_errors() {
+        assert_eq!(
+            compile_errors("return 1;"),
+            ["Can't return from top-level code."]
+        );
+        assert_eq!(
+            compile_errors("{ var a = a; }"),
+            ["Can't read local variable in its own initializer."]
+        );
+        assert_eq!(
+            compile_errors("print this;"),
+            ["Can't use 'this' outside of a class."]
+        );
+        assert_eq!(compile_errors("1 = 2;"), ["Invalid assignment target."]);
+        let nested = "fun f() {".repeat(300) + &"}".repeat(300);
+        assert_eq!(compile_errors(&nested)[0], "Too many nested functions.");
+        assert_eq!(
+            compile_errors("var; var b = ;"),
+            ["Expect variable name.", "Expect expression."]
+        );
+    }
+}